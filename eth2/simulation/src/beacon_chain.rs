@@ -0,0 +1,119 @@
+//! Beacon chain state transition.
+//!
+//! Loosely follows the `process_slots` / `process_block` structure of the
+//! [Phase 0 spec](https://github.com/ethereum/eth2.0-specs/blob/dev/specs/phase0/beacon-chain.md#beacon-chain-state-transition-function),
+//! minus everything that isn't simulated yet (eg. epoch processing, block headers, attestations).
+use crate::{Error, Result};
+use types::beacon_block::BeaconBlock;
+use types::beacon_state::BeaconState;
+use types::eth_spec::EthSpec;
+use types::slot_epoch_root::Slot;
+
+/// Advance `state` through empty slots until it reaches `slot`
+pub fn process_slots<T: EthSpec>(state: &mut BeaconState<T>, slot: Slot) -> Result<()> {
+    if slot < state.slot {
+        return Err(Error::SlotInPast {
+            slot: slot.into(),
+            current_slot: state.slot.into(),
+        });
+    }
+    // Empty slots don't change anything but the slot itself yet
+    state.slot = slot;
+    Ok(())
+}
+
+/// Apply `block` to `state`, which must already have been advanced to the block's slot
+///
+/// `latest_block_slot` is the slot of the most recently processed beacon block, if any.
+pub fn process_block<T: EthSpec>(
+    state: &mut BeaconState<T>,
+    block: &BeaconBlock,
+    latest_block_slot: Option<Slot>,
+) -> Result<()> {
+    // Verify that the slots match
+    if block.slot != state.slot {
+        return Err(Error::SlotMismatch {
+            block_slot: block.slot.into(),
+            state_slot: state.slot.into(),
+        });
+    }
+    // Verify that the block is newer than latest block
+    if let Some(latest_block_slot) = latest_block_slot {
+        if block.slot <= latest_block_slot {
+            return Err(Error::BeaconBlockExists {
+                slot: block.slot.into(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Run the full state transition for `block`: advance to the block's slot, then apply it
+pub fn state_transition<T: EthSpec>(
+    state: &mut BeaconState<T>,
+    block: &BeaconBlock,
+    latest_block_slot: Option<Slot>,
+) -> Result<()> {
+    process_slots(state, block.slot)?;
+    process_block(state, block, latest_block_slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::eth_spec::MainnetEthSpec;
+
+    #[test]
+    fn process_slots_advances_state() {
+        let mut state: BeaconState<MainnetEthSpec> = BeaconState::new();
+        process_slots(&mut state, Slot::new(3)).unwrap();
+        assert_eq!(state.slot, Slot::new(3));
+
+        // Processing the current slot again is a no-op
+        process_slots(&mut state, Slot::new(3)).unwrap();
+        assert_eq!(state.slot, Slot::new(3));
+    }
+
+    #[test]
+    fn process_slots_rejects_past_slot() {
+        let mut state: BeaconState<MainnetEthSpec> = BeaconState::new();
+        process_slots(&mut state, Slot::new(3)).unwrap();
+        match process_slots(&mut state, Slot::new(2)) {
+            Err(Error::SlotInPast { slot, current_slot }) => {
+                assert_eq!(slot, 2);
+                assert_eq!(current_slot, 3);
+            }
+            other => panic!("expected slot in past error, got: {:?}", other),
+        }
+        assert_eq!(state.slot, Slot::new(3));
+    }
+
+    #[test]
+    fn state_transition_allows_one_block_per_slot() {
+        let mut state: BeaconState<MainnetEthSpec> = BeaconState::new();
+        let block = BeaconBlock::new(Slot::new(1));
+        state_transition(&mut state, &block, None).unwrap();
+        assert_eq!(state.slot, Slot::new(1));
+
+        match state_transition(&mut state, &block, Some(block.slot)) {
+            Err(Error::BeaconBlockExists { slot }) => assert_eq!(slot, 1),
+            other => panic!("expected beacon block exists error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn process_block_rejects_slot_mismatch() {
+        let mut state: BeaconState<MainnetEthSpec> = BeaconState::new();
+        let block = BeaconBlock::new(Slot::new(1));
+        match process_block(&mut state, &block, None) {
+            Err(Error::SlotMismatch {
+                block_slot,
+                state_slot,
+            }) => {
+                assert_eq!(block_slot, 1);
+                assert_eq!(state_slot, 0);
+            }
+            other => panic!("expected slot mismatch error, got: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Limits that the simulation enforces at runtime, so that one binary can run many
+//! parameterisations of a spec without recompiling.
+//!
+//! The limits of an `EthSpec` are compile-time types that size the SSZ lists in `types`, so a
+//! chain config can lower them but never raise them. A chain config file is YAML, and any limit
+//! it leaves out is the spec's. A genesis file can hold the same under `chain_config`:
+//!
+//! ```yaml
+//! max_shards: 4
+//! max_execution_environments: 16
+//! max_ee_byte_code_size: 65536
+//! max_transactions_per_block: 16
+//! max_gas_per_shard_block: 10000000
+//! ```
+use crate::{ChainConfigIo, Error, Result};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fs;
+use std::path::Path;
+use typenum::Unsigned;
+use types::eth_spec::EthSpec;
+
+/// Limits on EEs and blocks that the simulation enforces
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ChainConfig {
+    // Only limits the shards that blocks can be added to and EEs can be active on. Every EE still
+    // has a state on each of the spec's shards, as that's how the beacon state is laid out
+    pub max_shards: u64,
+    pub max_execution_environments: u64,
+    // The largest EE code in bytes
+    pub max_ee_byte_code_size: u64,
+    pub max_transactions_per_block: u64,
+    // The most gas the transactions in a shard block may use altogether
+    pub max_gas_per_shard_block: u64,
+}
+
+/// The limits a chain config file sets, as read from it
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ChainConfigFile {
+    #[serde(default)]
+    max_shards: Option<u64>,
+    #[serde(default)]
+    max_execution_environments: Option<u64>,
+    #[serde(default)]
+    max_ee_byte_code_size: Option<u64>,
+    #[serde(default)]
+    max_transactions_per_block: Option<u64>,
+    #[serde(default)]
+    max_gas_per_shard_block: Option<u64>,
+}
+
+impl ChainConfig {
+    /// The limits of the spec `T`, which are also the highest a chain config can set
+    pub fn of<T: EthSpec>() -> Self {
+        Self {
+            max_shards: T::MaxShards::to_u64(),
+            max_execution_environments: T::MaxExecutionEnvironments::to_u64(),
+            max_ee_byte_code_size: T::MaxEEByteCodeSize::to_u64(),
+            max_transactions_per_block: T::MaxTransactionsPerBlock::to_u64(),
+            max_gas_per_shard_block: T::MaxGasPerShardBlock::to_u64(),
+        }
+    }
+
+    /// Read a chain config file for the spec `T`
+    pub fn read<T: EthSpec, P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let yaml = fs::read_to_string(path).context(ChainConfigIo { path })?;
+        let file: ChainConfigFile =
+            serde_yaml::from_str(&yaml).map_err(|e| Error::InvalidChainConfig {
+                path: path.to_path_buf(),
+                reason: e.to_string(),
+            })?;
+        file.resolve::<T>()
+    }
+
+    /// Check that no limit is zero or higher than the spec `T` allows
+    pub fn check<T: EthSpec>(&self) -> Result<()> {
+        let spec = Self::of::<T>();
+        let limits = [
+            ("max_shards", self.max_shards, spec.max_shards),
+            (
+                "max_execution_environments",
+                self.max_execution_environments,
+                spec.max_execution_environments,
+            ),
+            (
+                "max_ee_byte_code_size",
+                self.max_ee_byte_code_size,
+                spec.max_ee_byte_code_size,
+            ),
+            (
+                "max_transactions_per_block",
+                self.max_transactions_per_block,
+                spec.max_transactions_per_block,
+            ),
+            (
+                "max_gas_per_shard_block",
+                self.max_gas_per_shard_block,
+                spec.max_gas_per_shard_block,
+            ),
+        ];
+        for &(what, value, max) in limits.iter() {
+            if value == 0 {
+                return Err(Error::ChainConfigZeroLimit {
+                    what: what.to_string(),
+                });
+            }
+            if value > max {
+                return Err(Error::ChainConfigExceedsSpec {
+                    what: what.to_string(),
+                    value,
+                    max,
+                    spec: T::spec_name().to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChainConfigFile {
+    /// The chain config with the limits in the file, and the spec `T`'s for the ones it leaves out
+    pub(crate) fn resolve<T: EthSpec>(self) -> Result<ChainConfig> {
+        let spec = ChainConfig::of::<T>();
+        let chain_config = ChainConfig {
+            max_shards: self.max_shards.unwrap_or(spec.max_shards),
+            max_execution_environments: self
+                .max_execution_environments
+                .unwrap_or(spec.max_execution_environments),
+            max_ee_byte_code_size: self
+                .max_ee_byte_code_size
+                .unwrap_or(spec.max_ee_byte_code_size),
+            max_transactions_per_block: self
+                .max_transactions_per_block
+                .unwrap_or(spec.max_transactions_per_block),
+            max_gas_per_shard_block: self
+                .max_gas_per_shard_block
+                .unwrap_or(spec.max_gas_per_shard_block),
+        };
+        chain_config.check::<T>()?;
+        Ok(chain_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::eth_spec::{MainnetEthSpec, MinimalEthSpec};
+
+    #[test]
+    fn missing_limits_are_the_spec_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        fs::write(&path, "max_shards: 2\nmax_transactions_per_block: 3\n").unwrap();
+
+        let chain_config = ChainConfig::read::<MainnetEthSpec, _>(&path).unwrap();
+        assert_eq!(
+            chain_config,
+            ChainConfig {
+                max_shards: 2,
+                max_transactions_per_block: 3,
+                ..ChainConfig::of::<MainnetEthSpec>()
+            }
+        );
+    }
+
+    #[test]
+    fn limits_above_the_spec_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        fs::write(&path, "max_shards: 8\n").unwrap();
+
+        assert!(ChainConfig::read::<MainnetEthSpec, _>(&path).is_ok());
+        match ChainConfig::read::<MinimalEthSpec, _>(&path) {
+            Err(Error::ChainConfigExceedsSpec {
+                value: 8, max: 4, ..
+            }) => (),
+            other => panic!("expected a chain config error, got {:?}", other),
+        }
+
+        fs::write(&path, "max_execution_environments: 0\n").unwrap();
+        match ChainConfig::read::<MainnetEthSpec, _>(&path) {
+            Err(Error::ChainConfigZeroLimit { what }) => {
+                assert_eq!(what, "max_execution_environments")
+            }
+            other => panic!("expected a chain config error, got {:?}", other),
+        }
+
+        fs::write(&path, "max_shard: 8\n").unwrap();
+        match ChainConfig::read::<MainnetEthSpec, _>(&path) {
+            Err(Error::InvalidChainConfig { .. }) => (),
+            other => panic!("expected an invalid chain config error, got {:?}", other),
+        }
+    }
+}
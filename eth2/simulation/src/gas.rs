@@ -0,0 +1,191 @@
+//! Deterministic gas metering for EE code.
+//!
+//! Before EE code is run, it is instrumented so that it charges gas to a counter as it executes,
+//! and traps once the counter goes negative. Every instruction in the original code costs one
+//! unit of gas. The cost of a straight-line run of instructions is charged up front when the run
+//! is entered, so a loop pays for its body on every iteration.
+//!
+//! Growing memory costs `MEMORY_GROW_COST_PER_PAGE` for each page asked for on top of that, since
+//! the runtime allocates and zeroes the pages. Each `memory.grow` is replaced by a call to an
+//! injected function that charges for the pages before growing the memory, as pwasm-utils does.
+//!
+//! The counter is a mutable global, which wasmi won't export. Instead, the runtime sets it to the
+//! transaction's gas limit before execution through the `SET_GAS_LEFT_EXPORT` function, and reads
+//! it back afterwards through the `GAS_LEFT_EXPORT` function.
+//!
+//! Only code that has already been validated should be instrumented. Otherwise an EE could
+//! refer to the counter or its functions by index, since they don't exist until the code is
+//! instrumented.
+use parity_wasm::builder::{self, FunctionDefinition};
+use parity_wasm::elements::{
+    BlockType, ExportEntry, GlobalEntry, GlobalType, InitExpr, Instruction, Instructions, Internal,
+    Module, ValueType,
+};
+
+/// Exported by instrumented code, returns the gas left as an `i64`
+pub const GAS_LEFT_EXPORT: &str = "__gas_left";
+/// Exported by instrumented code, sets the gas left from an `i64`
+pub const SET_GAS_LEFT_EXPORT: &str = "__set_gas_left";
+
+/// The gas charged for each page of memory that `memory.grow` asks for, which is one unit for each
+/// 8 bytes of the 64KiB page
+pub const MEMORY_GROW_COST_PER_PAGE: u64 = 8192;
+
+/// Returns `module` instrumented to charge gas to a counter, see the module documentation
+pub fn inject_gas_metering(mut module: Module) -> Module {
+    // Globals and functions defined by the module come after imported ones, so appending new
+    // ones doesn't change the index of anything that already exists
+    let gas_left_index = module.globals_space() as u32;
+    let get_gas_left_index = module.functions_space() as u32;
+    let set_gas_left_index = get_gas_left_index + 1;
+    let grow_memory_index = get_gas_left_index + 2;
+
+    let mut grows_memory = false;
+    if let Some(code_section) = module.code_section_mut() {
+        for body in code_section.bodies_mut() {
+            let instructions = body.code_mut().elements_mut();
+            for instruction in instructions.iter_mut() {
+                if let Instruction::GrowMemory(_) = instruction {
+                    *instruction = Instruction::Call(grow_memory_index);
+                    grows_memory = true;
+                }
+            }
+            *instructions = metered(instructions, gas_left_index);
+        }
+    }
+
+    let get_gas_left = builder::function()
+        .signature()
+        .with_return_type(Some(ValueType::I64))
+        .build()
+        .body()
+        .with_instructions(Instructions::new(vec![
+            Instruction::GetGlobal(gas_left_index),
+            Instruction::End,
+        ]))
+        .build()
+        .build();
+    let set_gas_left = builder::function()
+        .signature()
+        .with_param(ValueType::I64)
+        .build()
+        .body()
+        .with_instructions(Instructions::new(vec![
+            Instruction::GetLocal(0),
+            Instruction::SetGlobal(gas_left_index),
+            Instruction::End,
+        ]))
+        .build()
+        .build();
+
+    let mut builder = builder::from_module(module).with_global(GlobalEntry::new(
+        GlobalType::new(ValueType::I64, true),
+        InitExpr::new(vec![Instruction::I64Const(0), Instruction::End]),
+    ));
+    builder.push_function(get_gas_left);
+    builder.push_function(set_gas_left);
+    // Only added if it's called, since a module without memory can't have a `memory.grow`
+    if grows_memory {
+        builder.push_function(grow_memory(gas_left_index));
+    }
+    builder
+        .with_export(ExportEntry::new(
+            GAS_LEFT_EXPORT.to_string(),
+            Internal::Function(get_gas_left_index),
+        ))
+        .with_export(ExportEntry::new(
+            SET_GAS_LEFT_EXPORT.to_string(),
+            Internal::Function(set_gas_left_index),
+        ))
+        .build()
+}
+
+/// A function that takes the place of `memory.grow`: it charges for the pages asked for, and only
+/// grows the memory if there was enough gas
+fn grow_memory(gas_left_index: u32) -> FunctionDefinition {
+    let mut instructions = vec![
+        Instruction::GetGlobal(gas_left_index),
+        Instruction::GetLocal(0),
+        Instruction::I64ExtendUI32,
+        Instruction::I64Const(MEMORY_GROW_COST_PER_PAGE as i64),
+        Instruction::I64Mul,
+        Instruction::I64Sub,
+        Instruction::SetGlobal(gas_left_index),
+    ];
+    instructions.extend(trap_if_out_of_gas(gas_left_index));
+    instructions.extend(vec![
+        Instruction::GetLocal(0),
+        Instruction::GrowMemory(0),
+        Instruction::End,
+    ]);
+    builder::function()
+        .signature()
+        .with_param(ValueType::I32)
+        .with_return_type(Some(ValueType::I32))
+        .build()
+        .body()
+        .with_instructions(Instructions::new(instructions))
+        .build()
+        .build()
+}
+
+/// Splits the body of a function into runs of instructions that are always executed together
+/// (unless the EE traps), and charges for each run at its start
+fn metered(instructions: &[Instruction], gas_left_index: u32) -> Vec<Instruction> {
+    let mut metered = Vec::with_capacity(instructions.len());
+    let mut run = Vec::new();
+
+    for instruction in instructions {
+        run.push(instruction.clone());
+        if ends_run(instruction) {
+            metered.extend(charge(run.len() as i64, gas_left_index));
+            metered.append(&mut run);
+        }
+    }
+    // Function bodies always finish with `end`, so there is never anything left over
+    debug_assert!(run.is_empty());
+
+    metered
+}
+
+/// Whether control can leave the straight-line run at this instruction, or enter the middle of
+/// the code after it
+fn ends_run(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Block(_)
+            | Instruction::Loop(_)
+            | Instruction::If(_)
+            | Instruction::Else
+            | Instruction::End
+            | Instruction::Br(_)
+            | Instruction::BrIf(_)
+            | Instruction::BrTable(_)
+            | Instruction::Return
+            | Instruction::Unreachable
+    )
+}
+
+/// Instructions that subtract `cost` from the gas counter, and trap if it goes negative
+fn charge(cost: i64, gas_left_index: u32) -> Vec<Instruction> {
+    let mut instructions = vec![
+        Instruction::GetGlobal(gas_left_index),
+        Instruction::I64Const(cost),
+        Instruction::I64Sub,
+        Instruction::SetGlobal(gas_left_index),
+    ];
+    instructions.extend(trap_if_out_of_gas(gas_left_index));
+    instructions
+}
+
+/// Instructions that trap if the gas counter is negative
+fn trap_if_out_of_gas(gas_left_index: u32) -> Vec<Instruction> {
+    vec![
+        Instruction::GetGlobal(gas_left_index),
+        Instruction::I64Const(0),
+        Instruction::I64LtS,
+        Instruction::If(BlockType::NoResult),
+        Instruction::Unreachable,
+        Instruction::End,
+    ]
+}
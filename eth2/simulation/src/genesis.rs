@@ -0,0 +1,284 @@
+//! The state a simulation starts from, so that a server can come up already loaded with EEs
+//! instead of every client creating them first.
+//!
+//! A genesis file is YAML:
+//!
+//! ```yaml
+//! # Optional, the simulation refuses a genesis for another spec
+//! spec: mainnet
+//! # Optional, the beacon chain starts at slot 0 if not set
+//! slot: 0
+//! # Optional, limits the simulation enforces instead of the spec's, as in a chain config file
+//! chain_config:
+//!   max_shards: 4
+//! execution_environments:
+//!   - wasm: phase2_bazaar.wasm
+//!     initial_state: "22ea9b045f8792170b45ec629c98e1b92bc6a19cd8d0e9f37baaadf2564142f4"
+//!     # Optional, the EE starts from these states on the listed shards and from
+//!     # `initial_state` on the others
+//!     shard_initial_states:
+//!       3: "0000000000000000000000000000000000000000000000000000000000000000"
+//!     # Optional, the EE is deployed to every shard if not set
+//!     active_shards: [0, 3]
+//! ```
+//!
+//! Wasm paths are relative to the directory of the genesis file, and states are hex encoded.
+use crate::chain_config::{ChainConfig, ChainConfigFile};
+use crate::{Error, GenesisIo, Result};
+use hex::FromHex;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use types::eth_spec::{EthSpec, EthSpecId};
+
+/// The state a simulation starts from
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Genesis {
+    /// The spec the genesis was written for, if it's only meant for one
+    pub spec: Option<EthSpecId>,
+    pub slot: u64,
+    /// Limits the simulation enforces instead of the spec's
+    pub chain_config: Option<ChainConfig>,
+    /// EEs in order of their indices
+    pub execution_environments: Vec<GenesisExecutionEnvironment>,
+}
+
+/// An EE that exists at genesis
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct GenesisExecutionEnvironment {
+    pub ee: simulation_args::ExecutionEnvironment,
+    /// States the EE starts from on particular shards instead of `ee.initial_state`
+    #[serde(with = "simulation_args::map_base64_arrs")]
+    pub shard_initial_states: BTreeMap<u64, [u8; 32]>,
+    /// Shards the EE is deployed to, all of them if not set
+    pub active_shards: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GenesisFile {
+    #[serde(default)]
+    spec: Option<EthSpecId>,
+    #[serde(default)]
+    slot: u64,
+    #[serde(default)]
+    chain_config: Option<ChainConfigFile>,
+    #[serde(default)]
+    execution_environments: Vec<ExecutionEnvironmentEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExecutionEnvironmentEntry {
+    wasm: PathBuf,
+    initial_state: String,
+    #[serde(default)]
+    shard_initial_states: BTreeMap<u64, String>,
+    #[serde(default)]
+    active_shards: Option<Vec<u64>>,
+}
+
+impl Genesis {
+    /// Read a genesis file for the spec `T`, along with the code of its EEs
+    pub fn read<T: EthSpec, P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let invalid = |reason: String| Error::InvalidGenesis {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let yaml = fs::read_to_string(path).context(GenesisIo { path })?;
+        let file: GenesisFile = serde_yaml::from_str(&yaml).map_err(|e| invalid(e.to_string()))?;
+        let chain_config = match file.chain_config {
+            Some(chain_config) => Some(chain_config.resolve::<T>()?),
+            None => None,
+        };
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut execution_environments = Vec::with_capacity(file.execution_environments.len());
+        for entry in file.execution_environments {
+            let wasm = dir.join(&entry.wasm);
+            let wasm_code = fs::read(&wasm).context(GenesisIo { path: &wasm })?;
+            let mut shard_initial_states = BTreeMap::new();
+            for (shard_index, state) in entry.shard_initial_states {
+                shard_initial_states.insert(shard_index, decode_state(&state).map_err(invalid)?);
+            }
+            execution_environments.push(GenesisExecutionEnvironment {
+                ee: simulation_args::ExecutionEnvironment {
+                    initial_state: decode_state(&entry.initial_state).map_err(invalid)?,
+                    wasm_code,
+                },
+                shard_initial_states,
+                active_shards: entry.active_shards,
+            });
+        }
+
+        Ok(Self {
+            spec: file.spec,
+            slot: file.slot,
+            chain_config,
+            execution_environments,
+        })
+    }
+}
+
+fn decode_state(state: &str) -> std::result::Result<[u8; 32], String> {
+    <[u8; 32]>::from_hex(state)
+        .map_err(|e| format!("execution environment state {:?}: {}", state, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bazaar::{BAZAAR_INITIAL_STATE, BAZAAR_WASM};
+    use crate::{DiskStore, Simulation};
+    use types::eth_spec::MainnetEthSpec;
+
+    fn write_genesis(dir: &Path, genesis: &str) -> PathBuf {
+        let path = dir.join("genesis.yaml");
+        fs::write(dir.join("phase2_bazaar.wasm"), BAZAAR_WASM).unwrap();
+        fs::write(&path, genesis).unwrap();
+        path
+    }
+
+    #[test]
+    fn simulation_starts_from_genesis_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_genesis(
+            dir.path(),
+            &format!(
+                r#"spec: mainnet
+slot: 5
+chain_config:
+  max_shards: 4
+execution_environments:
+  - wasm: phase2_bazaar.wasm
+    initial_state: "{}"
+    shard_initial_states:
+      3: "0101010101010101010101010101010101010101010101010101010101010101"
+    active_shards: [0, 3]
+"#,
+                BAZAAR_INITIAL_STATE
+            ),
+        );
+        let genesis = Genesis::read::<MainnetEthSpec, _>(&path).unwrap();
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::from_genesis(genesis).unwrap();
+        assert_eq!(
+            simulation.chain_config(),
+            &ChainConfig {
+                max_shards: 4,
+                ..ChainConfig::of::<MainnetEthSpec>()
+            }
+        );
+
+        let ee = simulation
+            .get_execution_environment(simulation_args::GetExecutionEnvironment { ee_index: 0 })
+            .unwrap();
+        assert_eq!(ee.wasm_code, BAZAAR_WASM.to_vec());
+        let ee_state = |simulation: &Simulation<MainnetEthSpec>, shard_index| {
+            simulation
+                .get_execution_environment_state(simulation_args::GetExecutionEnvironmentState {
+                    ee_index: 0,
+                    shard_index,
+                    shard_slot_index: None,
+                })
+                .unwrap()
+        };
+        assert_eq!(
+            ee_state(&simulation, 0),
+            <[u8; 32]>::from_hex(BAZAAR_INITIAL_STATE).unwrap()
+        );
+        assert_eq!(ee_state(&simulation, 3), [1; 32]);
+        let simulate_transaction_args = |shard_index| simulation_args::SimulateTransaction {
+            shard_index,
+            transaction: simulation_args::ShardTransaction {
+                data: Vec::new(),
+                ee_index: 0,
+                gas_limit: 1_000_000,
+            },
+        };
+        assert!(simulation
+            .simulate_transaction(simulate_transaction_args(3))
+            .is_ok());
+        match simulation.simulate_transaction(simulate_transaction_args(1)) {
+            Err(Error::ExecutionEnvironmentInactive { .. }) => (),
+            other => panic!("expected an inactive EE error, got {:?}", other),
+        }
+        // The beacon chain carries on from the genesis slot
+        assert_eq!(simulation.advance_slot().unwrap(), 6);
+    }
+
+    #[test]
+    fn invalid_genesis_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_genesis(dir.path(), "spec: nonexistent\n");
+        match Genesis::read::<MainnetEthSpec, _>(&path) {
+            Err(Error::InvalidGenesis { .. }) => (),
+            other => panic!("expected an invalid genesis error, got {:?}", other),
+        }
+        let path = write_genesis(dir.path(), "chain_config:\n  max_shards: 65\n");
+        match Genesis::read::<MainnetEthSpec, _>(&path) {
+            Err(Error::ChainConfigExceedsSpec { .. }) => (),
+            other => panic!("expected a chain config error, got {:?}", other),
+        }
+
+        let mut shard_initial_states = BTreeMap::new();
+        shard_initial_states.insert(64, [0; 32]);
+        let genesis = Genesis {
+            execution_environments: vec![GenesisExecutionEnvironment {
+                ee: simulation_args::ExecutionEnvironment {
+                    initial_state: [0; 32],
+                    wasm_code: BAZAAR_WASM.to_vec(),
+                },
+                shard_initial_states,
+                active_shards: None,
+            }],
+            ..Default::default()
+        };
+        match Simulation::<MainnetEthSpec>::from_genesis(genesis) {
+            Err(Error::OutOfBounds { index: 64, .. }) => (),
+            other => panic!("expected an out of bounds error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn genesis_is_only_stored_whole_and_in_an_empty_simulation() {
+        let dir = tempfile::tempdir().unwrap();
+        let genesis_ee = |shard_index| {
+            let mut shard_initial_states = BTreeMap::new();
+            shard_initial_states.insert(shard_index, [0; 32]);
+            GenesisExecutionEnvironment {
+                ee: simulation_args::ExecutionEnvironment {
+                    initial_state: [0; 32],
+                    wasm_code: include_bytes!("../tests/do_nothing.wasm").to_vec(),
+                },
+                shard_initial_states,
+                active_shards: None,
+            }
+        };
+        let open =
+            || Simulation::<MainnetEthSpec>::with_store(DiskStore::open(dir.path()).unwrap());
+
+        // The second EE is invalid, so the first one isn't stored either
+        let genesis = Genesis {
+            slot: 5,
+            execution_environments: vec![genesis_ee(0), genesis_ee(64)],
+            ..Default::default()
+        };
+        assert!(open().unwrap().with_genesis(genesis).is_err());
+        assert!(open().unwrap().is_empty());
+
+        let genesis = Genesis {
+            execution_environments: vec![genesis_ee(0)],
+            ..Default::default()
+        };
+        let simulation = open().unwrap().with_genesis(genesis.clone()).unwrap();
+        assert!(!simulation.is_empty());
+        drop(simulation);
+        match open().unwrap().with_genesis(genesis) {
+            Err(Error::GenesisOverExistingSimulation) => (),
+            other => panic!("expected an existing simulation error, got {:?}", other),
+        }
+    }
+}
@@ -1,9 +1,24 @@
+#[cfg(test)]
+#[path = "../tests/support/bazaar.rs"]
+mod bazaar;
+mod beacon_chain;
+pub mod chain_config;
+mod gas;
+pub mod genesis;
+mod module_cache;
+pub mod op_log;
+mod proof;
+mod runtime;
+pub mod scout;
 pub mod simulation;
+mod snapshot;
 mod store;
 
 use simulation_args::Error as SimulationArgsError;
 use snafu::{Backtrace, Snafu};
 use std::fmt;
+use std::io;
+use std::path::PathBuf;
 
 /// Shorthand for result types returned from the Simulation simulation.
 pub type Result<V, E = Error> = std::result::Result<V, E>;
@@ -34,16 +49,195 @@ pub enum Error {
         backtrace: Backtrace,
         source: SimulationArgsError,
     },
+    #[snafu(display("a beacon block already exists at or after slot {}", slot))]
+    BeaconBlockExists {
+        slot: u64,
+    },
+    #[snafu(display(
+        "shard block gas limit {} is more than the maximum of {}",
+        gas_limit,
+        max
+    ))]
+    BlockGasLimitExceeded {
+        gas_limit: u64,
+        max: u64,
+    },
+    #[snafu(display(
+        "chain config sets {} to {}, but the {} spec allows at most {}",
+        what,
+        value,
+        spec,
+        max
+    ))]
+    ChainConfigExceedsSpec {
+        what: String,
+        value: u64,
+        max: u64,
+        spec: String,
+    },
+    #[snafu(display("unable to access chain config file {}: {}", path.display(), source))]
+    ChainConfigIo {
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[snafu(display("chain config sets {} to 0, which allows nothing", what))]
+    ChainConfigZeroLimit {
+        what: String,
+    },
+    #[snafu(display(
+        "execution environment {} is not active on shard {}",
+        ee_index,
+        shard_index
+    ))]
+    ExecutionEnvironmentInactive {
+        ee_index: u64,
+        shard_index: u64,
+    },
+    #[snafu(display("unable to access genesis file {}: {}", path.display(), source))]
+    GenesisIo {
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[snafu(display("genesis can only start a simulation that doesn't hold anything yet"))]
+    GenesisOverExistingSimulation,
+    #[snafu(display(
+        "genesis was written for the {} spec, but the simulation uses the {} spec",
+        spec,
+        expected
+    ))]
+    GenesisSpecMismatch {
+        spec: String,
+        expected: String,
+    },
     InvalidBytes32,
+    #[snafu(display("invalid chain config file {}: {}", path.display(), reason))]
+    InvalidChainConfig {
+        path: PathBuf,
+        reason: String,
+    },
+    #[snafu(display("invalid execution environment code: {}", source))]
+    InvalidExecutionEnvironmentCode {
+        source: runtime::Error,
+    },
+    #[snafu(display("invalid genesis file {}: {}", path.display(), reason))]
+    InvalidGenesis {
+        path: PathBuf,
+        reason: String,
+    },
+    #[snafu(display("invalid operation log {} at line {}: {}", path.display(), line, reason))]
+    InvalidOpLog {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+    #[snafu(display("invalid snapshot: {}", reason))]
+    InvalidSnapshot {
+        reason: String,
+    },
+    #[snafu(display("invalid store file {}: {}", path.display(), reason))]
+    InvalidStore {
+        path: PathBuf,
+        reason: String,
+    },
+    #[snafu(display("invalid test vector {}: {}", path.display(), reason))]
+    InvalidTestVector {
+        path: PathBuf,
+        reason: String,
+    },
     #[snafu(display("{} exceeds max allowable length", what))]
     MaxLengthExceeded {
         what: String,
     },
+    #[snafu(display(
+        "the {} differs from the one the operation log was recorded with",
+        what
+    ))]
+    OpLogHeaderMismatch {
+        what: String,
+    },
+    #[snafu(display("unable to access operation log {}: {}", path.display(), source))]
+    OpLogIo {
+        path: PathBuf,
+        source: io::Error,
+    },
     #[snafu(display("no {} exists at index: {}", what, index))]
     OutOfBounds {
         what: WhatBound,
         index: usize,
     },
+    #[snafu(display(
+        "the simulation needs {} {}, but the chain config allows at most {}",
+        value,
+        what,
+        max
+    ))]
+    SimulationExceedsChainConfig {
+        what: String,
+        value: u64,
+        max: u64,
+    },
+    #[snafu(display("unable to access snapshot file {}: {}", path.display(), source))]
+    SnapshotIo {
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[snafu(display(
+        "snapshot was created with the {} spec, but the simulation uses the {} spec",
+        spec,
+        expected
+    ))]
+    SnapshotSpecMismatch {
+        spec: String,
+        expected: String,
+    },
+    #[snafu(display(
+        "snapshot version {} is not supported, expected version {}",
+        version,
+        expected
+    ))]
+    SnapshotVersionMismatch {
+        version: u64,
+        expected: u64,
+    },
+    #[snafu(display("slot {} is before the current slot {}", slot, current_slot))]
+    SlotInPast {
+        slot: u64,
+        current_slot: u64,
+    },
+    #[snafu(display(
+        "block slot {} does not match beacon state slot {}",
+        block_slot,
+        state_slot
+    ))]
+    SlotMismatch {
+        block_slot: u64,
+        state_slot: u64,
+    },
+    #[snafu(display("slot {} is the last slot, the beacon chain can't advance", slot))]
+    SlotOverflow {
+        slot: u64,
+    },
+    #[snafu(display(
+        "shard blocks were executed before the simulation last changed, and must be executed again"
+    ))]
+    StaleShardBlocks,
+    #[snafu(display("unable to access store file {}: {}", path.display(), source))]
+    StoreIo {
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[snafu(display("unable to access test vector file {}: {}", path.display(), source))]
+    TestVectorIo {
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[snafu(display("no shard block exists with root: {}", base64::encode(root)))]
+    UnknownShardBlockRoot {
+        root: [u8; 32],
+    },
 }
 
-pub use crate::simulation::Simulation;
+pub use crate::chain_config::ChainConfig;
+pub use crate::genesis::Genesis;
+pub use crate::simulation::{ExecutedShardBlocks, Simulation};
+pub use crate::store::{DiskStore, MemoryStore, ShardBlockRecord, StoreBackend};
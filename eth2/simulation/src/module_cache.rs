@@ -0,0 +1,68 @@
+//! A cache of compiled EE code.
+//!
+//! Compiling EE code (parsing it, validating it and instrumenting it for gas metering) costs far
+//! more than running a typical transaction, so each distinct piece of code is only compiled once.
+//! Modules are keyed by the hash of the code rather than by EE index, so EEs that share code also
+//! share a module.
+//!
+//! The cache can be shared between threads executing shard blocks in parallel.
+use crate::runtime;
+use eth2_hashing::hash;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use wasmi::Module;
+
+#[derive(Default)]
+pub struct ModuleCache {
+    modules: RwLock<HashMap<Vec<u8>, Arc<Module>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ModuleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the compiled module for `wasm_code`, compiling it if it isn't cached yet
+    pub fn get_or_compile(&self, wasm_code: &[u8]) -> runtime::Result<Arc<Module>> {
+        let code_hash = hash(wasm_code);
+        // Nothing can be left half-updated by a panic while the lock is held, so a poisoned lock is
+        // still safe to use
+        let modules = self.modules.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(module) = modules.get(&code_hash) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Arc::clone(module));
+        }
+        drop(modules);
+
+        // Code that fails to compile isn't cached, so it counts as a miss every time
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let module = Arc::new(runtime::compile(wasm_code)?);
+        self.modules
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(code_hash, Arc::clone(&module));
+        Ok(module)
+    }
+
+    pub fn stats(&self) -> simulation_args::ModuleCacheStats {
+        let modules = self.modules.read().unwrap_or_else(PoisonError::into_inner);
+        simulation_args::ModuleCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            modules: modules.len() as u64,
+        }
+    }
+}
+
+// `wasmi::Module` doesn't implement `Debug`
+impl fmt::Debug for ModuleCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ModuleCache")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
@@ -0,0 +1,780 @@
+//! A write-ahead log of the operations that change the simulation, so that a simulation can be
+//! rebuilt, or a bug reproduced, by replaying them from genesis.
+//!
+//! The log is a file of JSON entries, one per line. It starts with a header that records the spec,
+//! chain config and genesis the simulation started from, so that the log alone is enough to
+//! replay it. Each operation is appended before it is applied, and is followed by the roots of the
+//! state it led to, so that a replay can check it arrives at the same states. An operation has no
+//! post-state roots if the simulation stopped while applying it. A line left incomplete by a
+//! crash is discarded when the log is opened.
+use crate::{ChainConfig, Error, Genesis, OpLogIo, Result, Simulation};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::any::Any;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use types::eth_spec::{EthSpec, EthSpecId};
+
+/// What the simulation a log was recorded from started as
+#[derive(Debug, Clone, Deserialize, PartialEq, Serialize)]
+pub struct Header {
+    pub spec: EthSpecId,
+    pub chain_config: ChainConfig,
+    pub genesis: Option<Genesis>,
+}
+
+impl Header {
+    /// The header for a simulation of the spec `T` that enforces `chain_config`, started from
+    /// `genesis` if there is one
+    pub fn new<T: EthSpec>(chain_config: ChainConfig, genesis: Option<Genesis>) -> Self {
+        Self {
+            spec: T::spec_name(),
+            chain_config,
+            genesis,
+        }
+    }
+
+    /// Check that the log was recorded under the spec `T`, and with `chain_config` and `genesis`
+    /// if they are given
+    pub fn check<T: EthSpec>(
+        &self,
+        chain_config: Option<&ChainConfig>,
+        genesis: Option<&Genesis>,
+    ) -> Result<()> {
+        let mismatch = |what: &str| Error::OpLogHeaderMismatch {
+            what: what.to_string(),
+        };
+        if self.spec != T::spec_name() {
+            return Err(mismatch("spec"));
+        }
+        match chain_config {
+            Some(chain_config) if *chain_config != self.chain_config => {
+                return Err(mismatch("chain config"))
+            }
+            _ => {}
+        }
+        match genesis {
+            Some(genesis) if Some(genesis) != self.genesis.as_ref() => {
+                return Err(mismatch("genesis"))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Create the simulation the log starts from
+    pub fn simulation<T: EthSpec>(&self) -> Result<Simulation<T>> {
+        self.check::<T>(None, None)?;
+        let simulation = Simulation::new().with_chain_config(self.chain_config.clone())?;
+        match &self.genesis {
+            Some(genesis) => simulation.with_genesis(genesis.clone()),
+            None => Ok(simulation),
+        }
+    }
+}
+
+/// An operation that changes the simulation
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    AdvanceSlot,
+    CreateBeaconBlock(simulation_args::CreateBeaconBlock),
+    CreateExecutionEnvironment(simulation_args::CreateExecutionEnvironment),
+    CreateShardBlock(simulation_args::CreateShardBlock),
+    CreateShardBlocks(simulation_args::CreateShardBlocks),
+}
+
+impl Operation {
+    /// The shards the operation adds blocks to, in order and without duplicates
+    fn shard_indices(&self) -> Vec<u64> {
+        let mut shard_indices = match self {
+            Operation::CreateShardBlock(a) => vec![a.shard_index],
+            Operation::CreateShardBlocks(a) => {
+                a.shard_blocks.iter().map(|a| a.shard_index).collect()
+            }
+            _ => Vec::new(),
+        };
+        shard_indices.sort();
+        shard_indices.dedup();
+        shard_indices
+    }
+
+    fn apply<T: EthSpec>(self, simulation: &mut Simulation<T>) -> Result<()> {
+        match self {
+            Operation::AdvanceSlot => simulation.advance_slot().map(drop),
+            Operation::CreateBeaconBlock(a) => simulation.create_beacon_block(a).map(drop),
+            Operation::CreateExecutionEnvironment(a) => {
+                simulation.create_execution_environment(a).map(drop)
+            }
+            Operation::CreateShardBlock(a) => simulation.create_shard_block(a).map(drop),
+            Operation::CreateShardBlocks(a) => simulation.create_shard_blocks(a).map(drop),
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::AdvanceSlot => write!(f, "advance slot"),
+            Operation::CreateBeaconBlock(a) => {
+                write!(f, "create beacon block at slot {}", a.beacon_block.slot)
+            }
+            Operation::CreateExecutionEnvironment(_) => write!(f, "create execution environment"),
+            Operation::CreateShardBlock(a) => {
+                write!(f, "create shard block on shard {}", a.shard_index)
+            }
+            Operation::CreateShardBlocks(a) => {
+                write!(f, "create {} shard blocks", a.shard_blocks.len())
+            }
+        }
+    }
+}
+
+/// The roots of the state an operation led to
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct PostState {
+    // The error the operation failed with, if any. A failed operation leaves the state as it was.
+    pub error: Option<String>,
+    #[serde(with = "simulation_args::base64_arr")]
+    pub beacon_state_root: [u8; 32],
+    // The states of the shards that the operation adds blocks to, leaving out shards that don't
+    // exist
+    pub shard_states: Vec<ShardPostState>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct ShardPostState {
+    pub shard_index: u64,
+    #[serde(with = "simulation_args::base64_arr")]
+    pub state_root: [u8; 32],
+}
+
+impl PostState {
+    fn new<T: EthSpec>(
+        simulation: &Simulation<T>,
+        shard_indices: Vec<u64>,
+        error: Option<String>,
+    ) -> Result<Self> {
+        let shard_states = shard_indices
+            .into_iter()
+            .filter_map(|shard_index| {
+                let a = simulation_args::GetShardState {
+                    shard_index,
+                    shard_slot_index: None,
+                };
+                let state_root = simulation.get_shard_state_root(a).ok()?;
+                Some(ShardPostState {
+                    shard_index,
+                    state_root,
+                })
+            })
+            .collect();
+        Ok(Self {
+            error,
+            beacon_state_root: simulation.get_beacon_state_root()?,
+            shard_states,
+        })
+    }
+}
+
+impl fmt::Display for PostState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "beacon state root {}",
+            base64::encode(&self.beacon_state_root)
+        )?;
+        for shard_state in &self.shard_states {
+            write!(
+                f,
+                ", shard {} state root {}",
+                shard_state.shard_index,
+                base64::encode(&shard_state.state_root)
+            )?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, ", failed with: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entry {
+    Header(Header),
+    Operation(Operation),
+    PostState(PostState),
+}
+
+/// The first operation in a replay that led to different roots than the ones recorded in the log
+#[derive(Debug)]
+pub struct Divergence {
+    // The position of the operation in the log, counting operations only
+    pub operation_index: usize,
+    pub operation: String,
+    pub recorded: PostState,
+    pub replayed: PostState,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "operation {} ({}) led to a different state than recorded\n  recorded: {}\n  replayed: {}",
+            self.operation_index, self.operation, self.recorded, self.replayed
+        )
+    }
+}
+
+/// An operation that has been appended to the log, but whose post-state hasn't been yet
+#[derive(Debug)]
+#[must_use]
+struct PendingOperation {
+    shard_indices: Vec<u64>,
+}
+
+/// An operation log that is open for appending
+#[derive(Debug)]
+pub struct OpLog {
+    path: PathBuf,
+    file: File,
+}
+
+impl OpLog {
+    /// Open the log at `path`, creating it if it doesn't exist, and return it along with the
+    /// entries already in it. An incomplete line at the end of the log is removed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<(Self, Vec<Entry>)> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .context(OpLogIo { path })?;
+        let bytes = fs::read(path).context(OpLogIo { path })?;
+
+        let (entries, length) = parse(path, &bytes)?;
+        if length < bytes.len() {
+            file.set_len(length as u64).context(OpLogIo { path })?;
+        }
+
+        let op_log = Self {
+            path: path.to_path_buf(),
+            file,
+        };
+        Ok((op_log, entries))
+    }
+
+    /// Append the header to a log that is still empty
+    pub fn append_header(&mut self, header: Header) -> Result<()> {
+        self.append(&Entry::Header(header))
+    }
+
+    /// Append `operation` to the log, then apply it to `simulation` with `apply` and append the
+    /// roots of the state it led to.
+    ///
+    /// The operation isn't applied if it can't be appended. If the roots can't be appended, the
+    /// error is returned even though the operation was applied. If `apply` panics, the roots are
+    /// appended along with the panic message before the panic carries on, so that the log shows
+    /// where the simulation stopped.
+    pub fn record<T, V, F>(
+        &mut self,
+        simulation: &mut Simulation<T>,
+        operation: Operation,
+        apply: F,
+    ) -> Result<V>
+    where
+        T: EthSpec,
+        F: FnOnce(&mut Simulation<T>) -> Result<V>,
+    {
+        let pending = self.append_operation(operation)?;
+        let res = match catch_panic(simulation, apply) {
+            Ok(res) => res,
+            Err((error, payload)) => {
+                // Best effort, the panic is what gets reported
+                let _ = PostState::new(simulation, pending.shard_indices, Some(error))
+                    .and_then(|post_state| self.append(&Entry::PostState(post_state)));
+                panic::resume_unwind(payload)
+            }
+        };
+        self.append_post_state(simulation, pending, res.as_ref().err())?;
+        res
+    }
+
+    /// Append `operation` to the log before applying it
+    fn append_operation(&mut self, operation: Operation) -> Result<PendingOperation> {
+        let shard_indices = operation.shard_indices();
+        self.append(&Entry::Operation(operation))?;
+        Ok(PendingOperation { shard_indices })
+    }
+
+    /// Append the roots of the state `simulation` is in once a pending operation has been applied,
+    /// along with the error applying it returned, if any
+    fn append_post_state<T: EthSpec>(
+        &mut self,
+        simulation: &Simulation<T>,
+        pending: PendingOperation,
+        error: Option<&Error>,
+    ) -> Result<()> {
+        let error = error.map(ToString::to_string);
+        let post_state = PostState::new(simulation, pending.shard_indices, error)?;
+        self.append(&Entry::PostState(post_state))
+    }
+
+    fn append(&mut self, entry: &Entry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry).expect("entries can always be encoded as JSON");
+        line.push(b'\n');
+
+        let path = &self.path;
+        let previous_length = self.file.metadata().context(OpLogIo { path })?.len();
+        if let Err(e) = self
+            .file
+            .write_all(&line)
+            .and_then(|_| self.file.sync_data())
+        {
+            let _ = self.file.set_len(previous_length);
+            return Err(e).context(OpLogIo { path });
+        }
+        Ok(())
+    }
+}
+
+/// Read the entries in the log at `path`, ignoring an incomplete line at the end
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Entry>> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).context(OpLogIo { path })?;
+    let (entries, _) = parse(path, &bytes)?;
+    Ok(entries)
+}
+
+/// The header of a log, which is missing only if the log is empty
+pub fn header(entries: &[Entry]) -> Option<&Header> {
+    match entries.first() {
+        Some(Entry::Header(header)) => Some(header),
+        _ => None,
+    }
+}
+
+/// Apply the operations in `entries` to `simulation` in order, stopping at the first one that
+/// leads to different roots than the ones recorded after it
+///
+/// `simulation` must be the one the header describes, see `Header::simulation`. An operation that
+/// panics is compared with the recorded roots like one that returns an error, since `record`
+/// records the panic.
+pub fn replay<T: EthSpec>(
+    simulation: &mut Simulation<T>,
+    entries: Vec<Entry>,
+) -> Result<Option<Divergence>> {
+    replay_with(simulation, entries, |simulation, operation| {
+        operation.apply(simulation)
+    })
+}
+
+fn replay_with<T, F>(
+    simulation: &mut Simulation<T>,
+    entries: Vec<Entry>,
+    mut apply: F,
+) -> Result<Option<Divergence>>
+where
+    T: EthSpec,
+    F: FnMut(&mut Simulation<T>, Operation) -> Result<()>,
+{
+    let mut operation_index = 0;
+    let mut last_operation = None;
+    for entry in entries {
+        match entry {
+            Entry::Header(_) => {}
+            Entry::Operation(operation) => {
+                let description = operation.to_string();
+                let shard_indices = operation.shard_indices();
+                let error = match catch_panic(simulation, |simulation| apply(simulation, operation))
+                {
+                    Ok(res) => res.err().map(|e| e.to_string()),
+                    Err((error, _)) => Some(error),
+                };
+                let replayed = PostState::new(simulation, shard_indices, error)?;
+                last_operation = Some((operation_index, description, replayed));
+                operation_index += 1;
+            }
+            Entry::PostState(recorded) => {
+                // `parse` checks that every `PostState` follows an `Operation`
+                if let Some((operation_index, operation, replayed)) = last_operation.take() {
+                    if recorded != replayed {
+                        return Ok(Some(Divergence {
+                            operation_index,
+                            operation,
+                            recorded,
+                            replayed,
+                        }));
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Apply `apply` to `simulation`, catching a panic. A panic is returned as the error that a
+/// post-state records for it, along with its payload so that it can carry on.
+fn catch_panic<T, V, F>(
+    simulation: &mut Simulation<T>,
+    apply: F,
+) -> std::result::Result<Result<V>, (String, Box<dyn Any + Send>)>
+where
+    T: EthSpec,
+    F: FnOnce(&mut Simulation<T>) -> Result<V>,
+{
+    panic::catch_unwind(AssertUnwindSafe(|| apply(simulation))).map_err(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        (format!("panicked: {}", message), payload)
+    })
+}
+
+/// Parse the complete lines in `bytes`, returning the entries and the length of those lines
+fn parse(path: &Path, bytes: &[u8]) -> Result<(Vec<Entry>, usize)> {
+    let mut entries = Vec::new();
+    let mut length = 0;
+    let mut follows_operation = false;
+    let mut lines = bytes.split(|b| *b == b'\n').enumerate().peekable();
+    // The last item from `split` is whatever follows the last newline, so it isn't a whole line
+    while let Some((index, line)) = lines.next() {
+        if lines.peek().is_none() {
+            break;
+        }
+        let invalid = |reason| Error::InvalidOpLog {
+            path: path.to_path_buf(),
+            line: index + 1,
+            reason,
+        };
+
+        let entry: Entry = serde_json::from_slice(line).map_err(|e| invalid(e.to_string()))?;
+        match entry {
+            Entry::Header(_) if entries.is_empty() => {}
+            Entry::Header(_) => {
+                return Err(invalid("only the first line can be a header".to_string()))
+            }
+            _ if entries.is_empty() => {
+                return Err(invalid("the log must start with a header".to_string()))
+            }
+            Entry::Operation(_) => follows_operation = true,
+            Entry::PostState(_) if follows_operation => follows_operation = false,
+            Entry::PostState(_) => {
+                return Err(invalid(
+                    "post-state roots must follow an operation".to_string(),
+                ))
+            }
+        }
+        entries.push(entry);
+        length += line.len() + 1;
+    }
+    Ok((entries, length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::eth_spec::{MainnetEthSpec, MinimalEthSpec};
+
+    const GAS_LIMIT: u64 = 1_000_000;
+
+    fn record_operations(op_log: &mut OpLog, simulation: &mut Simulation<MainnetEthSpec>) {
+        let chain_config = simulation.chain_config().clone();
+        op_log
+            .append_header(Header::new::<MainnetEthSpec>(chain_config, None))
+            .unwrap();
+
+        let a = simulation_args::CreateExecutionEnvironment {
+            ee: simulation_args::ExecutionEnvironment {
+                initial_state: [0; 32],
+                wasm_code: include_bytes!("../tests/log.wasm").to_vec(),
+            },
+            ..Default::default()
+        };
+        op_log
+            .record(
+                simulation,
+                Operation::CreateExecutionEnvironment(a.clone()),
+                |simulation| simulation.create_execution_environment(a),
+            )
+            .unwrap();
+
+        // The second block refers to an EE that doesn't exist, so it fails without changing the
+        // state, which is recorded too
+        for ee_index in 0..2 {
+            let a = simulation_args::CreateShardBlock {
+                shard_index: 3,
+                shard_block: simulation_args::ShardBlock {
+                    transactions: vec![simulation_args::ShardTransaction {
+                        data: vec![1],
+                        ee_index,
+                        gas_limit: GAS_LIMIT,
+                    }],
+                    ..Default::default()
+                },
+            };
+            let _ = op_log.record(
+                simulation,
+                Operation::CreateShardBlock(a.clone()),
+                |simulation| simulation.create_shard_block(a),
+            );
+        }
+
+        op_log
+            .record(simulation, Operation::AdvanceSlot, |simulation| {
+                simulation.advance_slot()
+            })
+            .unwrap();
+
+        let a = simulation_args::CreateShardBlocks {
+            shard_blocks: (3..5)
+                .map(|shard_index| simulation_args::CreateShardBlock {
+                    shard_index,
+                    shard_block: simulation_args::ShardBlock {
+                        transactions: vec![simulation_args::ShardTransaction {
+                            data: vec![2; 32],
+                            ee_index: 0,
+                            gas_limit: GAS_LIMIT,
+                        }],
+                        ..Default::default()
+                    },
+                })
+                .collect(),
+        };
+        op_log
+            .record(
+                simulation,
+                Operation::CreateShardBlocks(a.clone()),
+                |simulation| simulation.create_shard_blocks(a),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn operations_are_replayed_from_the_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("op_log.json");
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let (mut op_log, entries) = OpLog::open(&path).unwrap();
+        assert!(entries.is_empty());
+        record_operations(&mut op_log, &mut simulation);
+
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 11);
+        match &entries[6] {
+            Entry::PostState(post_state) => assert!(post_state.error.is_some()),
+            entry => panic!("expected post-state roots, got {:?}", entry),
+        }
+        match &entries[10] {
+            Entry::PostState(post_state) => assert_eq!(post_state.shard_states.len(), 2),
+            entry => panic!("expected post-state roots, got {:?}", entry),
+        }
+
+        let mut replayed: Simulation<MainnetEthSpec> =
+            header(&entries).unwrap().simulation().unwrap();
+        assert!(replay(&mut replayed, entries).unwrap().is_none());
+        assert_eq!(
+            replayed.get_beacon_state_root().unwrap(),
+            simulation.get_beacon_state_root().unwrap()
+        );
+        for shard_index in 3..5 {
+            let a = || simulation_args::GetShardState {
+                shard_index,
+                shard_slot_index: None,
+            };
+            assert_eq!(
+                replayed.get_shard_state_root(a()).unwrap(),
+                simulation.get_shard_state_root(a()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn replay_reports_the_first_divergence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("op_log.json");
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let (mut op_log, _) = OpLog::open(&path).unwrap();
+        record_operations(&mut op_log, &mut simulation);
+
+        let mut entries = read(&path).unwrap();
+        for index in &[4, 8] {
+            match &mut entries[*index] {
+                Entry::PostState(post_state) => post_state.beacon_state_root = [1; 32],
+                entry => panic!("expected post-state roots, got {:?}", entry),
+            }
+        }
+
+        let mut replayed: Simulation<MainnetEthSpec> =
+            header(&entries).unwrap().simulation().unwrap();
+        let divergence = replay(&mut replayed, entries).unwrap().unwrap();
+        assert_eq!(divergence.operation_index, 1);
+        assert_eq!(divergence.operation, "create shard block on shard 3");
+        assert_eq!(divergence.recorded.beacon_state_root, [1; 32]);
+        assert_ne!(divergence.replayed.beacon_state_root, [1; 32]);
+    }
+
+    #[test]
+    fn operation_that_panics_is_recorded_as_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("op_log.json");
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let (mut op_log, _) = OpLog::open(&path).unwrap();
+        op_log
+            .append_header(Header::new::<MainnetEthSpec>(
+                ChainConfig::of::<MainnetEthSpec>(),
+                None,
+            ))
+            .unwrap();
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            op_log.record(&mut simulation, Operation::AdvanceSlot, |_| -> Result<()> {
+                panic!("out of cheese")
+            })
+        }));
+        assert!(res.is_err());
+
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        match &entries[2] {
+            Entry::PostState(post_state) => {
+                assert_eq!(post_state.error.as_deref(), Some("panicked: out of cheese"))
+            }
+            entry => panic!("expected post-state roots, got {:?}", entry),
+        }
+    }
+
+    #[test]
+    fn recorded_panic_is_replayed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("op_log.json");
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let (mut op_log, _) = OpLog::open(&path).unwrap();
+        op_log
+            .append_header(Header::new::<MainnetEthSpec>(
+                ChainConfig::of::<MainnetEthSpec>(),
+                None,
+            ))
+            .unwrap();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            op_log.record(&mut simulation, Operation::AdvanceSlot, |_| -> Result<()> {
+                panic!("out of cheese")
+            })
+        }));
+        assert!(res.is_err());
+        op_log
+            .record(&mut simulation, Operation::AdvanceSlot, |simulation| {
+                simulation.advance_slot()
+            })
+            .unwrap();
+        let entries = || read(&path).unwrap();
+
+        // The first operation panics again, as recorded, and the replay carries on after it
+        let mut replayed: Simulation<MainnetEthSpec> = Simulation::new();
+        let mut applied = 0;
+        let divergence = replay_with(&mut replayed, entries(), |simulation, operation| {
+            applied += 1;
+            if applied == 1 {
+                panic!("out of cheese");
+            }
+            operation.apply(simulation)
+        })
+        .unwrap();
+        assert!(divergence.is_none());
+        assert_eq!(
+            replayed.get_beacon_state_root().unwrap(),
+            simulation.get_beacon_state_root().unwrap()
+        );
+
+        // If it doesn't panic, that's a divergence rather than a panic during the replay
+        let mut replayed: Simulation<MainnetEthSpec> = Simulation::new();
+        let divergence = replay(&mut replayed, entries()).unwrap().unwrap();
+        assert_eq!(divergence.operation_index, 0);
+        assert_eq!(
+            divergence.recorded.error.as_deref(),
+            Some("panicked: out of cheese")
+        );
+    }
+
+    #[test]
+    fn incomplete_line_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("op_log.json");
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let (mut op_log, _) = OpLog::open(&path).unwrap();
+        record_operations(&mut op_log, &mut simulation);
+        let length = fs::metadata(&path).unwrap().len();
+
+        op_log.file.write_all(b"{\"operation\":").unwrap();
+        drop(op_log);
+
+        let (_, entries) = OpLog::open(&path).unwrap();
+        assert_eq!(entries.len(), 11);
+        assert_eq!(fs::metadata(&path).unwrap().len(), length);
+    }
+
+    #[test]
+    fn header_sets_up_the_simulation_and_is_checked() {
+        let chain_config = ChainConfig {
+            max_shards: 8,
+            ..ChainConfig::of::<MainnetEthSpec>()
+        };
+        let genesis = Genesis {
+            slot: 3,
+            ..Default::default()
+        };
+        let header = Header::new::<MainnetEthSpec>(chain_config.clone(), Some(genesis.clone()));
+
+        let mut simulation: Simulation<MainnetEthSpec> = header.simulation().unwrap();
+        assert_eq!(simulation.chain_config(), &chain_config);
+        assert_eq!(simulation.advance_slot().unwrap(), 4);
+
+        assert!(header
+            .check::<MainnetEthSpec>(Some(&chain_config), Some(&genesis))
+            .is_ok());
+        let mismatches = vec![
+            header.check::<MinimalEthSpec>(None, None),
+            header.check::<MainnetEthSpec>(Some(&ChainConfig::of::<MainnetEthSpec>()), None),
+            header.check::<MainnetEthSpec>(None, Some(&Genesis::default())),
+        ];
+        for (mismatch, what) in mismatches
+            .into_iter()
+            .zip(&["spec", "chain config", "genesis"])
+        {
+            match mismatch {
+                Err(Error::OpLogHeaderMismatch { what: actual }) => assert_eq!(actual, *what),
+                other => panic!("expected a header mismatch error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn log_must_start_with_its_only_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("op_log.json");
+        let header = Header::new::<MainnetEthSpec>(ChainConfig::of::<MainnetEthSpec>(), None);
+        let line = |entry: &Entry| serde_json::to_string(entry).unwrap() + "\n";
+        let header_line = line(&Entry::Header(header));
+        let operation_line = line(&Entry::Operation(Operation::AdvanceSlot));
+
+        for lines in &[
+            vec![operation_line.clone()],
+            vec![header_line.clone(), header_line.clone()],
+        ] {
+            fs::write(&path, lines.concat()).unwrap();
+            match read(&path) {
+                Err(Error::InvalidOpLog { .. }) => {}
+                other => panic!("expected an invalid op log error, got {:?}", other),
+            }
+        }
+        fs::write(&path, header_line + &operation_line).unwrap();
+        assert_eq!(read(&path).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,104 @@
+//! Merkle proofs against the `BeaconState`.
+//!
+//! Proofs are checked with the verifier in `simulation_args`, which also defines the generalized
+//! indices they are taken at.
+use crate::{Error, Result, WhatBound};
+use eth2_hashing::hash;
+use tree_hash::TreeHash;
+use typenum::Unsigned;
+use types::beacon_state::BeaconState;
+use types::eth_spec::EthSpec;
+
+/// Returns the branch proving
+/// `state.shard_states[shard_index].execution_environment_states[ee_index]`, starting at the leaf
+pub fn execution_environment_state_branch<T: EthSpec>(
+    state: &BeaconState<T>,
+    shard_index: usize,
+    ee_index: usize,
+) -> Result<Vec<[u8; 32]>> {
+    let shard_state = state
+        .shard_states
+        .get(shard_index)
+        .ok_or(Error::OutOfBounds {
+            what: WhatBound::Shard,
+            index: shard_index,
+        })?;
+    let ee_states = &shard_state.execution_environment_states;
+    if ee_index >= ee_states.len() {
+        return Err(Error::OutOfBounds {
+            what: WhatBound::ExecutionEnvironmentState,
+            index: ee_index,
+        });
+    }
+    let mut branch = Vec::new();
+
+    // ShardState.execution_environment_states[ee_index]
+    let leaves = ee_states.iter().map(|root| root.tree_hash_root()).collect();
+    branch.extend(list_branch(
+        leaves,
+        T::MaxExecutionEnvironments::to_usize(),
+        ee_index,
+    ));
+    // `ShardState` only has a single field, so it adds nothing to the branch
+
+    // BeaconState.shard_states[shard_index]
+    let leaves = state
+        .shard_states
+        .iter()
+        .map(|shard_state| shard_state.tree_hash_root())
+        .collect();
+    branch.extend(list_branch(leaves, T::MaxShards::to_usize(), shard_index));
+
+    // BeaconState.shard_states, the fields must be in the same order as in `BeaconState`
+    let leaves = vec![
+        state.slot.tree_hash_root(),
+        state.shard_states.tree_hash_root(),
+        state.execution_environments.tree_hash_root(),
+    ];
+    branch.extend(branch_of(leaves, 2, 1));
+
+    Ok(branch)
+}
+
+/// Branch for the item at `index` of an SSZ list whose items have roots `leaves`, including the
+/// mixed in length
+fn list_branch(leaves: Vec<Vec<u8>>, max_length: usize, index: usize) -> Vec<[u8; 32]> {
+    let length = leaves.len();
+    let depth = max_length.next_power_of_two().trailing_zeros() as usize;
+    let mut branch = branch_of(leaves, depth, index);
+
+    let mut length_chunk = [0; 32];
+    length_chunk[..8].copy_from_slice(&(length as u64).to_le_bytes());
+    branch.push(length_chunk);
+    branch
+}
+
+/// Branch for the leaf at `index` of a tree of the given `depth`, where any leaves after `leaves`
+/// are zero
+fn branch_of(leaves: Vec<Vec<u8>>, depth: usize, index: usize) -> Vec<[u8; 32]> {
+    let mut branch = Vec::with_capacity(depth);
+    let mut layer = leaves;
+    let mut index = index;
+    // The root of an all-zero subtree at the current layer
+    let mut zero_node = vec![0; 32];
+
+    for _ in 0..depth {
+        let sibling = layer.get(index ^ 1).unwrap_or(&zero_node);
+        let mut node = [0; 32];
+        node.copy_from_slice(sibling);
+        branch.push(node);
+
+        layer = layer
+            .chunks(2)
+            .map(|pair| {
+                let mut preimage = pair[0].clone();
+                preimage.extend_from_slice(pair.get(1).unwrap_or(&zero_node));
+                hash(&preimage)
+            })
+            .collect();
+        zero_node = hash(&[&zero_node[..], &zero_node[..]].concat());
+        index /= 2;
+    }
+
+    branch
+}
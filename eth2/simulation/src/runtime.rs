@@ -0,0 +1,555 @@
+//! A minimal WebAssembly runtime for executing EE code against a single transaction.
+//!
+//! Implements the subset of the scout host functions used by the EEs in this repository:
+//! `eth2_loadPreStateRoot`, `eth2_savePostStateRoot`, `eth2_blockDataSize` and
+//! `eth2_blockDataCopy`, along with `eth2_log` so that EEs can report changes to their virtual
+//! state. Unlike `ewasm::RootRuntime`, failures inside the EE (traps, bad
+//! memory access, missing exports) are returned as errors instead of panicking, so that one
+//! misbehaving transaction can't take down the whole simulation. EE code is metered (see `gas`),
+//! so a transaction that runs for too long fails instead of hanging the simulation.
+use crate::gas;
+use parity_wasm::elements::{
+    BlockType, Error as ParityWasmError, Instruction, Module as ParityWasmModule, Type,
+    ValueType as ParityWasmValueType,
+};
+use snafu::{ResultExt, Snafu};
+use ssz_types::VariableList;
+use typenum::Unsigned;
+use types::shard_block_receipt::{Log, MaxLogDataSize, MaxLogTopics, MaxLogsPerTransaction};
+use types::slot_epoch_root::Root;
+use wasmi::{
+    Error as WasmiError, Externals, FuncInstance, FuncRef, HostError, ImportsBuilder, MemoryRef,
+    Module, ModuleImportResolver, ModuleInstance, NopExternals, NotStartedModuleRef, RuntimeArgs,
+    RuntimeValue, Signature, Trap, TrapKind, ValueType,
+};
+
+const LOAD_PRE_STATE_ROOT_FUNC_INDEX: usize = 0;
+const SAVE_POST_STATE_ROOT_FUNC_INDEX: usize = 1;
+const BLOCK_DATA_SIZE_FUNC_INDEX: usize = 2;
+const BLOCK_DATA_COPY_FUNC_INDEX: usize = 3;
+const LOG_FUNC_INDEX: usize = 4;
+
+/// Shorthand for result types returned from the runtime.
+pub type Result<V, E = Error> = std::result::Result<V, E>;
+
+/// Errors arising from executing EE code.
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("unable to parse execution environment code: {}", source))]
+    Parse { source: ParityWasmError },
+    #[snafu(display("unable to load execution environment code: {}", source))]
+    Load { source: WasmiError },
+    #[snafu(display("execution environment code has no '{}' export", name))]
+    MissingExport { name: String },
+    #[snafu(display("execution environment code's 'main' export must take no arguments"))]
+    MainTakesArguments,
+    #[snafu(display(
+        "execution environment code uses floating point numbers ({}), which aren't deterministic",
+        what
+    ))]
+    FloatingPoint { what: String },
+    #[snafu(display("execution environment trapped: {}", source))]
+    Execution { source: WasmiError },
+    #[snafu(display("execution environment trapped in a host function: {}", message))]
+    HostFunction { message: String },
+    #[snafu(display("execution environment ran out of gas, gas limit: {}", gas_limit))]
+    OutOfGas { gas_limit: u64 },
+}
+
+/// Errors raised inside a host function, which trap the EE that called it.
+#[derive(Debug, Snafu)]
+enum HostFunctionError {
+    #[snafu(display("log has {} topics, but at most {} are allowed", count, max))]
+    TooManyLogTopics { count: usize, max: usize },
+    #[snafu(display("log data is {} bytes, but at most {} are allowed", length, max))]
+    LogDataTooLong { length: usize, max: usize },
+    #[snafu(display("transaction emitted more than {} logs", max))]
+    TooManyLogs { max: usize },
+}
+
+impl HostError for HostFunctionError {}
+
+/// The result of running a transaction that didn't trap.
+#[derive(Debug)]
+pub struct Output {
+    pub post_root: [u8; 32],
+    // Logs emitted by the EE, in the order they were emitted
+    pub logs: Vec<Log>,
+}
+
+/// The outcome of running a transaction, along with the gas it used. Gas is used whether or not
+/// the EE traps.
+#[derive(Debug)]
+pub struct Outcome {
+    pub result: Result<Output>,
+    pub gas_used: u64,
+}
+
+/// Run the `main` export of a module returned by `compile` with the given transaction data and
+/// pre state root, returning the post state root saved by the EE and any logs it emitted. The EE
+/// traps once it has used `gas_limit` gas.
+pub fn execute(module: &Module, data: &[u8], pre_root: [u8; 32], gas_limit: u64) -> Outcome {
+    let mut gas_used = 0;
+    let result = execute_metered(module, data, pre_root, gas_limit, &mut gas_used);
+    Outcome { result, gas_used }
+}
+
+/// Check that `wasm_code` can be run as an EE, and instrument it for gas metering so that it is
+/// ready to be executed. The code must be a valid module that exports `main` and `memory`, only
+/// imports host functions provided by the runtime, and doesn't use floating point numbers.
+pub fn compile(wasm_code: &[u8]) -> Result<Module> {
+    let module: ParityWasmModule = parity_wasm::deserialize_buffer(wasm_code).context(Parse)?;
+    validate(module.clone())?;
+    // The code must be valid before it is instrumented, see `gas`
+    Module::from_parity_wasm_module(gas::inject_gas_metering(module)).context(Load)
+}
+
+fn validate(module: ParityWasmModule) -> Result<()> {
+    if let Some(what) = floating_point_use(&module) {
+        return Err(Error::FloatingPoint { what });
+    }
+
+    let module = Module::from_parity_wasm_module(module).context(Load)?;
+    // Resolving the imports checks that each one is a host function with the right signature
+    let imports = ImportsBuilder::new().with_resolver("env", &HostFunctionResolver);
+    let not_started = ModuleInstance::new(&module, &imports).context(Load)?;
+    let instance = not_started.not_started_instance();
+
+    instance
+        .export_by_name("memory")
+        .and_then(|export| export.as_memory().cloned())
+        .ok_or(Error::MissingExport {
+            name: "memory".to_string(),
+        })?;
+    let main = instance
+        .export_by_name("main")
+        .and_then(|export| export.as_func().cloned())
+        .ok_or(Error::MissingExport {
+            name: "main".to_string(),
+        })?;
+    if !main.signature().params().is_empty() {
+        return Err(Error::MainTakesArguments);
+    }
+
+    Ok(())
+}
+
+/// Describes the first use of a floating point type or instruction in `module`, if there is one
+fn floating_point_use(module: &ParityWasmModule) -> Option<String> {
+    let is_float = |value_type: &ParityWasmValueType| {
+        matches!(
+            value_type,
+            ParityWasmValueType::F32 | ParityWasmValueType::F64
+        )
+    };
+
+    let types = module
+        .type_section()
+        .map_or(&[][..], |section| section.types());
+    for (index, Type::Function(function_type)) in types.iter().enumerate() {
+        let return_type = function_type.return_type();
+        let mut value_types = function_type.params().iter().chain(return_type.as_ref());
+        if let Some(value_type) = value_types.find(|value_type| is_float(value_type)) {
+            return Some(format!("{} in function type {}", value_type, index));
+        }
+    }
+
+    let globals = module
+        .global_section()
+        .map_or(&[][..], |section| section.entries());
+    for (index, global) in globals.iter().enumerate() {
+        if is_float(&global.global_type().content_type()) {
+            return Some(format!(
+                "{} global {}",
+                global.global_type().content_type(),
+                index
+            ));
+        }
+    }
+
+    let bodies = module
+        .code_section()
+        .map_or(&[][..], |section| section.bodies());
+    for (index, body) in bodies.iter().enumerate() {
+        let local = body
+            .locals()
+            .iter()
+            .find(|local| is_float(&local.value_type()));
+        if let Some(local) = local {
+            return Some(format!(
+                "{} local in function body {}",
+                local.value_type(),
+                index
+            ));
+        }
+        let instruction = body
+            .code()
+            .elements()
+            .iter()
+            .find(|instruction| match instruction {
+                Instruction::Block(BlockType::Value(value_type))
+                | Instruction::Loop(BlockType::Value(value_type))
+                | Instruction::If(BlockType::Value(value_type)) => is_float(value_type),
+                _ => is_float_instruction(instruction),
+            });
+        if let Some(instruction) = instruction {
+            return Some(format!("{} in function body {}", instruction, index));
+        }
+    }
+
+    None
+}
+
+/// Whether the instruction operates on floating point numbers
+fn is_float_instruction(instruction: &Instruction) -> bool {
+    use Instruction::*;
+
+    matches!(
+        instruction,
+        F32Load(..)
+            | F64Load(..)
+            | F32Store(..)
+            | F64Store(..)
+            | F32Const(_)
+            | F64Const(_)
+            | F32Eq
+            | F32Ne
+            | F32Lt
+            | F32Gt
+            | F32Le
+            | F32Ge
+            | F64Eq
+            | F64Ne
+            | F64Lt
+            | F64Gt
+            | F64Le
+            | F64Ge
+            | F32Abs
+            | F32Neg
+            | F32Ceil
+            | F32Floor
+            | F32Trunc
+            | F32Nearest
+            | F32Sqrt
+            | F32Add
+            | F32Sub
+            | F32Mul
+            | F32Div
+            | F32Min
+            | F32Max
+            | F32Copysign
+            | F64Abs
+            | F64Neg
+            | F64Ceil
+            | F64Floor
+            | F64Trunc
+            | F64Nearest
+            | F64Sqrt
+            | F64Add
+            | F64Sub
+            | F64Mul
+            | F64Div
+            | F64Min
+            | F64Max
+            | F64Copysign
+            | I32TruncSF32
+            | I32TruncUF32
+            | I32TruncSF64
+            | I32TruncUF64
+            | I64TruncSF32
+            | I64TruncUF32
+            | I64TruncSF64
+            | I64TruncUF64
+            | F32ConvertSI32
+            | F32ConvertUI32
+            | F32ConvertSI64
+            | F32ConvertUI64
+            | F32DemoteF64
+            | F64ConvertSI32
+            | F64ConvertUI32
+            | F64ConvertSI64
+            | F64ConvertUI64
+            | F64PromoteF32
+            | I32ReinterpretF32
+            | I64ReinterpretF64
+            | F32ReinterpretI32
+            | F64ReinterpretI64
+    )
+}
+
+fn execute_metered(
+    module: &Module,
+    data: &[u8],
+    pre_root: [u8; 32],
+    gas_limit: u64,
+    gas_used: &mut u64,
+) -> Result<Output> {
+    let imports = ImportsBuilder::new().with_resolver("env", &HostFunctionResolver);
+    let not_started = ModuleInstance::new(module, &imports).context(Load)?;
+    let not_started_instance = not_started.not_started_instance().clone();
+
+    // The gas counter is a signed 64 bit integer
+    let gas_limit = gas_limit.min(i64::MAX as u64);
+    not_started_instance
+        .invoke_export(
+            gas::SET_GAS_LEFT_EXPORT,
+            &[RuntimeValue::I64(gas_limit as i64)],
+            &mut NopExternals,
+        )
+        .context(Load)?;
+
+    let memory = not_started_instance
+        .export_by_name("memory")
+        .and_then(|export| export.as_memory().cloned())
+        .ok_or(Error::MissingExport {
+            name: "memory".to_string(),
+        })?;
+    let mut externals = HostExternals {
+        memory,
+        data,
+        pre_root,
+        post_root: [0; 32],
+        logs: Vec::new(),
+    };
+
+    let result = run(not_started, &mut externals);
+
+    // Running out of gas traps the EE, so it takes precedence over any other error
+    let gas_left = not_started_instance
+        .invoke_export(gas::GAS_LEFT_EXPORT, &[], &mut NopExternals)
+        .context(Load)?
+        .and_then(|gas_left| gas_left.try_into::<i64>())
+        .unwrap_or(0);
+    *gas_used = gas_limit - gas_left.max(0) as u64;
+    if gas_left < 0 {
+        return Err(Error::OutOfGas { gas_limit });
+    }
+    result?;
+
+    Ok(Output {
+        post_root: externals.post_root,
+        logs: externals.logs,
+    })
+}
+
+/// Run the start function of the module, if it has one, and then its `main` export
+fn run(not_started: NotStartedModuleRef, externals: &mut HostExternals) -> Result<()> {
+    let instance = not_started
+        .run_start(externals)
+        .map_err(|trap| execution_error(WasmiError::Trap(trap)))?;
+    instance
+        .export_by_name("main")
+        .ok_or(Error::MissingExport {
+            name: "main".to_string(),
+        })?;
+    instance
+        .invoke_export("main", &[], externals)
+        .map_err(execution_error)?;
+    Ok(())
+}
+
+/// Keeps the message of errors raised by host functions, which wasmi only shows in debug form.
+fn execution_error(error: WasmiError) -> Error {
+    match error.as_host_error() {
+        Some(host_error) => Error::HostFunction {
+            message: host_error.to_string(),
+        },
+        None => Error::Execution { source: error },
+    }
+}
+
+/// Maps host function names imported by EE code to the indices used by `HostExternals`.
+struct HostFunctionResolver;
+
+impl ModuleImportResolver for HostFunctionResolver {
+    fn resolve_func(
+        &self,
+        field_name: &str,
+        _signature: &Signature,
+    ) -> Result<FuncRef, WasmiError> {
+        let func_ref = match field_name {
+            "eth2_loadPreStateRoot" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], None),
+                LOAD_PRE_STATE_ROOT_FUNC_INDEX,
+            ),
+            "eth2_savePostStateRoot" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], None),
+                SAVE_POST_STATE_ROOT_FUNC_INDEX,
+            ),
+            "eth2_blockDataSize" => FuncInstance::alloc_host(
+                Signature::new(&[][..], Some(ValueType::I32)),
+                BLOCK_DATA_SIZE_FUNC_INDEX,
+            ),
+            "eth2_blockDataCopy" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 3][..], None),
+                BLOCK_DATA_COPY_FUNC_INDEX,
+            ),
+            "eth2_log" => FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32; 4][..], None),
+                LOG_FUNC_INDEX,
+            ),
+            _ => {
+                return Err(WasmiError::Instantiation(format!(
+                    "host module doesn't export function with name {}",
+                    field_name
+                )))
+            }
+        };
+        Ok(func_ref)
+    }
+}
+
+/// Host-side state for a single EE execution.
+struct HostExternals<'a> {
+    memory: MemoryRef,
+    data: &'a [u8],
+    pre_root: [u8; 32],
+    post_root: [u8; 32],
+    logs: Vec<Log>,
+}
+
+impl<'a> HostExternals<'a> {
+    /// Copies the pre state root into memory at the given offset.
+    ///
+    /// # Signature
+    ///
+    /// ```text
+    /// eth2_loadPreStateRoot(offset: u32) -> ()
+    /// ```
+    fn ext_load_pre_state_root(&mut self, args: RuntimeArgs) -> Result<(), Trap> {
+        let ptr: u32 = args.nth_checked(0)?;
+        self.memory
+            .set(ptr, &self.pre_root)
+            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+        Ok(())
+    }
+
+    /// Saves the 32 bytes at the given offset as the post state root.
+    ///
+    /// # Signature
+    ///
+    /// ```text
+    /// eth2_savePostStateRoot(offset: u32) -> ()
+    /// ```
+    fn ext_save_post_state_root(&mut self, args: RuntimeArgs) -> Result<(), Trap> {
+        let ptr: u32 = args.nth_checked(0)?;
+        self.memory
+            .get_into(ptr, &mut self.post_root)
+            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+        Ok(())
+    }
+
+    /// Returns the length of the transaction data.
+    ///
+    /// # Signature
+    ///
+    /// ```text
+    /// eth2_blockDataSize() -> u32
+    /// ```
+    fn ext_block_data_size(&mut self) -> Result<i32, Trap> {
+        Ok(self.data.len() as i32)
+    }
+
+    /// Copies `length` bytes of the transaction data, starting at `offset`, into memory.
+    ///
+    /// # Signature
+    ///
+    /// ```text
+    /// eth2_blockDataCopy(dest_offset: u32, offset: u32, length: u32) -> ()
+    /// ```
+    fn ext_block_data_copy(&mut self, args: RuntimeArgs) -> Result<(), Trap> {
+        let ptr: u32 = args.nth_checked(0)?;
+        let offset = args.nth_checked::<u32>(1)? as usize;
+        let length = args.nth_checked::<u32>(2)? as usize;
+
+        let end = offset
+            .checked_add(length)
+            .ok_or(TrapKind::MemoryAccessOutOfBounds)?;
+        let bytes = self
+            .data
+            .get(offset..end)
+            .ok_or(TrapKind::MemoryAccessOutOfBounds)?;
+        self.memory
+            .set(ptr, bytes)
+            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+        Ok(())
+    }
+
+    /// Emits a log with `topic_count` 32 byte topics, stored one after another starting at
+    /// `topics_offset`, and `data_length` bytes of data starting at `data_offset`.
+    ///
+    /// # Signature
+    ///
+    /// ```text
+    /// eth2_log(topics_offset: u32, topic_count: u32, data_offset: u32, data_length: u32) -> ()
+    /// ```
+    fn ext_log(&mut self, args: RuntimeArgs) -> Result<(), Trap> {
+        let topics_offset: u32 = args.nth_checked(0)?;
+        let topic_count = args.nth_checked::<u32>(1)? as usize;
+        let data_offset: u32 = args.nth_checked(2)?;
+        let data_length = args.nth_checked::<u32>(3)? as usize;
+
+        // Check the limits before reading anything, so an EE can't make the host allocate more
+        // memory than a log can hold
+        if self.logs.len() >= MaxLogsPerTransaction::to_usize() {
+            return Err(HostFunctionError::TooManyLogs {
+                max: MaxLogsPerTransaction::to_usize(),
+            }
+            .into());
+        }
+        if topic_count > MaxLogTopics::to_usize() {
+            return Err(HostFunctionError::TooManyLogTopics {
+                count: topic_count,
+                max: MaxLogTopics::to_usize(),
+            }
+            .into());
+        }
+        if data_length > MaxLogDataSize::to_usize() {
+            return Err(HostFunctionError::LogDataTooLong {
+                length: data_length,
+                max: MaxLogDataSize::to_usize(),
+            }
+            .into());
+        }
+
+        let mut topics = Vec::with_capacity(topic_count);
+        for i in 0..topic_count {
+            let mut topic = [0; 32];
+            let ptr = topics_offset
+                .checked_add(32 * i as u32)
+                .ok_or(TrapKind::MemoryAccessOutOfBounds)?;
+            self.memory
+                .get_into(ptr, &mut topic)
+                .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+            topics.push(Root::from(topic));
+        }
+        let data = self
+            .memory
+            .get(data_offset, data_length)
+            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+
+        // The lengths were checked above, so nothing is truncated here
+        self.logs.push(Log {
+            topics: VariableList::from(topics),
+            data: VariableList::from(data),
+        });
+        Ok(())
+    }
+}
+
+impl<'a> Externals for HostExternals<'a> {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        match index {
+            LOAD_PRE_STATE_ROOT_FUNC_INDEX => self.ext_load_pre_state_root(args).map(|_| None),
+            SAVE_POST_STATE_ROOT_FUNC_INDEX => self.ext_save_post_state_root(args).map(|_| None),
+            BLOCK_DATA_SIZE_FUNC_INDEX => self.ext_block_data_size().map(|size| Some(size.into())),
+            BLOCK_DATA_COPY_FUNC_INDEX => self.ext_block_data_copy(args).map(|_| None),
+            LOG_FUNC_INDEX => self.ext_log(args).map(|_| None),
+            _ => panic!("unknown host function index: {}", index),
+        }
+    }
+}
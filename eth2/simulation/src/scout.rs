@@ -0,0 +1,366 @@
+//! Test vectors in the YAML format used by [scout](https://github.com/ewasm/scout), so that an EE
+//! can ship with declarative tests of the states its transactions lead to.
+//!
+//! ```yaml
+//! beacon_state:
+//!   execution_scripts:
+//!     - phase2_helloworld.wasm
+//! shard_pre_state:
+//!   exec_env_states:
+//!     - "0000000000000000000000000000000000000000000000000000000000000000"
+//! shard_blocks:
+//!   - env: 0
+//!     data: ""
+//! shard_post_state:
+//!   exec_env_states:
+//!     - "0000000000000000000000000000000000000000000000000000000000000000"
+//! ```
+//!
+//! Each script becomes an EE, with the pre-state at the same position as its initial state, and
+//! script paths are relative to the directory of the YAML file. Each shard block holds a single
+//! transaction for the EE `env`, with hex encoded `data`. Blocks run on shard 0 unless they have a
+//! `shard`, which scout doesn't use, and the post-state is checked on every shard blocks ran on.
+//! A transaction that traps fails the test vector too, even if the post-state matches.
+use crate::{ChainConfig, Error, Result, Simulation, TestVectorIo};
+use hex::FromHex;
+use serde::Deserialize;
+use snafu::ResultExt;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use types::eth_spec::EthSpec;
+
+#[derive(Debug, Deserialize)]
+struct TestVector {
+    beacon_state: BeaconState,
+    shard_pre_state: ShardState,
+    shard_blocks: Vec<ShardBlock>,
+    shard_post_state: ShardState,
+}
+
+#[derive(Debug, Deserialize)]
+struct BeaconState {
+    execution_scripts: Vec<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShardState {
+    exec_env_states: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShardBlock {
+    env: u64,
+    data: String,
+    #[serde(default)]
+    shard: u64,
+}
+
+/// A way in which a test vector failed
+#[derive(Debug, PartialEq)]
+pub enum Failure {
+    Mismatch(Mismatch),
+    Trap(Trap),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Mismatch(mismatch) => mismatch.fmt(f),
+            Failure::Trap(trap) => trap.fmt(f),
+        }
+    }
+}
+
+/// An EE state after running a test vector that isn't the one it expects
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub shard_index: u64,
+    pub ee_index: u64,
+    pub expected: [u8; 32],
+    pub actual: [u8; 32],
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "shard {} EE {}: expected post-state {}, got {}",
+            self.shard_index,
+            self.ee_index,
+            hex::encode(self.expected),
+            hex::encode(self.actual)
+        )
+    }
+}
+
+/// A transaction in a test vector that trapped
+#[derive(Debug, PartialEq)]
+pub struct Trap {
+    // The position of the shard block in the test vector
+    pub block_index: usize,
+    pub shard_index: u64,
+    pub ee_index: u64,
+    pub message: String,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "shard block {} on shard {} EE {}: transaction trapped: {}",
+            self.block_index, self.shard_index, self.ee_index, self.message
+        )
+    }
+}
+
+/// Run the test vector in the YAML file at `path` in a new simulation, and return the ways it
+/// failed: the transactions that trapped, in order, followed by the EE states that don't match its
+/// post-state
+pub fn run<T: EthSpec>(path: &Path) -> Result<Vec<Failure>> {
+    run_with_chain_config::<T>(path, ChainConfig::of::<T>())
+}
+
+/// Like `run`, in a simulation that enforces the limits in `chain_config`
+pub fn run_with_chain_config<T: EthSpec>(
+    path: &Path,
+    chain_config: ChainConfig,
+) -> Result<Vec<Failure>> {
+    let invalid = |reason: String| Error::InvalidTestVector {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let yaml = fs::read_to_string(path).context(TestVectorIo { path })?;
+    let test_vector: TestVector =
+        serde_yaml::from_str(&yaml).map_err(|e| invalid(e.to_string()))?;
+
+    let scripts = test_vector.beacon_state.execution_scripts;
+    let pre_states = decode_states(&test_vector.shard_pre_state).map_err(invalid)?;
+    let post_states = decode_states(&test_vector.shard_post_state).map_err(invalid)?;
+    if pre_states.len() != scripts.len() || post_states.len() != scripts.len() {
+        return Err(invalid(
+            "there must be a pre-state and a post-state for each execution script".to_string(),
+        ));
+    }
+
+    let mut simulation: Simulation<T> = Simulation::new().with_chain_config(chain_config)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    for (script, initial_state) in scripts.iter().zip(pre_states) {
+        let script = dir.join(script);
+        let wasm_code = fs::read(&script).context(TestVectorIo { path: &script })?;
+        simulation.create_execution_environment(simulation_args::CreateExecutionEnvironment {
+            ee: simulation_args::ExecutionEnvironment {
+                initial_state,
+                wasm_code,
+            },
+            ..Default::default()
+        })?;
+    }
+
+    let mut failures = Vec::new();
+    let mut shard_indices = BTreeSet::new();
+    for (block_index, block) in test_vector.shard_blocks.into_iter().enumerate() {
+        let data = Vec::from_hex(&block.data)
+            .map_err(|e| invalid(format!("shard block data {:?}: {}", block.data, e)))?;
+        // Scout doesn't limit gas, so each transaction may use all that a block allows
+        let transaction = simulation_args::ShardTransaction {
+            data,
+            ee_index: block.env,
+            gas_limit: simulation.chain_config().max_gas_per_shard_block,
+        };
+        let shard_block_receipt =
+            simulation.create_shard_block(simulation_args::CreateShardBlock {
+                shard_index: block.shard,
+                shard_block: simulation_args::ShardBlock {
+                    transactions: vec![transaction],
+                    ..Default::default()
+                },
+            })?;
+        for transaction_receipt in shard_block_receipt.transaction_receipts {
+            if !transaction_receipt.success {
+                failures.push(Failure::Trap(Trap {
+                    block_index,
+                    shard_index: block.shard,
+                    ee_index: transaction_receipt.ee_index,
+                    message: transaction_receipt.trap_message.unwrap_or_default(),
+                }));
+            }
+        }
+        shard_indices.insert(block.shard);
+    }
+    if shard_indices.is_empty() {
+        shard_indices.insert(0);
+    }
+
+    for shard_index in shard_indices {
+        for (ee_index, expected) in post_states.iter().enumerate() {
+            let ee_index = ee_index as u64;
+            let actual = simulation.get_execution_environment_state(
+                simulation_args::GetExecutionEnvironmentState {
+                    ee_index,
+                    shard_index,
+                    shard_slot_index: None,
+                },
+            )?;
+            if actual != *expected {
+                failures.push(Failure::Mismatch(Mismatch {
+                    shard_index,
+                    ee_index,
+                    expected: *expected,
+                    actual,
+                }));
+            }
+        }
+    }
+    Ok(failures)
+}
+
+fn decode_states(shard_state: &ShardState) -> std::result::Result<Vec<[u8; 32]>, String> {
+    shard_state
+        .exec_env_states
+        .iter()
+        .map(|state| {
+            <[u8; 32]>::from_hex(state)
+                .map_err(|e| format!("execution environment state {:?}: {}", state, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bazaar::{BAZAAR_DATA, BAZAAR_INITIAL_STATE, BAZAAR_POST_STATE, BAZAAR_WASM};
+    use types::eth_spec::{MainnetEthSpec, MinimalEthSpec};
+
+    fn test_vector_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join(name)
+    }
+
+    #[test]
+    fn scout_test_vectors_pass() {
+        for name in &["phase2_bazaar.yaml", "phase2_helloworld.yaml"] {
+            let failures = run::<MainnetEthSpec>(&test_vector_path(name)).unwrap();
+            assert_eq!(failures, vec![], "{}", name);
+            let failures = run::<MinimalEthSpec>(&test_vector_path(name)).unwrap();
+            assert_eq!(failures, vec![], "{}", name);
+        }
+    }
+
+    #[test]
+    fn bazaar_test_vector_matches_the_constants_tests_use() {
+        let path = test_vector_path("phase2_bazaar.yaml");
+        let test_vector: TestVector =
+            serde_yaml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let script =
+            test_vector_path(&test_vector.beacon_state.execution_scripts[0].to_string_lossy());
+        assert_eq!(fs::read(script).unwrap(), BAZAAR_WASM);
+        assert_eq!(
+            test_vector.shard_pre_state.exec_env_states,
+            vec![BAZAAR_INITIAL_STATE]
+        );
+        assert_eq!(test_vector.shard_blocks[0].data, BAZAAR_DATA);
+        assert_eq!(
+            test_vector.shard_post_state.exec_env_states,
+            vec![BAZAAR_POST_STATE]
+        );
+    }
+
+    #[test]
+    fn mismatched_post_states_are_reported_for_each_shard() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bazaar.yaml");
+        // The bazaar block runs on shards 0 and 5, but the EE state is expected not to change
+        let yaml = format!(
+            r#"beacon_state:
+  execution_scripts:
+    - {script}
+shard_pre_state:
+  exec_env_states:
+    - "{initial_state}"
+shard_blocks:
+  - env: 0
+    data: "{data}"
+  - env: 0
+    shard: 5
+    data: "{data}"
+shard_post_state:
+  exec_env_states:
+    - "{initial_state}"
+"#,
+            script = test_vector_path("phase2_bazaar.wasm").display(),
+            initial_state = BAZAAR_INITIAL_STATE,
+            data = BAZAAR_DATA,
+        );
+        fs::write(&path, yaml).unwrap();
+
+        let failures = run::<MainnetEthSpec>(&path).unwrap();
+        let expected = |shard_index| {
+            Failure::Mismatch(Mismatch {
+                shard_index,
+                ee_index: 0,
+                expected: <[u8; 32]>::from_hex(BAZAAR_INITIAL_STATE).unwrap(),
+                actual: <[u8; 32]>::from_hex(BAZAAR_POST_STATE).unwrap(),
+            })
+        };
+        assert_eq!(failures, vec![expected(0), expected(5)]);
+    }
+
+    #[test]
+    fn trapped_transactions_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("unreachable.yaml");
+        // The EE traps without changing its state, so only the trap fails the test vector
+        let state = "0000000000000000000000000000000000000000000000000000000000000000";
+        let yaml = format!(
+            r#"beacon_state:
+  execution_scripts:
+    - {script}
+shard_pre_state:
+  exec_env_states:
+    - "{state}"
+shard_blocks:
+  - env: 0
+    data: ""
+shard_post_state:
+  exec_env_states:
+    - "{state}"
+"#,
+            script = test_vector_path("unreachable.wasm").display(),
+            state = state,
+        );
+        fs::write(&path, yaml).unwrap();
+
+        match run::<MainnetEthSpec>(&path).unwrap().as_slice() {
+            [Failure::Trap(trap)] => {
+                assert_eq!(
+                    (trap.block_index, trap.shard_index, trap.ee_index),
+                    (0, 0, 0)
+                );
+                assert!(!trap.message.is_empty());
+            }
+            other => panic!("expected a single trap, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_test_vectors_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("helloworld.yaml");
+        let helloworld = fs::read_to_string(test_vector_path("phase2_helloworld.yaml")).unwrap();
+        // The post-state is missing the state of the only EE
+        let yaml = helloworld.replace(
+            "shard_post_state:\n  exec_env_states:\n    - \"0000000000000000000000000000000000000000000000000000000000000000\"\n",
+            "shard_post_state:\n  exec_env_states: []\n",
+        );
+        assert_ne!(yaml, helloworld);
+        fs::write(&path, yaml).unwrap();
+
+        match run::<MainnetEthSpec>(&path) {
+            Err(Error::InvalidTestVector { .. }) => (),
+            other => panic!("expected an invalid test vector error, got {:?}", other),
+        }
+    }
+}
@@ -1,53 +1,285 @@
-use crate::store::Store;
-use crate::{ArgsError, Error, Result, WhatBound};
-use ewasm::{Execute, RootRuntime};
-use simulation_args;
+use crate::beacon_chain;
+use crate::chain_config::ChainConfig;
+use crate::genesis::Genesis;
+use crate::module_cache::ModuleCache;
+use crate::proof;
+use crate::runtime;
+use crate::snapshot;
+use crate::store::{self, MemoryStore, ShardBlockRecord, StoreBackend};
+use crate::{ArgsError, Error, InvalidExecutionEnvironmentCode, Result, SnapshotIo, WhatBound};
+use rayon::prelude::*;
+use simulation_args::{self, async_trait, SimulationApi};
 use snafu::ResultExt;
+use ssz_types::{BitVector, VariableList};
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::fs;
+use std::mem;
+use std::path::Path;
+use std::time::Instant;
+use tree_hash::TreeHash;
+use types::beacon_block::BeaconBlock;
+use types::beacon_state::BeaconState;
 use types::eth_spec::EthSpec;
 use types::execution_environment::ExecutionEnvironment;
 use types::shard_block::ShardBlock;
-use types::slot_epoch_root::{Root, Shard};
+use types::shard_block_receipt::{ShardBlockReceipt, TransactionReceipt};
+use types::shard_state::ShardState;
+use types::slot_epoch_root::{Root, Shard, ShardSlot, Slot, ValidatorIndex};
+
+/// The outcome of executing a shard block, before anything is stored
+#[derive(Debug)]
+struct ExecutedShardBlock<T>
+where
+    T: EthSpec,
+{
+    execution_environment_states: VariableList<Root, T::MaxExecutionEnvironments>,
+    transaction_receipts: Vec<TransactionReceipt>,
+    // Kept out of the receipts, which are hashed and stored, since timings differ between runs
+    execution_times_nanos: Vec<u64>,
+    gas_used: u64,
+}
+
+/// Shard blocks that have been executed by `Simulation::execute_shard_blocks` but not stored yet
+#[derive(Debug)]
+pub struct ExecutedShardBlocks<T>
+where
+    T: EthSpec,
+{
+    // In the order the blocks were given
+    shard_blocks: Vec<(usize, ShardBlock<T>, ExecutedShardBlock<T>)>,
+    // The revision of the simulation the blocks were executed against
+    revision: u64,
+}
 
 #[derive(Debug)]
 pub struct Simulation<T>
 where
     T: EthSpec,
 {
-    store: Store<T>,
+    store: Box<dyn StoreBackend<T>>,
+    module_cache: ModuleCache,
+    chain_config: ChainConfig,
+    // Bumped whenever the store changes, so that results computed from an earlier state can be
+    // told apart
+    revision: u64,
 }
 
 impl<T: EthSpec> Simulation<T> {
+    /// Create a simulation that keeps everything in memory
     pub fn new() -> Self {
         Self {
-            store: Store::new(),
+            store: Box::new(MemoryStore::new()),
+            module_cache: ModuleCache::new(),
+            chain_config: ChainConfig::of::<T>(),
+            revision: 0,
+        }
+    }
+
+    /// Create a simulation that uses `store`, carrying on from whatever is already in it
+    pub fn with_store<S: StoreBackend<T> + 'static>(store: S) -> Result<Self> {
+        let module_cache = ModuleCache::new();
+        for ee in store.current_beacon_state().execution_environments.iter() {
+            module_cache
+                .get_or_compile(&ee.wasm_code)
+                .context(InvalidExecutionEnvironmentCode)?;
+        }
+        Ok(Self {
+            store: Box::new(store),
+            module_cache,
+            chain_config: ChainConfig::of::<T>(),
+            revision: 0,
+        })
+    }
+
+    /// Enforce the limits in `chain_config` instead of the spec's, which they must not exceed.
+    /// Whatever the simulation already holds must be within them too.
+    pub fn with_chain_config(mut self, chain_config: ChainConfig) -> Result<Self> {
+        chain_config.check::<T>()?;
+        check_within_chain_config(&chain_config, self.store.as_ref())?;
+        self.chain_config = chain_config;
+        Ok(self)
+    }
+
+    /// The limits the simulation enforces
+    pub fn chain_config(&self) -> &ChainConfig {
+        &self.chain_config
+    }
+
+    /// Create a simulation that keeps everything in memory, starting from `genesis`
+    pub fn from_genesis(genesis: Genesis) -> Result<Self> {
+        Self::with_store_from_genesis(MemoryStore::new(), genesis)
+    }
+
+    /// Create a simulation that uses `store`, which must not hold a simulation yet, starting from
+    /// `genesis`
+    pub fn with_store_from_genesis<S: StoreBackend<T> + 'static>(
+        store: S,
+        genesis: Genesis,
+    ) -> Result<Self> {
+        Self::with_store(store)?.with_genesis(genesis)
+    }
+
+    /// Start a simulation that doesn't hold anything yet from `genesis`, whose EEs must be within
+    /// the chain config. A chain config in the genesis replaces the simulation's.
+    ///
+    /// The genesis is stored in one write, so nothing is stored if any of it is invalid.
+    pub fn with_genesis(mut self, genesis: Genesis) -> Result<Self> {
+        if let Some(spec) = genesis.spec {
+            if spec != T::spec_name() {
+                return Err(Error::GenesisSpecMismatch {
+                    spec: spec.to_string(),
+                    expected: T::spec_name().to_string(),
+                });
+            }
+        }
+        if !self.is_empty() {
+            return Err(Error::GenesisOverExistingSimulation);
+        }
+        if let Some(chain_config) = genesis.chain_config {
+            self = self.with_chain_config(chain_config)?;
         }
+
+        let mut beacon_state = self.store.current_beacon_state().clone();
+        for genesis_ee in genesis.execution_environments {
+            let create_ee_args = simulation_args::CreateExecutionEnvironment {
+                ee: genesis_ee.ee,
+                shard_initial_states: genesis_ee.shard_initial_states,
+                active_shards: genesis_ee.active_shards,
+            };
+            self.add_execution_environment(&mut beacon_state, create_ee_args)?;
+        }
+        beacon_state.slot = Slot::new(genesis.slot);
+        self.store_mut().put_beacon_state(beacon_state)?;
+        Ok(self)
+    }
+
+    /// Whether the simulation doesn't hold anything yet: no EEs, no blocks, and the beacon chain
+    /// hasn't left slot 0
+    pub fn is_empty(&self) -> bool {
+        let beacon_state = self.store.current_beacon_state();
+        let no_shard_blocks = (0..beacon_state.shard_states.len() as u64).all(|shard_index| {
+            let shard_blocks = self.store.shard_blocks(Shard::new(shard_index));
+            shard_blocks.unwrap_or(&[]).is_empty()
+        });
+        beacon_state.slot.as_u64() == 0
+            && beacon_state.execution_environments.is_empty()
+            && self.store.beacon_blocks().is_empty()
+            && no_shard_blocks
+    }
+
+    /// Advance the beacon chain to the next slot without a beacon block, return the new slot
+    pub fn advance_slot(&mut self) -> Result<u64> {
+        let mut beacon_state = self.store.current_beacon_state().clone();
+        let next_slot = beacon_state
+            .slot
+            .as_u64()
+            .checked_add(1)
+            .map(Slot::new)
+            .ok_or(Error::SlotOverflow {
+                slot: beacon_state.slot.into(),
+            })?;
+        beacon_chain::process_slots(&mut beacon_state, next_slot)?;
+        self.store_mut().put_beacon_state(beacon_state)?;
+        Ok(next_slot.into())
+    }
+
+    /// Add a new beacon block, advancing the beacon chain to the block's slot, return the slot
+    pub fn create_beacon_block(&mut self, a: simulation_args::CreateBeaconBlock) -> Result<u64> {
+        let beacon_block = BeaconBlock::from(a.beacon_block);
+        let latest_block_slot = self.store.beacon_blocks().last().map(|block| block.slot);
+
+        // The state transition is applied to a copy of the beacon state, so an invalid block
+        // leaves the stored beacon state untouched
+        let mut beacon_state = self.store.current_beacon_state().clone();
+        beacon_chain::state_transition(&mut beacon_state, &beacon_block, latest_block_slot)?;
+
+        let slot = beacon_block.slot;
+        self.store_mut()
+            .put_beacon_block(beacon_block, beacon_state)?;
+        Ok(slot.into())
     }
 
     /// Add a new execution environment, return EE index
     pub fn create_execution_environment(
         &mut self,
         a: simulation_args::CreateExecutionEnvironment,
+    ) -> Result<u64> {
+        let mut beacon_state = self.store.current_beacon_state().clone();
+        let ee_index = self.add_execution_environment(&mut beacon_state, a)?;
+        self.store_mut().put_beacon_state(beacon_state)?;
+        Ok(ee_index)
+    }
+
+    /// Add a new execution environment to `beacon_state` without storing it, return EE index
+    fn add_execution_environment(
+        &self,
+        beacon_state: &mut BeaconState<T>,
+        a: simulation_args::CreateExecutionEnvironment,
     ) -> Result<u64> {
         // Create internal EE struct from args
-        let ee = ExecutionEnvironment::try_from(a.ee).context(ArgsError)?;
-        let cloned_initial_state = ee.initial_state.clone();
+        let mut ee = ExecutionEnvironment::try_from(a.ee).context(ArgsError)?;
+        if ee.wasm_code.len() as u64 > self.chain_config.max_ee_byte_code_size {
+            return Err(Error::MaxLengthExceeded {
+                what: "execution environment code".to_string(),
+            });
+        }
+        // Compile the code now, so that problems with it are caught here rather than when a
+        // shard block is created, and so that transactions can use the cached module
+        self.module_cache
+            .get_or_compile(&ee.wasm_code)
+            .context(InvalidExecutionEnvironmentCode)?;
+        let max_shards = self.chain_config.max_shards;
+        let out_of_bounds = |shard_index: u64| Error::OutOfBounds {
+            what: WhatBound::Shard,
+            index: shard_index as usize,
+        };
+        let shard_indices = a
+            .active_shards
+            .iter()
+            .flatten()
+            .chain(a.shard_initial_states.keys());
+        for &shard_index in shard_indices {
+            if shard_index >= max_shards {
+                return Err(out_of_bounds(shard_index));
+            }
+        }
+        if let Some(active_shards) = a.active_shards {
+            ee.active_shards = BitVector::new();
+            for shard_index in active_shards {
+                ee.active_shards
+                    .set(shard_index as usize, true)
+                    .map_err(|_| out_of_bounds(shard_index))?;
+            }
+        }
+        let initial_state = ee.initial_state;
 
         // Add EE code to beacon chain
-        self.store
-            .current_beacon_state
+        if beacon_state.execution_environments.len() as u64
+            >= self.chain_config.max_execution_environments
+        {
+            return Err(Error::MaxLengthExceeded {
+                what: "number of execution environments".to_string(),
+            });
+        }
+        beacon_state
             .execution_environments
             .push(ee)
             .map_err(|_| Error::MaxLengthExceeded {
                 what: format!("number of execution environments"),
             })?;
 
-        // For each shard, add the initial state to the shard
-        for shard_state in self.store.current_beacon_state.shard_states.iter_mut() {
-            // Set the initial state of the EE on each ShardState
+        // For each shard, add the initial state to the shard. Shards the EE isn't active on get
+        // one too, so that EE indices are the same on every shard. That includes the shards past
+        // the chain config's `max_shards`, which never get any blocks
+        for (shard_index, shard_state) in beacon_state.shard_states.iter_mut().enumerate() {
+            let state = a
+                .shard_initial_states
+                .get(&(shard_index as u64))
+                .map_or(initial_state, |&state| Root::from(state));
             shard_state
                 .execution_environment_states
-                .push(cloned_initial_state)
+                .push(state)
                 .map_err(|_| Error::MaxLengthExceeded {
                     what: format!("number of execution environment states"),
                 })?;
@@ -55,79 +287,318 @@ impl<T: EthSpec> Simulation<T> {
             // Each shard should have the same # of ee states as there are EEs
             assert_eq!(
                 shard_state.execution_environment_states.len(),
-                self.store.current_beacon_state.execution_environments.len()
+                beacon_state.execution_environments.len()
             );
         }
 
-        let ee_index = self.store.current_beacon_state.execution_environments.len() - 1;
+        let ee_index = beacon_state.execution_environments.len() - 1;
         Ok(ee_index as u64)
     }
 
     /// Add a new shard block containing a list of transactions that need to be executed
-    /// Execute all transactions on the appropriate shards / EEs, return ShardBlock index
-    pub fn create_shard_block(&mut self, a: simulation_args::CreateShardBlock) -> Result<u64> {
-        // Get the specified ShardState (if it exists)
+    /// Execute all transactions on the appropriate shards / EEs, return a receipt describing
+    /// the outcome of each transaction
+    ///
+    /// Block execution is atomic: if the block is invalid, neither the shard state nor the
+    /// stored shard blocks are modified. A transaction that traps inside its EE does not
+    /// invalidate the block; it leaves its EE state unchanged and is reported in the receipt.
+    pub fn create_shard_block(
+        &mut self,
+        a: simulation_args::CreateShardBlock,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
         let shard_index = a.shard_index as usize;
-        let shard = Shard::new(a.shard_index);
-        let shard_state = self
+
+        // Create the internal shard block from args, the header fields are filled in once the
+        // block is known to be valid
+        let shard_block: ShardBlock<T> = ShardBlock::try_from(a.shard_block).context(ArgsError)?;
+
+        // Nothing is changed if the block is invalid
+        let executed = self.execute_shard_block(shard_index, &shard_block)?;
+        self.commit_shard_block(shard_index, shard_block, executed)
+    }
+
+    /// Add blocks to several shards at once, returning a receipt for each block in the order the
+    /// blocks were given
+    ///
+    /// Blocks for different shards are executed in parallel, while blocks for the same shard are
+    /// executed one after another in the order they were given, so the outcome is the same as
+    /// creating the blocks one at a time. If any of the blocks is invalid, none of them are stored.
+    pub fn create_shard_blocks(
+        &mut self,
+        a: simulation_args::CreateShardBlocks,
+    ) -> Result<Vec<simulation_args::ShardBlockReceipt>> {
+        let executed = self.execute_shard_blocks(a)?;
+        self.commit_shard_blocks(executed)
+    }
+
+    /// Execute blocks for several shards in the same way as `create_shard_blocks`, without storing
+    /// them
+    ///
+    /// This only reads the simulation, so queries can be answered while the blocks run. The result
+    /// has to be passed to `commit_shard_blocks` before anything else changes the simulation, since
+    /// it was executed against the current state, and is rejected otherwise.
+    pub fn execute_shard_blocks(
+        &self,
+        a: simulation_args::CreateShardBlocks,
+    ) -> Result<ExecutedShardBlocks<T>> {
+        let mut shard_blocks = Vec::with_capacity(a.shard_blocks.len());
+        for a in a.shard_blocks {
+            let shard_index = a.shard_index as usize;
+            let shard_block: ShardBlock<T> =
+                ShardBlock::try_from(a.shard_block).context(ArgsError)?;
+            self.validate_shard_block(shard_index, &shard_block)?;
+            shard_blocks.push((shard_index, shard_block));
+        }
+
+        // Each block starts from the EE states left by the one before it on the same shard, so
+        // each shard's blocks run in order on a single thread
+        let mut positions_by_shard: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (position, (shard_index, _)) in shard_blocks.iter().enumerate() {
+            positions_by_shard
+                .entry(*shard_index)
+                .or_default()
+                .push(position);
+        }
+        let beacon_state = self.store.current_beacon_state();
+        let module_cache = &self.module_cache;
+        let executed_by_shard = positions_by_shard
+            .into_par_iter()
+            .map(|(shard_index, positions)| {
+                let mut execution_environment_states = beacon_state.shard_states[shard_index]
+                    .execution_environment_states
+                    .clone();
+                let mut executed = Vec::with_capacity(positions.len());
+                for position in positions {
+                    let executed_shard_block = execute_transactions(
+                        beacon_state,
+                        module_cache,
+                        execution_environment_states,
+                        &shard_blocks[position].1,
+                    )?;
+                    execution_environment_states =
+                        executed_shard_block.execution_environment_states.clone();
+                    executed.push((position, executed_shard_block));
+                }
+                Ok(executed)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut executed: Vec<_> = executed_by_shard.into_iter().flatten().collect();
+        executed.sort_by_key(|(position, _)| *position);
+        Ok(ExecutedShardBlocks {
+            shard_blocks: shard_blocks
+                .into_iter()
+                .zip(executed)
+                .map(|((shard_index, shard_block), (_, executed))| {
+                    (shard_index, shard_block, executed)
+                })
+                .collect(),
+            revision: self.revision,
+        })
+    }
+
+    /// Store blocks executed by `execute_shard_blocks`, returning a receipt for each block in the
+    /// order the blocks were given
+    ///
+    /// The blocks are rejected if the simulation changed since they were executed. They are
+    /// stored all at once, so if any of them can't be, none are.
+    pub fn commit_shard_blocks(
+        &mut self,
+        executed: ExecutedShardBlocks<T>,
+    ) -> Result<Vec<simulation_args::ShardBlockReceipt>> {
+        if executed.revision != self.revision {
+            return Err(Error::StaleShardBlocks);
+        }
+        let mut records = Vec::with_capacity(executed.shard_blocks.len());
+        let mut execution_times_nanos = Vec::with_capacity(executed.shard_blocks.len());
+        for (shard_index, shard_block, mut executed) in executed.shard_blocks {
+            execution_times_nanos.push(mem::take(&mut executed.execution_times_nanos));
+            let record = self.shard_block_record(shard_index, shard_block, executed, &records)?;
+            records.push(record);
+        }
+        let receipts: Vec<_> = records
+            .iter()
+            .map(|record| record.receipt.clone())
+            .collect();
+        self.store_mut().put_shard_blocks(records)?;
+
+        Ok(receipts
+            .into_iter()
+            .zip(execution_times_nanos)
+            .map(|(receipt, execution_times_nanos)| {
+                receipt_with_execution_times(receipt, &execution_times_nanos)
+            })
+            .collect())
+    }
+
+    /// Store a shard block that has been executed, along with its receipt and the shard state it
+    /// led to
+    fn commit_shard_block(
+        &mut self,
+        shard_index: usize,
+        shard_block: ShardBlock<T>,
+        mut executed: ExecutedShardBlock<T>,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
+        let execution_times_nanos = mem::take(&mut executed.execution_times_nanos);
+        let record = self.shard_block_record(shard_index, shard_block, executed, &[])?;
+        let receipt = record.receipt.clone();
+        self.store_mut().put_shard_blocks(vec![record])?;
+        Ok(receipt_with_execution_times(
+            receipt,
+            &execution_times_nanos,
+        ))
+    }
+
+    /// Make the record of an executed shard block that is stored after the ones in `pending`,
+    /// filling in the header fields of the block and making its receipt and post state
+    fn shard_block_record(
+        &self,
+        shard_index: usize,
+        shard_block: ShardBlock<T>,
+        executed: ExecutedShardBlock<T>,
+        pending: &[ShardBlockRecord<T>],
+    ) -> Result<ShardBlockRecord<T>> {
+        let shard = Shard::new(shard_index as u64);
+        let shard_blocks_for_shard = self.store.shard_blocks(shard).ok_or(Error::OutOfBounds {
+            index: shard_index,
+            what: WhatBound::Shard,
+        })?;
+        let pending_for_shard: Vec<_> = pending
+            .iter()
+            .map(|record| &record.block)
+            .filter(|block| block.shard == shard)
+            .collect();
+        let shard_slot =
+            ShardSlot::new((shard_blocks_for_shard.len() + pending_for_shard.len()) as u64);
+        let shard_parent_root = pending_for_shard
+            .last()
+            .copied()
+            .or_else(|| shard_blocks_for_shard.last())
+            .map_or_else(Root::zero, ShardBlock::canonical_root);
+        let beacon_parent_root = self
             .store
-            .current_beacon_state
+            .beacon_blocks()
+            .last()
+            .map_or_else(Root::zero, BeaconBlock::canonical_root);
+        // Proposers aren't simulated yet, so every block is proposed by validator 0
+        let block = ShardBlock::new(
+            shard_parent_root,
+            beacon_parent_root,
+            shard_slot,
+            shard,
+            ValidatorIndex::new(0),
+            shard_block.body,
+        );
+        let receipt = ShardBlockReceipt {
+            shard,
+            shard_slot,
+            shard_block_root: block.canonical_root(),
+            beacon_slot: self.store.current_beacon_state().slot,
+            gas_used: executed.gas_used,
+            transaction_receipts: VariableList::new(executed.transaction_receipts).map_err(
+                |_| Error::MaxLengthExceeded {
+                    what: "number of transaction receipts".to_string(),
+                },
+            )?,
+        };
+        let mut post_state = self.store.current_beacon_state().shard_states[shard_index].clone();
+        post_state.execution_environment_states = executed.execution_environment_states;
+        Ok(ShardBlockRecord {
+            block,
+            receipt,
+            post_state,
+        })
+    }
+
+    /// Execute the transactions in a shard block against a copy of the shard's EE states, so that
+    /// neither the store nor the current shard state is changed
+    fn execute_shard_block(
+        &self,
+        shard_index: usize,
+        shard_block: &ShardBlock<T>,
+    ) -> Result<ExecutedShardBlock<T>> {
+        // Make sure every transaction in the block can be executed before running any of them
+        self.validate_shard_block(shard_index, shard_block)?;
+        let shard_state = &self.store.current_beacon_state().shard_states[shard_index];
+
+        execute_transactions(
+            self.store.current_beacon_state(),
+            &self.module_cache,
+            shard_state.execution_environment_states.clone(),
+            shard_block,
+        )
+    }
+
+    /// Check that every transaction in a shard block refers to an EE that exists and is active on
+    /// the shard, and that the block is within the block gas limit
+    fn validate_shard_block(&self, shard_index: usize, shard_block: &ShardBlock<T>) -> Result<()> {
+        self.check_shard(shard_index as u64)?;
+        if shard_block.body.transactions.len() as u64 > self.chain_config.max_transactions_per_block
+        {
+            return Err(Error::MaxLengthExceeded {
+                what: "number of transactions in a shard block".to_string(),
+            });
+        }
+        let beacon_state = self.store.current_beacon_state();
+        let shard_state = beacon_state
             .shard_states
-            .get_mut(shard_index)
+            .get(shard_index)
             .ok_or(Error::OutOfBounds {
                 what: WhatBound::Shard,
                 index: shard_index,
             })?;
 
-        // Create the internal shard block from args
-        let shard_block: ShardBlock<T> = ShardBlock::try_from(a.shard_block).context(ArgsError)?;
-
-        // Execute transactions and update shard state for all transactions
-        for transaction in shard_block.transactions.iter() {
-            // Get the specified EE (if it exists)
+        for transaction in shard_block.body.transactions.iter() {
             let ee_index: usize = transaction.ee_index.into();
-            let execution_environment = self
-                .store
-                .current_beacon_state
-                .execution_environments
-                .get(ee_index)
-                .ok_or(Error::OutOfBounds {
-                    what: WhatBound::ExecutionEnvironment,
-                    index: ee_index,
-                })?;
-
-            // Get the current EE state
-            let pre_state = shard_state
+            let ee =
+                beacon_state
+                    .execution_environments
+                    .get(ee_index)
+                    .ok_or(Error::OutOfBounds {
+                        what: WhatBound::ExecutionEnvironment,
+                        index: ee_index,
+                    })?;
+            if !ee.is_active_on(shard_index) {
+                return Err(Error::ExecutionEnvironmentInactive {
+                    ee_index: ee_index as u64,
+                    shard_index: shard_index as u64,
+                });
+            }
+            if shard_state
                 .execution_environment_states
                 .get(ee_index)
-                .ok_or(Error::OutOfBounds {
+                .is_none()
+            {
+                return Err(Error::OutOfBounds {
                     what: WhatBound::ExecutionEnvironmentState,
                     index: ee_index,
-                })?;
-
-            // Create a new runtime with the EE code, transaction data, and pre state root
-            let wasm_code: &[u8] = &*execution_environment.wasm_code;
-            let data: &[u8] = &*transaction.data;
-            let pre_state: [u8; 32] = pre_state.clone().into();
-            let post_root = RootRuntime::new(wasm_code, data, pre_state).execute();
+                });
+            }
+        }
 
-            // Update shard state with new root
-            shard_state.execution_environment_states[ee_index] = Root::from(post_root);
+        // The block must not be able to use more gas than a block is allowed, even if every
+        // transaction uses all of its gas
+        let gas_limit = shard_block
+            .body
+            .transactions
+            .iter()
+            .fold(0u64, |total, transaction| {
+                total.saturating_add(transaction.gas_limit)
+            });
+        if gas_limit > self.chain_config.max_gas_per_shard_block {
+            return Err(Error::BlockGasLimitExceeded {
+                gas_limit,
+                max: self.chain_config.max_gas_per_shard_block,
+            });
         }
 
-        // Add shard block to store for later access
-        let shard_blocks_for_shard =
-            self.store
-                .shard_blocks_by_shard
-                .get_mut(&shard)
-                .ok_or(Error::OutOfBounds {
-                    index: shard_index,
-                    what: WhatBound::Shard,
-                })?;
-        shard_blocks_for_shard.push(shard_block);
+        Ok(())
+    }
 
-        // Return the slot of the newly added shard block
-        Ok((shard_blocks_for_shard.len() - 1) as u64)
+    /// Get the hash_tree_root of the current beacon state
+    pub fn get_beacon_state_root(&self) -> Result<[u8; 32]> {
+        let beacon_state_root = self.store.current_beacon_state().tree_hash_root();
+        Ok(Root::from_slice(&beacon_state_root[..]).into())
     }
 
     /// Get an EE that was previously added
@@ -138,7 +609,7 @@ impl<T: EthSpec> Simulation<T> {
         let ee_index = a.ee_index as usize;
         let ee = self
             .store
-            .current_beacon_state
+            .current_beacon_state()
             .execution_environments
             .get(ee_index)
             .ok_or(Error::OutOfBounds {
@@ -148,22 +619,14 @@ impl<T: EthSpec> Simulation<T> {
         Ok(ee.clone().into())
     }
 
-    /// Get the current state of an execution environment on a shard
+    /// Get the state of an execution environment on a shard, either the current state or the
+    /// state after the shard block at `shard_slot_index`
     pub fn get_execution_environment_state(
         &self,
         a: simulation_args::GetExecutionEnvironmentState,
     ) -> Result<[u8; 32]> {
         let ee_index = a.ee_index as usize;
-        let shard_index = a.shard_index as usize;
-        let shard_state = self
-            .store
-            .current_beacon_state
-            .shard_states
-            .get(shard_index)
-            .ok_or(Error::OutOfBounds {
-                what: WhatBound::Shard,
-                index: shard_index,
-            })?;
+        let shard_state = self.shard_state(a.shard_index, a.shard_slot_index)?;
         let ee_state_root = shard_state
             .execution_environment_states
             .get(ee_index)
@@ -174,142 +637,732 @@ impl<T: EthSpec> Simulation<T> {
         Ok(ee_state_root.clone().into())
     }
 
+    /// Get a Merkle proof of the current state root of an EE on a shard, against the current beacon
+    /// state root
+    pub fn get_execution_environment_state_proof(
+        &self,
+        a: simulation_args::GetExecutionEnvironmentStateProof,
+    ) -> Result<simulation_args::ExecutionEnvironmentStateProof> {
+        let beacon_state = self.store.current_beacon_state();
+        let branch = proof::execution_environment_state_branch(
+            beacon_state,
+            a.shard_index as usize,
+            a.ee_index as usize,
+        )?;
+        let generalized_index =
+            simulation_args::execution_environment_state_generalized_index::<T>(
+                a.shard_index,
+                a.ee_index,
+            )
+            .ok_or(Error::OutOfBounds {
+                what: WhatBound::ExecutionEnvironmentState,
+                index: a.ee_index as usize,
+            })?;
+        let ee_state_root =
+            self.get_execution_environment_state(simulation_args::GetExecutionEnvironmentState {
+                ee_index: a.ee_index,
+                shard_index: a.shard_index,
+                shard_slot_index: None,
+            })?;
+
+        Ok(simulation_args::ExecutionEnvironmentStateProof {
+            ee_index: a.ee_index,
+            shard_index: a.shard_index,
+            ee_state_root,
+            beacon_state_root: self.get_beacon_state_root()?,
+            branch,
+            generalized_index,
+        })
+    }
+
+    /// Get the logs emitted by transactions in previously added shard blocks that match every
+    /// filter in `a`, ordered by shard, then by shard slot, then in the order they were emitted
+    pub fn get_logs(&self, a: simulation_args::GetLogs) -> Result<Vec<simulation_args::LogEntry>> {
+        let shard_indices = match a.shard_index {
+            Some(shard_index) => vec![shard_index],
+            None => (0..self.chain_config.max_shards).collect(),
+        };
+        let topics: Vec<Root> = a.topics.iter().map(|topic| Root::from(*topic)).collect();
+
+        let mut log_entries = Vec::new();
+        for shard_index in shard_indices {
+            self.check_shard(shard_index)?;
+            let shard_block_receipts = self
+                .store
+                .shard_block_receipts(Shard::new(shard_index))
+                .ok_or(Error::OutOfBounds {
+                    what: WhatBound::Shard,
+                    index: shard_index as usize,
+                })?;
+            let from = a.from_shard_slot_index.unwrap_or(0) as usize;
+            let to = a
+                .to_shard_slot_index
+                .map_or(shard_block_receipts.len(), |to| {
+                    (to.saturating_add(1) as usize).min(shard_block_receipts.len())
+                });
+            let shard_block_receipts = shard_block_receipts
+                .iter()
+                .enumerate()
+                .skip(from)
+                .take(to.saturating_sub(from));
+
+            for (shard_slot_index, shard_block_receipt) in shard_block_receipts {
+                let transaction_receipts = shard_block_receipt.transaction_receipts.iter();
+                for (transaction_index, transaction_receipt) in transaction_receipts.enumerate() {
+                    let ee_index: u64 = transaction_receipt.ee_index.into();
+                    if a.ee_index.is_some() && a.ee_index != Some(ee_index) {
+                        continue;
+                    }
+                    let logs = transaction_receipt
+                        .logs
+                        .iter()
+                        .filter(|log| log.topics.starts_with(&topics));
+                    log_entries.extend(logs.map(|log| simulation_args::LogEntry {
+                        shard_index,
+                        shard_slot_index: shard_slot_index as u64,
+                        transaction_index: transaction_index as u64,
+                        ee_index,
+                        log: log.clone().into(),
+                    }));
+                }
+            }
+        }
+        Ok(log_entries)
+    }
+
+    /// Get the number of compiled EE modules that are cached, and how often the cache was used
+    pub fn get_module_cache_stats(&self) -> simulation_args::ModuleCacheStats {
+        self.module_cache.stats()
+    }
+
+    /// Get the spec the simulation runs and the limits its chain config enforces
+    pub fn get_spec(&self) -> simulation_args::Spec {
+        simulation_args::Spec {
+            name: T::spec_name(),
+            max_execution_environments: self.chain_config.max_execution_environments,
+            max_ee_byte_code_size: self.chain_config.max_ee_byte_code_size,
+            max_transactions_per_block: self.chain_config.max_transactions_per_block,
+            max_gas_per_shard_block: self.chain_config.max_gas_per_shard_block,
+            max_shards: self.chain_config.max_shards,
+        }
+    }
+
     /// Get a shard block that was previously added
     pub fn get_shard_block(
         &self,
         a: simulation_args::GetShardBlock,
     ) -> Result<simulation_args::ShardBlock> {
+        let shard_block = self.shard_block(a.shard_index, a.shard_slot_index)?;
+        Ok(shard_block.clone().into())
+    }
+
+    /// Get the hash_tree_root of a shard block that was previously added
+    pub fn get_shard_block_root(&self, a: simulation_args::GetShardBlock) -> Result<[u8; 32]> {
+        let shard_block = self.shard_block(a.shard_index, a.shard_slot_index)?;
+        Ok(shard_block.canonical_root().into())
+    }
+
+    /// Get a shard block that was previously added by its root
+    pub fn get_shard_block_by_root(
+        &self,
+        a: simulation_args::GetShardBlockByRoot,
+    ) -> Result<simulation_args::ShardBlock> {
+        let shard_block_root = Root::from(a.shard_block_root);
+        let (shard, shard_slot) = self
+            .store
+            .shard_block_slot_by_root(&shard_block_root)
+            .ok_or(Error::UnknownShardBlockRoot {
+                root: a.shard_block_root,
+            })?;
+        self.get_shard_block(simulation_args::GetShardBlock {
+            shard_index: shard.as_u64(),
+            shard_slot_index: shard_slot.as_u64(),
+        })
+    }
+
+    /// Get the receipts for a shard block that was previously added
+    pub fn get_shard_block_receipts(
+        &self,
+        a: simulation_args::GetShardBlockReceipts,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
         let shard_index = a.shard_index as usize;
         let shard_slot_index = a.shard_slot_index as usize;
         let shard = Shard::new(a.shard_index);
-        let shard_blocks =
+        self.check_shard(a.shard_index)?;
+        let shard_block_receipts =
             self.store
-                .shard_blocks_by_shard
-                .get(&shard)
+                .shard_block_receipts(shard)
                 .ok_or(Error::OutOfBounds {
                     what: WhatBound::Shard,
                     index: shard_index,
                 })?;
-        let shard_block = shard_blocks
-            .get(shard_slot_index)
-            .ok_or(Error::OutOfBounds {
-                what: WhatBound::ShardBlock(shard_index),
-                index: shard_slot_index,
-            })?;
-        Ok(shard_block.clone().into())
+        let shard_block_receipt =
+            shard_block_receipts
+                .get(shard_slot_index)
+                .ok_or(Error::OutOfBounds {
+                    what: WhatBound::ShardBlock(shard_index),
+                    index: shard_slot_index,
+                })?;
+        Ok(shard_block_receipt.clone().into())
     }
 
     /// Get the specified ShardState, will contain EE states
+    /// Returns the current state, or the state after the shard block at `shard_slot_index`
     pub fn get_shard_state(
         &self,
         a: simulation_args::GetShardState,
     ) -> Result<simulation_args::ShardState> {
-        let shard_index = a.shard_index as usize;
-        let shard_state = self
-            .store
-            .current_beacon_state
-            .shard_states
-            .get(shard_index)
-            .ok_or(Error::OutOfBounds {
-                what: WhatBound::Shard,
-                index: shard_index,
-            })?;
+        let shard_state = self.shard_state(a.shard_index, a.shard_slot_index)?;
         Ok(shard_state.clone().into())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use hex::FromHex;
-    use typenum::Unsigned;
-    use types::eth_spec::MainnetEthSpec;
-    use types::slot_epoch_root::{EeIndex, ShardSlot};
+    /// Get the hash_tree_root of a shard state, either the current state or the state after the
+    /// shard block at `shard_slot_index`
+    pub fn get_shard_state_root(&self, a: simulation_args::GetShardState) -> Result<[u8; 32]> {
+        let shard_state = self.shard_state(a.shard_index, a.shard_slot_index)?;
+        Ok(Root::from_slice(&shard_state.tree_hash_root()[..]).into())
+    }
 
+    /// Replace the whole simulation with one saved by `save_snapshot`, which must be within the
+    /// chain config
+    ///
+    /// Nothing is changed if the snapshot can't be loaded
+    pub fn load_snapshot<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).context(SnapshotIo { path })?;
+        let store = snapshot::decode(&bytes)?;
+        check_within_chain_config(&self.chain_config, &store)?;
 
-    #[test]
-    fn simulation_new() {
-        let simulation: Simulation<MainnetEthSpec> = Simulation::new();
-        let max_shards = <MainnetEthSpec as EthSpec>::MaxShards::to_usize();
-        // Should have MaxShards shard states
-        assert_eq!(
-            simulation.store.current_beacon_state.shard_states.len(),
-            max_shards
-        );
-        // Should have no ees initially
-        assert_eq!(
-            simulation
-                .store
-                .current_beacon_state
-                .execution_environments
-                .len(),
-            0
-        );
-        // Should have no ee states initially
-        for i in 0..max_shards {
-            let shard_state = simulation
-                .store
-                .current_beacon_state
-                .shard_states
-                .get(i)
-                .unwrap();
-            assert_eq!(shard_state.execution_environment_states.len(), 0);
+        // Compile the code of every EE up front, as if they had just been created
+        for ee in store.current_beacon_state.execution_environments.iter() {
+            self.module_cache
+                .get_or_compile(&ee.wasm_code)
+                .context(InvalidExecutionEnvironmentCode)?;
         }
-        // Should have MaxShards shards, but no shard blocks
-        assert_eq!(simulation.store.shard_blocks_by_shard.len(), max_shards);
-        for i in 0..max_shards {
-            let shard_blocks_for_shard_i = simulation
-                .store
-                .shard_blocks_by_shard
-                .get(&Shard::new(i as u64))
-                .unwrap();
-            assert_eq!(shard_blocks_for_shard_i.len(), 0);
-        }
-    }
-
-    #[test]
-    fn can_create_and_get_ee() {
-        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
 
-        // Set up args::CreateExecutionEnvironment
-        let mut initial_state: [u8; 32] = [0; 32];
-        initial_state[5] = 1;
-        let example_wasm_code: &[u8] = include_bytes!("../tests/do_nothing.wasm");
-        let example_wasm_code2: &[u8] = include_bytes!("../tests/phase2_bazaar.wasm");
+        self.store_mut().replace(store)
+    }
 
-        let interface_ee = simulation_args::ExecutionEnvironment {
-            initial_state,
-            wasm_code: example_wasm_code.to_vec(),
-        };
-        let create_ee_args = simulation_args::CreateExecutionEnvironment { ee: interface_ee };
+    /// Save the whole simulation to a file, so that it can be restored with `load_snapshot`
+    ///
+    /// The file is replaced all at once, so it is left as it was if the snapshot can't be saved
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        store::write_atomically(path, &snapshot::encode(self.store.as_ref()))
+            .context(SnapshotIo { path })
+    }
 
-        let interface_ee2 = simulation_args::ExecutionEnvironment {
-            initial_state: initial_state.clone(),
-            wasm_code: example_wasm_code2.to_vec(),
-        };
-        let create_ee_args2 = simulation_args::CreateExecutionEnvironment { ee: interface_ee2 };
+    /// Run a shard block against the current shard state, returning the EE states it would lead
+    /// to along with its receipts. Nothing is stored and no state is changed.
+    pub fn simulate_shard_block(
+        &self,
+        a: simulation_args::SimulateShardBlock,
+    ) -> Result<simulation_args::SimulatedShardBlock> {
+        let shard_block: ShardBlock<T> = ShardBlock::try_from(a.shard_block).context(ArgsError)?;
+        let executed = self.execute_shard_block(a.shard_index as usize, &shard_block)?;
 
-        // Calling create_execution_environment repeatedly should return an increasing EE index
-        let ee_index = simulation
-            .create_execution_environment(create_ee_args)
-            .unwrap();
-        assert_eq!(ee_index, 0);
-        let ee_index2 = simulation
-            .create_execution_environment(create_ee_args2)
-            .unwrap();
-        assert_eq!(ee_index2, 1);
+        Ok(simulation_args::SimulatedShardBlock {
+            shard_index: a.shard_index,
+            execution_environment_states: executed
+                .execution_environment_states
+                .iter()
+                .map(|root| (*root).into())
+                .collect(),
+            gas_used: executed.gas_used,
+            transaction_receipts: with_execution_times(
+                executed
+                    .transaction_receipts
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+                &executed.execution_times_nanos,
+            ),
+        })
+    }
 
-        // Set up simulation_args::GetExecutionEnvironment
-        let get_ee_args = simulation_args::GetExecutionEnvironment { ee_index };
-        let get_ee_args2 = simulation_args::GetExecutionEnvironment {
-            ee_index: ee_index2,
+    /// Run a transaction against the current state of its EE on a shard, returning its receipt.
+    /// Nothing is stored and no state is changed.
+    pub fn simulate_transaction(
+        &self,
+        a: simulation_args::SimulateTransaction,
+    ) -> Result<simulation_args::TransactionReceipt> {
+        let shard_block = simulation_args::ShardBlock {
+            transactions: vec![a.transaction],
+            ..Default::default()
         };
+        let mut simulated = self.simulate_shard_block(simulation_args::SimulateShardBlock {
+            shard_index: a.shard_index,
+            shard_block,
+        })?;
+        Ok(simulated.transaction_receipts.remove(0))
+    }
 
-        // Make sure the retrieved EEs have the same wasm code originally passed in
-        let ee = simulation.get_execution_environment(get_ee_args).unwrap();
-        let ee2 = simulation.get_execution_environment(get_ee_args2).unwrap();
-        assert_eq!(ee.wasm_code, example_wasm_code.to_vec());
-        assert_eq!(ee2.wasm_code, example_wasm_code2.to_vec());
+    /// Look up a previously added shard block by its shard and index
+    fn shard_block(&self, shard_index: u64, shard_slot_index: u64) -> Result<&ShardBlock<T>> {
+        self.check_shard(shard_index)?;
+        let shard = Shard::new(shard_index);
+        let shard_index = shard_index as usize;
+        let shard_slot_index = shard_slot_index as usize;
+        let shard_blocks = self.store.shard_blocks(shard).ok_or(Error::OutOfBounds {
+            what: WhatBound::Shard,
+            index: shard_index,
+        })?;
+        shard_blocks
+            .get(shard_slot_index)
+            .ok_or(Error::OutOfBounds {
+                what: WhatBound::ShardBlock(shard_index),
+                index: shard_slot_index,
+            })
+    }
+
+    /// Look up the current ShardState, or the post state of a previous shard block if
+    /// `shard_slot_index` is specified
+    fn shard_state(
+        &self,
+        shard_index: u64,
+        shard_slot_index: Option<u64>,
+    ) -> Result<&ShardState<T>> {
+        self.check_shard(shard_index)?;
+        let shard = Shard::new(shard_index);
+        let shard_index = shard_index as usize;
+        match shard_slot_index {
+            None => self
+                .store
+                .current_beacon_state()
+                .shard_states
+                .get(shard_index)
+                .ok_or(Error::OutOfBounds {
+                    what: WhatBound::Shard,
+                    index: shard_index,
+                }),
+            Some(shard_slot_index) => {
+                let shard_slot_index = shard_slot_index as usize;
+                let shard_post_states =
+                    self.store
+                        .shard_post_states(shard)
+                        .ok_or(Error::OutOfBounds {
+                            what: WhatBound::Shard,
+                            index: shard_index,
+                        })?;
+                shard_post_states
+                    .get(shard_slot_index)
+                    .ok_or(Error::OutOfBounds {
+                        what: WhatBound::ShardBlock(shard_index),
+                        index: shard_slot_index,
+                    })
+            }
+        }
+    }
+
+    /// Get the store to change it, which makes any results computed from its current state stale
+    fn store_mut(&mut self) -> &mut dyn StoreBackend<T> {
+        self.revision += 1;
+        &mut *self.store
+    }
+
+    /// Check that a shard exists under the chain config, since the beacon state holds every shard
+    /// the spec allows
+    fn check_shard(&self, shard_index: u64) -> Result<()> {
+        if shard_index >= self.chain_config.max_shards {
+            return Err(Error::OutOfBounds {
+                what: WhatBound::Shard,
+                index: shard_index as usize,
+            });
+        }
+        Ok(())
+    }
+}
+
+// The calls name `Simulation` explicitly, since `self.method()` would pick the trait's `&mut self`
+// method over an inherent `&self` one and recurse
+#[async_trait]
+impl<T: EthSpec> SimulationApi for Simulation<T> {
+    type Error = Error;
+
+    async fn advance_slot(&mut self) -> Result<u64> {
+        Simulation::advance_slot(self)
+    }
+
+    async fn create_beacon_block(&mut self, a: simulation_args::CreateBeaconBlock) -> Result<u64> {
+        Simulation::create_beacon_block(self, a)
+    }
+
+    async fn create_execution_environment(
+        &mut self,
+        a: simulation_args::CreateExecutionEnvironment,
+    ) -> Result<u64> {
+        Simulation::create_execution_environment(self, a)
+    }
+
+    async fn create_shard_block(
+        &mut self,
+        a: simulation_args::CreateShardBlock,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
+        Simulation::create_shard_block(self, a)
+    }
+
+    async fn create_shard_blocks(
+        &mut self,
+        a: simulation_args::CreateShardBlocks,
+    ) -> Result<Vec<simulation_args::ShardBlockReceipt>> {
+        Simulation::create_shard_blocks(self, a)
+    }
+
+    async fn get_beacon_state_root(&mut self) -> Result<[u8; 32]> {
+        Simulation::get_beacon_state_root(self)
+    }
+
+    async fn get_execution_environment(
+        &mut self,
+        a: simulation_args::GetExecutionEnvironment,
+    ) -> Result<simulation_args::ExecutionEnvironment> {
+        Simulation::get_execution_environment(self, a)
+    }
+
+    async fn get_execution_environment_state(
+        &mut self,
+        a: simulation_args::GetExecutionEnvironmentState,
+    ) -> Result<[u8; 32]> {
+        Simulation::get_execution_environment_state(self, a)
+    }
+
+    async fn get_execution_environment_state_proof(
+        &mut self,
+        a: simulation_args::GetExecutionEnvironmentStateProof,
+    ) -> Result<simulation_args::ExecutionEnvironmentStateProof> {
+        Simulation::get_execution_environment_state_proof(self, a)
+    }
+
+    async fn get_logs(
+        &mut self,
+        a: simulation_args::GetLogs,
+    ) -> Result<Vec<simulation_args::LogEntry>> {
+        Simulation::get_logs(self, a)
+    }
+
+    async fn get_module_cache_stats(&mut self) -> Result<simulation_args::ModuleCacheStats> {
+        Ok(Simulation::get_module_cache_stats(self))
+    }
+
+    async fn get_shard_block(
+        &mut self,
+        a: simulation_args::GetShardBlock,
+    ) -> Result<simulation_args::ShardBlock> {
+        Simulation::get_shard_block(self, a)
+    }
+
+    async fn get_shard_block_by_root(
+        &mut self,
+        a: simulation_args::GetShardBlockByRoot,
+    ) -> Result<simulation_args::ShardBlock> {
+        Simulation::get_shard_block_by_root(self, a)
+    }
+
+    async fn get_shard_block_root(
+        &mut self,
+        a: simulation_args::GetShardBlock,
+    ) -> Result<[u8; 32]> {
+        Simulation::get_shard_block_root(self, a)
+    }
+
+    async fn get_shard_block_receipts(
+        &mut self,
+        a: simulation_args::GetShardBlockReceipts,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
+        Simulation::get_shard_block_receipts(self, a)
+    }
+
+    async fn get_shard_state(
+        &mut self,
+        a: simulation_args::GetShardState,
+    ) -> Result<simulation_args::ShardState> {
+        Simulation::get_shard_state(self, a)
+    }
+
+    async fn get_shard_state_root(
+        &mut self,
+        a: simulation_args::GetShardState,
+    ) -> Result<[u8; 32]> {
+        Simulation::get_shard_state_root(self, a)
+    }
+
+    async fn get_spec(&mut self) -> Result<simulation_args::Spec> {
+        Ok(Simulation::get_spec(self))
+    }
+
+    async fn load_snapshot(&mut self, a: simulation_args::LoadSnapshot) -> Result<()> {
+        Simulation::load_snapshot(self, a.path)
+    }
+
+    async fn save_snapshot(&mut self, a: simulation_args::SaveSnapshot) -> Result<()> {
+        Simulation::save_snapshot(self, a.path)
+    }
+
+    async fn simulate_shard_block(
+        &mut self,
+        a: simulation_args::SimulateShardBlock,
+    ) -> Result<simulation_args::SimulatedShardBlock> {
+        Simulation::simulate_shard_block(self, a)
+    }
+
+    async fn simulate_transaction(
+        &mut self,
+        a: simulation_args::SimulateTransaction,
+    ) -> Result<simulation_args::TransactionReceipt> {
+        Simulation::simulate_transaction(self, a)
+    }
+}
+
+/// Execute the transactions in a shard block one after another, starting from
+/// `execution_environment_states` and updating them as each transaction runs. The block must
+/// already have been validated.
+fn execute_transactions<T: EthSpec>(
+    beacon_state: &BeaconState<T>,
+    module_cache: &ModuleCache,
+    mut execution_environment_states: VariableList<Root, T::MaxExecutionEnvironments>,
+    shard_block: &ShardBlock<T>,
+) -> Result<ExecutedShardBlock<T>> {
+    let mut transaction_receipts = Vec::new();
+    let mut execution_times_nanos = Vec::new();
+    let mut gas_used = 0;
+    for transaction in shard_block.body.transactions.iter() {
+        // Get the specified EE (if it exists)
+        let ee_index: usize = transaction.ee_index.into();
+        let execution_environment =
+            beacon_state
+                .execution_environments
+                .get(ee_index)
+                .ok_or(Error::OutOfBounds {
+                    what: WhatBound::ExecutionEnvironment,
+                    index: ee_index,
+                })?;
+
+        // Get the current EE state
+        let pre_state_root =
+            *execution_environment_states
+                .get(ee_index)
+                .ok_or(Error::OutOfBounds {
+                    what: WhatBound::ExecutionEnvironmentState,
+                    index: ee_index,
+                })?;
+
+        // Run the EE code with the transaction data and pre state root
+        let wasm_code: &[u8] = &execution_environment.wasm_code;
+        let data: &[u8] = &transaction.data;
+        let start = Instant::now();
+        let outcome = match module_cache.get_or_compile(wasm_code) {
+            Ok(module) => {
+                runtime::execute(&module, data, pre_state_root.into(), transaction.gas_limit)
+            }
+            // The code was compiled when the EE was created, so this shouldn't happen, but
+            // if it does only the transaction fails
+            Err(e) => runtime::Outcome {
+                result: Err(e),
+                gas_used: 0,
+            },
+        };
+        execution_times_nanos.push(start.elapsed().as_nanos() as u64);
+        gas_used += outcome.gas_used;
+        let success = outcome.result.is_ok();
+
+        // A trapped transaction leaves the EE state as it was, and its logs are dropped since
+        // the state changes they describe never happened
+        let (post_state_root, trap_message, logs) = match outcome.result {
+            Ok(output) => (Root::from(output.post_root), Vec::new(), output.logs),
+            Err(e) => (pre_state_root, e.to_string().into_bytes(), Vec::new()),
+        };
+
+        // Update the working copy of the EE states with the new root
+        execution_environment_states[ee_index] = post_state_root;
+        transaction_receipts.push(TransactionReceipt {
+            ee_index: transaction.ee_index,
+            pre_state_root,
+            post_state_root,
+            success,
+            trap_message: VariableList::from(trap_message),
+            gas_used: outcome.gas_used,
+            // The runtime limits the number of logs, so nothing is truncated here
+            logs: VariableList::from(logs),
+        });
+    }
+
+    Ok(ExecutedShardBlock {
+        execution_environment_states,
+        transaction_receipts,
+        execution_times_nanos,
+        gas_used,
+    })
+}
+
+/// Add the time each transaction took to run to receipts of a block that was just run
+fn with_execution_times(
+    transaction_receipts: Vec<simulation_args::TransactionReceipt>,
+    execution_times_nanos: &[u64],
+) -> Vec<simulation_args::TransactionReceipt> {
+    transaction_receipts
+        .into_iter()
+        .zip(execution_times_nanos)
+        .map(
+            |(transaction_receipt, &execution_time_nanos)| simulation_args::TransactionReceipt {
+                execution_time_nanos: Some(execution_time_nanos),
+                ..transaction_receipt
+            },
+        )
+        .collect()
+}
+
+/// Turn the receipt of a block that was just run into the one returned to clients, with the time
+/// each transaction took to run
+fn receipt_with_execution_times<T: EthSpec>(
+    receipt: ShardBlockReceipt<T>,
+    execution_times_nanos: &[u64],
+) -> simulation_args::ShardBlockReceipt {
+    let mut receipt: simulation_args::ShardBlockReceipt = receipt.into();
+    receipt.transaction_receipts =
+        with_execution_times(receipt.transaction_receipts, execution_times_nanos);
+    receipt
+}
+
+/// Check that `store` holds no more EEs than `chain_config` allows, none with more code than it
+/// allows, and no blocks on shards past its `max_shards`
+fn check_within_chain_config<T: EthSpec>(
+    chain_config: &ChainConfig,
+    store: &dyn StoreBackend<T>,
+) -> Result<()> {
+    let beacon_state = store.current_beacon_state();
+    let ee_count = beacon_state.execution_environments.len() as u64;
+    if ee_count > chain_config.max_execution_environments {
+        return Err(Error::SimulationExceedsChainConfig {
+            what: "execution environments".to_string(),
+            value: ee_count,
+            max: chain_config.max_execution_environments,
+        });
+    }
+    for ee in beacon_state.execution_environments.iter() {
+        let code_size = ee.wasm_code.len() as u64;
+        if code_size > chain_config.max_ee_byte_code_size {
+            return Err(Error::SimulationExceedsChainConfig {
+                what: "bytes of execution environment code".to_string(),
+                value: code_size,
+                max: chain_config.max_ee_byte_code_size,
+            });
+        }
+    }
+    for shard_index in chain_config.max_shards..beacon_state.shard_states.len() as u64 {
+        let shard_blocks = store.shard_blocks(Shard::new(shard_index)).unwrap_or(&[]);
+        if !shard_blocks.is_empty() {
+            return Err(Error::SimulationExceedsChainConfig {
+                what: "shards".to_string(),
+                value: shard_index + 1,
+                max: chain_config.max_shards,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bazaar::{BAZAAR_DATA, BAZAAR_INITIAL_STATE, BAZAAR_POST_STATE, BAZAAR_WASM};
+    use crate::gas;
+    use hex::FromHex;
+    use typenum::Unsigned;
+    use types::eth_spec::{MainnetEthSpec, MinimalEthSpec};
+    use types::slot_epoch_root::{EeIndex, ShardSlot};
+
+    // Enough gas for any of the transactions in these tests
+    const GAS_LIMIT: u64 = 1_000_000;
+
+    #[test]
+    fn simulation_new() {
+        let simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let max_shards = <MainnetEthSpec as EthSpec>::MaxShards::to_usize();
+        // Should have MaxShards shard states
+        assert_eq!(
+            simulation.store.current_beacon_state().shard_states.len(),
+            max_shards
+        );
+        // Should have no ees initially
+        assert_eq!(
+            simulation
+                .store
+                .current_beacon_state()
+                .execution_environments
+                .len(),
+            0
+        );
+        // Should have no ee states initially
+        for i in 0..max_shards {
+            let shard_state = simulation
+                .store
+                .current_beacon_state()
+                .shard_states
+                .get(i)
+                .unwrap();
+            assert_eq!(shard_state.execution_environment_states.len(), 0);
+        }
+        // Should have MaxShards shards, but no shard blocks
+        for i in 0..max_shards {
+            let shard_blocks_for_shard_i =
+                simulation.store.shard_blocks(Shard::new(i as u64)).unwrap();
+            assert_eq!(shard_blocks_for_shard_i.len(), 0);
+        }
+        assert!(simulation
+            .store
+            .shard_blocks(Shard::new(max_shards as u64))
+            .is_none());
+    }
+
+    #[test]
+    fn can_create_and_get_ee() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+
+        // Set up args::CreateExecutionEnvironment
+        let mut initial_state: [u8; 32] = [0; 32];
+        initial_state[5] = 1;
+        let example_wasm_code: &[u8] = include_bytes!("../tests/do_nothing.wasm");
+        let example_wasm_code2: &[u8] = BAZAAR_WASM;
+
+        let interface_ee = simulation_args::ExecutionEnvironment {
+            initial_state,
+            wasm_code: example_wasm_code.to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee: interface_ee,
+            ..Default::default()
+        };
+
+        let interface_ee2 = simulation_args::ExecutionEnvironment {
+            initial_state: initial_state.clone(),
+            wasm_code: example_wasm_code2.to_vec(),
+        };
+        let create_ee_args2 = simulation_args::CreateExecutionEnvironment {
+            ee: interface_ee2,
+            ..Default::default()
+        };
+
+        // Calling create_execution_environment repeatedly should return an increasing EE index
+        let ee_index = simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+        assert_eq!(ee_index, 0);
+        let ee_index2 = simulation
+            .create_execution_environment(create_ee_args2)
+            .unwrap();
+        assert_eq!(ee_index2, 1);
+
+        // Set up simulation_args::GetExecutionEnvironment
+        let get_ee_args = simulation_args::GetExecutionEnvironment { ee_index };
+        let get_ee_args2 = simulation_args::GetExecutionEnvironment {
+            ee_index: ee_index2,
+        };
+
+        // Make sure the retrieved EEs have the same wasm code originally passed in
+        let ee = simulation.get_execution_environment(get_ee_args).unwrap();
+        let ee2 = simulation.get_execution_environment(get_ee_args2).unwrap();
+        assert_eq!(ee.wasm_code, example_wasm_code.to_vec());
+        assert_eq!(ee2.wasm_code, example_wasm_code2.to_vec());
 
         // Make sure the EEs have the correct initial_state specified for every shard
         let max_shards = <MainnetEthSpec as EthSpec>::MaxShards::to_usize();
@@ -317,6 +1370,7 @@ mod tests {
             let get_ee_state_args = simulation_args::GetExecutionEnvironmentState {
                 ee_index,
                 shard_index: i,
+                shard_slot_index: None,
             };
             let ee_state = simulation
                 .get_execution_environment_state(get_ee_state_args)
@@ -344,33 +1398,43 @@ mod tests {
             initial_state,
             wasm_code: wasm_code.to_vec(),
         };
-        let create_ee_args = simulation_args::CreateExecutionEnvironment { ee };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
         let ee_index = simulation
             .create_execution_environment(create_ee_args)
             .unwrap();
         assert_eq!(ee_index, 0);
 
         // Set up a shard transaction with the specified data
-        let shard_transaction = simulation_args::ShardTransaction { data, ee_index };
+        let shard_transaction = simulation_args::ShardTransaction {
+            data,
+            ee_index,
+            gas_limit: GAS_LIMIT,
+        };
         let shard_transaction_copy = shard_transaction.clone();
 
         // Create a shard block with the one transaction in it
         let shard_block = simulation_args::ShardBlock {
             transactions: vec![shard_transaction],
+            ..Default::default()
         };
         let create_shard_block_args = simulation_args::CreateShardBlock {
             shard_index,
             shard_block,
         };
         // This creates the block and runs all the transactions inside it
-        let shard_slot_index = simulation
+        let shard_block_receipt = simulation
             .create_shard_block(create_shard_block_args)
             .unwrap();
+        let shard_slot_index = shard_block_receipt.shard_slot_index;
 
         // Get back the EE state to make sure it matches the expected_post_state
         let get_ee_state_args = simulation_args::GetExecutionEnvironmentState {
             ee_index,
             shard_index,
+            shard_slot_index: None,
         };
         let ee_post_state = simulation
             .get_execution_environment_state(get_ee_state_args)
@@ -421,7 +1485,10 @@ mod tests {
         );
 
         // Test that GetShardState is working as expected
-        let get_shard_state_args = simulation_args::GetShardState { shard_index };
+        let get_shard_state_args = simulation_args::GetShardState {
+            shard_index,
+            shard_slot_index: None,
+        };
         let shard_state = simulation.get_shard_state(get_shard_state_args).unwrap();
         let ee_index = ee_index as usize;
         let ee_state = shard_state
@@ -434,23 +1501,1678 @@ mod tests {
     fn run_scout_bazaar_test() {
         use simulation_args::ToBytes32;
 
-        let initial_state = "22ea9b045f8792170b45ec629c98e1b92bc6a19cd8d0e9f37baaadf2564142f4";
+        let initial_state = BAZAAR_INITIAL_STATE;
         let initial_state = Vec::from_hex(initial_state).unwrap().to_bytes32().unwrap();
-        let expected_post_state =
-            "29505fd952857b5766c759bcb4af58eb8df5a91043540c1398dd987a503127fc";
+        let expected_post_state = BAZAAR_POST_STATE;
         let expected_post_state = Vec::from_hex(expected_post_state)
             .unwrap()
             .to_bytes32()
             .unwrap();
-        let data: Vec<u8> = Vec::from_hex("5c0000005000000001000000000000000000000000000000000000000000000000000000000000000000000000000000020000000000000001010101010101010101010101010101010101010101010101010101010101010400000000000000").unwrap();
+        let data: Vec<u8> = Vec::from_hex(BAZAAR_DATA).unwrap();
         let shard_index = 0;
-        let (_simulation, _shard_transaction, _shard_slot, _ee_index) =
+        let (simulation, _shard_transaction, shard_slot, ee_index) =
             test_block_with_single_transaction(
-                include_bytes!("../tests/phase2_bazaar.wasm"),
+                BAZAAR_WASM,
                 initial_state,
                 data,
                 expected_post_state,
                 shard_index,
             );
+
+        // The stored receipt should describe the state transition of the one transaction
+        let get_shard_block_receipts_args = simulation_args::GetShardBlockReceipts {
+            shard_index,
+            shard_slot_index: shard_slot.into(),
+        };
+        let shard_block_receipt = simulation
+            .get_shard_block_receipts(get_shard_block_receipts_args)
+            .unwrap();
+        assert_eq!(shard_block_receipt.shard_index, shard_index);
+        assert_eq!(shard_block_receipt.shard_slot_index, 0);
+        assert_eq!(shard_block_receipt.transaction_receipts.len(), 1);
+        let transaction_receipt = &shard_block_receipt.transaction_receipts[0];
+        assert_eq!(transaction_receipt.ee_index, ee_index.as_u64());
+        assert_eq!(transaction_receipt.pre_state_root, initial_state);
+        assert_eq!(transaction_receipt.post_state_root, expected_post_state);
+        assert!(transaction_receipt.success);
+        assert_eq!(transaction_receipt.trap_message, None);
+    }
+
+    #[test]
+    fn trapped_transaction_is_reported_and_does_not_change_state() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let initial_state = [0; 32];
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state,
+            wasm_code: include_bytes!("../tests/unreachable.wasm").to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
+        let ee_index = simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+
+        let shard_index = 0;
+        let create_shard_block_args = simulation_args::CreateShardBlock {
+            shard_index,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![simulation_args::ShardTransaction {
+                    data: Vec::new(),
+                    ee_index,
+                    gas_limit: GAS_LIMIT,
+                }],
+                ..Default::default()
+            },
+        };
+        let shard_block_receipt = simulation
+            .create_shard_block(create_shard_block_args)
+            .unwrap();
+
+        // The block is stored even though its transaction trapped
+        let transaction_receipt = &shard_block_receipt.transaction_receipts[0];
+        assert!(!transaction_receipt.success);
+        assert!(transaction_receipt
+            .trap_message
+            .as_ref()
+            .unwrap()
+            .contains("Unreachable"));
+        assert_eq!(transaction_receipt.post_state_root, initial_state);
+
+        // The post state root saved before the trap must not have been applied
+        let get_ee_state_args = simulation_args::GetExecutionEnvironmentState {
+            ee_index,
+            shard_index,
+            shard_slot_index: None,
+        };
+        let ee_state = simulation
+            .get_execution_environment_state(get_ee_state_args)
+            .unwrap();
+        assert_eq!(ee_state, initial_state);
+    }
+
+    #[test]
+    fn failed_shard_block_does_not_change_shard_state() {
+        use simulation_args::ToBytes32;
+
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+
+        // Create the bazaar EE, which changes its state root when the transaction below runs
+        let initial_state = BAZAAR_INITIAL_STATE;
+        let initial_state = Vec::from_hex(initial_state).unwrap().to_bytes32().unwrap();
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state,
+            wasm_code: BAZAAR_WASM.to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
+        let ee_index = simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+
+        // The first transaction is valid, the second refers to an EE that does not exist
+        let data: Vec<u8> = Vec::from_hex(BAZAAR_DATA).unwrap();
+        let valid_transaction = simulation_args::ShardTransaction {
+            data,
+            ee_index,
+            gas_limit: GAS_LIMIT,
+        };
+        let invalid_transaction = simulation_args::ShardTransaction {
+            data: Vec::new(),
+            ee_index: ee_index + 1,
+            gas_limit: GAS_LIMIT,
+        };
+        let shard_index = 0;
+        let create_shard_block_args = simulation_args::CreateShardBlock {
+            shard_index,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![valid_transaction, invalid_transaction],
+                ..Default::default()
+            },
+        };
+        let result = simulation.create_shard_block(create_shard_block_args);
+        match result {
+            Err(Error::OutOfBounds {
+                what: WhatBound::ExecutionEnvironment,
+                index,
+            }) => assert_eq!(index, (ee_index + 1) as usize),
+            other => panic!("expected out of bounds error, got: {:?}", other),
+        }
+
+        // The valid transaction must not have been applied
+        let get_ee_state_args = simulation_args::GetExecutionEnvironmentState {
+            ee_index,
+            shard_index,
+            shard_slot_index: None,
+        };
+        let ee_state = simulation
+            .get_execution_environment_state(get_ee_state_args)
+            .unwrap();
+        assert_eq!(ee_state, initial_state);
+
+        // The block must not have been stored
+        let get_shard_block_args = simulation_args::GetShardBlock {
+            shard_index,
+            shard_slot_index: 0,
+        };
+        assert!(simulation.get_shard_block(get_shard_block_args).is_err());
+    }
+
+    #[test]
+    fn shard_block_on_missing_shard_is_rejected() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let shard_index = <MainnetEthSpec as EthSpec>::MaxShards::to_u64();
+        let create_shard_block_args = simulation_args::CreateShardBlock {
+            shard_index,
+            shard_block: simulation_args::ShardBlock {
+                transactions: Vec::new(),
+                ..Default::default()
+            },
+        };
+        let result = simulation.create_shard_block(create_shard_block_args);
+        match result {
+            Err(Error::OutOfBounds {
+                what: WhatBound::Shard,
+                index,
+            }) => assert_eq!(index, shard_index as usize),
+            other => panic!("expected out of bounds error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn can_get_shard_state_after_previous_shard_block() {
+        use simulation_args::ToBytes32;
+
+        let initial_state = BAZAAR_INITIAL_STATE;
+        let initial_state = Vec::from_hex(initial_state).unwrap().to_bytes32().unwrap();
+        let expected_post_state = BAZAAR_POST_STATE;
+        let expected_post_state = Vec::from_hex(expected_post_state)
+            .unwrap()
+            .to_bytes32()
+            .unwrap();
+        let data: Vec<u8> = Vec::from_hex(BAZAAR_DATA).unwrap();
+        let shard_index = 0;
+        let (mut simulation, _shard_transaction, shard_slot, ee_index) =
+            test_block_with_single_transaction(
+                BAZAAR_WASM,
+                initial_state,
+                data,
+                expected_post_state,
+                shard_index,
+            );
+
+        // Add a second EE after the block, which changes the current shard state
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state,
+            wasm_code: include_bytes!("../tests/do_nothing.wasm").to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
+        simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+
+        // The post state of the block should only contain the EE that existed at the time
+        let get_shard_state_args = simulation_args::GetShardState {
+            shard_index,
+            shard_slot_index: Some(shard_slot.into()),
+        };
+        let shard_state = simulation.get_shard_state(get_shard_state_args).unwrap();
+        assert_eq!(
+            shard_state.execution_environment_states,
+            vec![expected_post_state]
+        );
+        let get_shard_state_args = simulation_args::GetShardState {
+            shard_index,
+            shard_slot_index: None,
+        };
+        let shard_state = simulation.get_shard_state(get_shard_state_args).unwrap();
+        assert_eq!(
+            shard_state.execution_environment_states,
+            vec![expected_post_state, initial_state]
+        );
+
+        // EE state can be queried at the same slot
+        let get_ee_state_args = simulation_args::GetExecutionEnvironmentState {
+            ee_index: ee_index.into(),
+            shard_index,
+            shard_slot_index: Some(shard_slot.into()),
+        };
+        let ee_state = simulation
+            .get_execution_environment_state(get_ee_state_args)
+            .unwrap();
+        assert_eq!(ee_state, expected_post_state);
+
+        // There is no shard block, and so no post state, at the next slot
+        let get_shard_state_args = simulation_args::GetShardState {
+            shard_index,
+            shard_slot_index: Some(1),
+        };
+        assert!(simulation.get_shard_state(get_shard_state_args).is_err());
+    }
+
+    #[test]
+    fn shard_blocks_are_linked_to_beacon_slots() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state: [0; 32],
+            wasm_code: include_bytes!("../tests/do_nothing.wasm").to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
+        let ee_index = simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+        let create_shard_block_args = || simulation_args::CreateShardBlock {
+            shard_index: 0,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![simulation_args::ShardTransaction {
+                    data: Vec::new(),
+                    ee_index,
+                    gas_limit: GAS_LIMIT,
+                }],
+                ..Default::default()
+            },
+        };
+
+        let receipt = simulation
+            .create_shard_block(create_shard_block_args())
+            .unwrap();
+        assert_eq!(receipt.beacon_slot, 0);
+
+        // Empty slot
+        assert_eq!(simulation.advance_slot().unwrap(), 1);
+        let receipt = simulation
+            .create_shard_block(create_shard_block_args())
+            .unwrap();
+        assert_eq!(receipt.beacon_slot, 1);
+
+        // Beacon block skipping a slot
+        let create_beacon_block_args = simulation_args::CreateBeaconBlock {
+            beacon_block: simulation_args::BeaconBlock { slot: 3 },
+        };
+        let slot = simulation
+            .create_beacon_block(create_beacon_block_args)
+            .unwrap();
+        assert_eq!(slot, 3);
+        let receipt = simulation
+            .create_shard_block(create_shard_block_args())
+            .unwrap();
+        assert_eq!(receipt.beacon_slot, 3);
+        assert_eq!(receipt.shard_slot_index, 2);
+
+        // Beacon blocks in the past or at an already filled slot are rejected
+        for slot in &[2, 3] {
+            let create_beacon_block_args = simulation_args::CreateBeaconBlock {
+                beacon_block: simulation_args::BeaconBlock { slot: *slot },
+            };
+            assert!(simulation
+                .create_beacon_block(create_beacon_block_args)
+                .is_err());
+        }
+        assert_eq!(simulation.advance_slot().unwrap(), 4);
+
+        // The beacon chain can jump straight to the last slot, but can't advance past it
+        let create_beacon_block_args = simulation_args::CreateBeaconBlock {
+            beacon_block: simulation_args::BeaconBlock { slot: u64::MAX },
+        };
+        simulation
+            .create_beacon_block(create_beacon_block_args)
+            .unwrap();
+        match simulation.advance_slot() {
+            Err(Error::SlotOverflow { slot }) => assert_eq!(slot, u64::MAX),
+            other => panic!("expected a slot overflow error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn shard_blocks_are_chained_and_can_be_found_by_root() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state: [0; 32],
+            wasm_code: include_bytes!("../tests/do_nothing.wasm").to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
+        let ee_index = simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+        let create_shard_block_args = |shard_index| simulation_args::CreateShardBlock {
+            shard_index,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![simulation_args::ShardTransaction {
+                    data: Vec::new(),
+                    ee_index,
+                    gas_limit: GAS_LIMIT,
+                }],
+                ..Default::default()
+            },
+        };
+
+        let first_receipt = simulation
+            .create_shard_block(create_shard_block_args(0))
+            .unwrap();
+        let create_beacon_block_args = simulation_args::CreateBeaconBlock {
+            beacon_block: simulation_args::BeaconBlock { slot: 1 },
+        };
+        simulation
+            .create_beacon_block(create_beacon_block_args)
+            .unwrap();
+        let second_receipt = simulation
+            .create_shard_block(create_shard_block_args(0))
+            .unwrap();
+
+        // The header fields are filled in by the simulation
+        let get_shard_block_args = simulation_args::GetShardBlockByRoot {
+            shard_block_root: first_receipt.shard_block_root,
+        };
+        let first_block = simulation
+            .get_shard_block_by_root(get_shard_block_args)
+            .unwrap();
+        assert_eq!(first_block.shard_parent_root, [0; 32]);
+        assert_eq!(first_block.beacon_parent_root, [0; 32]);
+        assert_eq!(first_block.slot, 0);
+        assert_eq!(first_block.shard, 0);
+
+        let get_shard_block_args = simulation_args::GetShardBlockByRoot {
+            shard_block_root: second_receipt.shard_block_root,
+        };
+        let second_block = simulation
+            .get_shard_block_by_root(get_shard_block_args)
+            .unwrap();
+        assert_eq!(
+            second_block.shard_parent_root,
+            first_receipt.shard_block_root
+        );
+        assert_ne!(second_block.beacon_parent_root, [0; 32]);
+        assert_eq!(second_block.slot, 1);
+
+        // Lookup by root and by index return the same block
+        let get_shard_block_args = simulation_args::GetShardBlock {
+            shard_index: 0,
+            shard_slot_index: 1,
+        };
+        let block_by_index = simulation.get_shard_block(get_shard_block_args).unwrap();
+        assert_eq!(
+            block_by_index.shard_parent_root,
+            second_block.shard_parent_root
+        );
+        assert_eq!(block_by_index.transactions, second_block.transactions);
+
+        // The same transactions on another shard produce a block with a different root
+        let other_shard_receipt = simulation
+            .create_shard_block(create_shard_block_args(1))
+            .unwrap();
+        assert_ne!(
+            other_shard_receipt.shard_block_root,
+            first_receipt.shard_block_root
+        );
+
+        let get_shard_block_args = simulation_args::GetShardBlockByRoot {
+            shard_block_root: [1; 32],
+        };
+        assert!(simulation
+            .get_shard_block_by_root(get_shard_block_args)
+            .is_err());
+    }
+
+    #[test]
+    fn roots_track_state_changes() {
+        use simulation_args::ToBytes32;
+
+        let initial_state = BAZAAR_INITIAL_STATE;
+        let initial_state = Vec::from_hex(initial_state).unwrap().to_bytes32().unwrap();
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let genesis_beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        let get_shard_state_args = |shard_index, shard_slot_index| simulation_args::GetShardState {
+            shard_index,
+            shard_slot_index,
+        };
+        let genesis_shard_state_root = simulation
+            .get_shard_state_root(get_shard_state_args(0, None))
+            .unwrap();
+
+        // Adding an EE changes every shard state, and so the beacon state
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state,
+            wasm_code: BAZAAR_WASM.to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
+        let ee_index = simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+        let ee_beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        assert_ne!(ee_beacon_state_root, genesis_beacon_state_root);
+        let ee_shard_state_root = simulation
+            .get_shard_state_root(get_shard_state_args(0, None))
+            .unwrap();
+        assert_ne!(ee_shard_state_root, genesis_shard_state_root);
+        assert_eq!(
+            ee_shard_state_root,
+            simulation
+                .get_shard_state_root(get_shard_state_args(1, None))
+                .unwrap()
+        );
+
+        // Running a block changes only the state of the shard it was run on
+        let data: Vec<u8> = Vec::from_hex(BAZAAR_DATA).unwrap();
+        let create_shard_block_args = simulation_args::CreateShardBlock {
+            shard_index: 0,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![simulation_args::ShardTransaction {
+                    data,
+                    ee_index,
+                    gas_limit: GAS_LIMIT,
+                }],
+                ..Default::default()
+            },
+        };
+        let shard_block_receipt = simulation
+            .create_shard_block(create_shard_block_args)
+            .unwrap();
+        assert_ne!(
+            simulation.get_beacon_state_root().unwrap(),
+            ee_beacon_state_root
+        );
+        let shard_state_root = simulation
+            .get_shard_state_root(get_shard_state_args(0, None))
+            .unwrap();
+        assert_ne!(shard_state_root, ee_shard_state_root);
+        assert_eq!(
+            simulation
+                .get_shard_state_root(get_shard_state_args(0, Some(0)))
+                .unwrap(),
+            shard_state_root
+        );
+        assert_eq!(
+            simulation
+                .get_shard_state_root(get_shard_state_args(1, None))
+                .unwrap(),
+            ee_shard_state_root
+        );
+
+        // The shard block root matches the root in the receipt
+        let get_shard_block_args = simulation_args::GetShardBlock {
+            shard_index: 0,
+            shard_slot_index: 0,
+        };
+        assert_eq!(
+            simulation
+                .get_shard_block_root(get_shard_block_args)
+                .unwrap(),
+            shard_block_receipt.shard_block_root
+        );
+    }
+
+    #[test]
+    fn can_prove_ee_state_against_beacon_state_root() {
+        let verify_proof =
+            simulation_args::verify_execution_environment_state_proof::<MainnetEthSpec>;
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        for initial_state in &[[1; 32], [2; 32], [3; 32]] {
+            let ee = simulation_args::ExecutionEnvironment {
+                initial_state: *initial_state,
+                wasm_code: include_bytes!("../tests/do_nothing.wasm").to_vec(),
+            };
+            let create_ee_args = simulation_args::CreateExecutionEnvironment {
+                ee,
+                ..Default::default()
+            };
+            simulation
+                .create_execution_environment(create_ee_args)
+                .unwrap();
+        }
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+
+        for (shard_index, ee_index) in &[(0, 0), (0, 2), (5, 1), (63, 2)] {
+            let get_proof_args = simulation_args::GetExecutionEnvironmentStateProof {
+                ee_index: *ee_index,
+                shard_index: *shard_index,
+            };
+            let proof = simulation
+                .get_execution_environment_state_proof(get_proof_args)
+                .unwrap();
+            assert_eq!(proof.ee_state_root, [*ee_index as u8 + 1; 32]);
+            assert_eq!(proof.beacon_state_root, beacon_state_root);
+            assert!(verify_proof(&proof, &beacon_state_root));
+
+            // A proof for a different EE state root, or claiming to be for another EE, fails
+            let mut bad_proof = proof.clone();
+            bad_proof.ee_state_root = [9; 32];
+            assert!(!verify_proof(&bad_proof, &beacon_state_root));
+            let mut bad_proof = proof.clone();
+            bad_proof.ee_index = (*ee_index + 1) % 3;
+            assert!(!verify_proof(&bad_proof, &beacon_state_root));
+        }
+
+        // EEs that don't exist can't be proven
+        let get_proof_args = simulation_args::GetExecutionEnvironmentStateProof {
+            ee_index: 3,
+            shard_index: 0,
+        };
+        assert!(simulation
+            .get_execution_environment_state_proof(get_proof_args)
+            .is_err());
+    }
+
+    #[test]
+    fn ee_state_proofs_follow_the_spec() {
+        let mut simulation: Simulation<MinimalEthSpec> = Simulation::new();
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state: [1; 32],
+            wasm_code: include_bytes!("../tests/do_nothing.wasm").to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
+        simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+
+        let max_shards = <MinimalEthSpec as EthSpec>::MaxShards::to_u64();
+        let get_proof_args = simulation_args::GetExecutionEnvironmentStateProof {
+            ee_index: 0,
+            shard_index: max_shards - 1,
+        };
+        let proof = simulation
+            .get_execution_environment_state_proof(get_proof_args)
+            .unwrap();
+        assert!(simulation_args::verify_execution_environment_state_proof::<
+            MinimalEthSpec,
+        >(&proof, &beacon_state_root));
+        // The proof doesn't fit the shape of a mainnet beacon state
+        assert!(
+            !simulation_args::verify_execution_environment_state_proof::<MainnetEthSpec>(
+                &proof,
+                &beacon_state_root
+            )
+        );
+
+        let get_proof_args = simulation_args::GetExecutionEnvironmentStateProof {
+            ee_index: 0,
+            shard_index: max_shards,
+        };
+        assert!(simulation
+            .get_execution_environment_state_proof(get_proof_args)
+            .is_err());
+    }
+
+    #[test]
+    fn logs_are_recorded_and_can_be_queried() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        // The EE logs its transaction data, with its pre state root as the only topic, then saves
+        // the first 32 bytes of the data as its post state root
+        let wasm_code = include_bytes!("../tests/log.wasm");
+        let mut create_ee = |initial_state| {
+            let ee = simulation_args::ExecutionEnvironment {
+                initial_state,
+                wasm_code: wasm_code.to_vec(),
+            };
+            let create_ee_args = simulation_args::CreateExecutionEnvironment {
+                ee,
+                ..Default::default()
+            };
+            simulation
+                .create_execution_environment(create_ee_args)
+                .unwrap()
+        };
+        let ee_index = create_ee([0; 32]);
+        let other_ee_index = create_ee([9; 32]);
+
+        let mut create_shard_block = |shard_index, transactions: Vec<(u64, Vec<u8>)>| {
+            let transactions = transactions
+                .into_iter()
+                .map(|(ee_index, data)| simulation_args::ShardTransaction {
+                    data,
+                    ee_index,
+                    gas_limit: GAS_LIMIT,
+                })
+                .collect();
+            let create_shard_block_args = simulation_args::CreateShardBlock {
+                shard_index,
+                shard_block: simulation_args::ShardBlock {
+                    transactions,
+                    ..Default::default()
+                },
+            };
+            simulation
+                .create_shard_block(create_shard_block_args)
+                .unwrap()
+        };
+        let shard_block_receipt = create_shard_block(0, vec![(ee_index, vec![1; 32])]);
+        create_shard_block(
+            0,
+            vec![(ee_index, vec![2; 32]), (other_ee_index, vec![3; 32])],
+        );
+        create_shard_block(1, vec![(other_ee_index, vec![4; 32])]);
+        // Logging more data than a log can hold traps, and the transaction emits no logs
+        let failed_receipt = create_shard_block(1, vec![(ee_index, vec![5; 5000])]);
+
+        // Logs are part of the transaction receipts
+        let log = simulation_args::Log {
+            topics: vec![[0; 32]],
+            data: vec![1; 32],
+        };
+        assert_eq!(shard_block_receipt.transaction_receipts[0].logs, vec![log]);
+        let failed_transaction_receipt = &failed_receipt.transaction_receipts[0];
+        assert!(!failed_transaction_receipt.success);
+        assert!(failed_transaction_receipt
+            .trap_message
+            .as_ref()
+            .unwrap()
+            .contains("log data is 5000 bytes"));
+        assert!(failed_transaction_receipt.logs.is_empty());
+
+        let get_logs = |a| -> Vec<(u64, u64, u64, u64, Vec<u8>)> {
+            simulation
+                .get_logs(a)
+                .unwrap()
+                .into_iter()
+                .map(|entry| {
+                    (
+                        entry.shard_index,
+                        entry.shard_slot_index,
+                        entry.transaction_index,
+                        entry.ee_index,
+                        entry.log.data,
+                    )
+                })
+                .collect()
+        };
+
+        // With no filters, every log is returned in order
+        let all_logs = get_logs(simulation_args::GetLogs::default());
+        assert_eq!(
+            all_logs,
+            vec![
+                (0, 0, 0, ee_index, vec![1; 32]),
+                (0, 1, 0, ee_index, vec![2; 32]),
+                (0, 1, 1, other_ee_index, vec![3; 32]),
+                (1, 0, 0, other_ee_index, vec![4; 32]),
+            ]
+        );
+
+        // Filter by shard, EE, slot range and topic
+        let shard_logs = get_logs(simulation_args::GetLogs {
+            shard_index: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(shard_logs, all_logs[3..].to_vec());
+        let ee_logs = get_logs(simulation_args::GetLogs {
+            ee_index: Some(other_ee_index),
+            ..Default::default()
+        });
+        assert_eq!(ee_logs, all_logs[2..].to_vec());
+        let slot_logs = get_logs(simulation_args::GetLogs {
+            from_shard_slot_index: Some(1),
+            to_shard_slot_index: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(slot_logs, all_logs[1..3].to_vec());
+        let open_ended_logs = get_logs(simulation_args::GetLogs {
+            from_shard_slot_index: Some(1),
+            to_shard_slot_index: Some(u64::MAX),
+            ..Default::default()
+        });
+        assert_eq!(open_ended_logs, all_logs[1..3].to_vec());
+        let topic_logs = get_logs(simulation_args::GetLogs {
+            topics: vec![[1; 32]],
+            ..Default::default()
+        });
+        assert_eq!(topic_logs, all_logs[1..2].to_vec());
+
+        // Shards must exist
+        let get_logs_args = simulation_args::GetLogs {
+            shard_index: Some(<MainnetEthSpec as EthSpec>::MaxShards::to_u64()),
+            ..Default::default()
+        };
+        assert!(simulation.get_logs(get_logs_args).is_err());
+    }
+
+    #[test]
+    fn transaction_that_runs_out_of_gas_is_stopped_and_reverted() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let mut create_ee = |wasm_code: &[u8]| {
+            let ee = simulation_args::ExecutionEnvironment {
+                initial_state: [0; 32],
+                wasm_code: wasm_code.to_vec(),
+            };
+            let create_ee_args = simulation_args::CreateExecutionEnvironment {
+                ee,
+                ..Default::default()
+            };
+            simulation
+                .create_execution_environment(create_ee_args)
+                .unwrap()
+        };
+        // The first EE loops forever, the second saves its transaction data as its post state
+        let loop_ee_index = create_ee(include_bytes!("../tests/loop.wasm"));
+        let log_ee_index = create_ee(include_bytes!("../tests/log.wasm"));
+
+        let shard_index = 0;
+        let gas_limit = 1000;
+        let create_shard_block_args = simulation_args::CreateShardBlock {
+            shard_index,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![
+                    simulation_args::ShardTransaction {
+                        data: Vec::new(),
+                        ee_index: loop_ee_index,
+                        gas_limit,
+                    },
+                    simulation_args::ShardTransaction {
+                        data: vec![1; 32],
+                        ee_index: log_ee_index,
+                        gas_limit,
+                    },
+                ],
+                ..Default::default()
+            },
+        };
+        let shard_block_receipt = simulation
+            .create_shard_block(create_shard_block_args)
+            .unwrap();
+
+        // The looping transaction uses all of its gas and fails, without affecting the next one
+        let loop_receipt = &shard_block_receipt.transaction_receipts[0];
+        assert!(!loop_receipt.success);
+        assert!(loop_receipt
+            .trap_message
+            .as_ref()
+            .unwrap()
+            .contains("out of gas"));
+        assert_eq!(loop_receipt.gas_used, gas_limit);
+        assert_eq!(loop_receipt.post_state_root, [0; 32]);
+        let log_receipt = &shard_block_receipt.transaction_receipts[1];
+        assert!(log_receipt.success);
+        assert!(log_receipt.gas_used > 0 && log_receipt.gas_used < gas_limit);
+        assert_eq!(log_receipt.post_state_root, [1; 32]);
+        assert_eq!(
+            shard_block_receipt.gas_used,
+            loop_receipt.gas_used + log_receipt.gas_used
+        );
+
+        // Gas is deterministic, the same transaction always uses the same amount
+        let create_shard_block_args = simulation_args::CreateShardBlock {
+            shard_index,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![simulation_args::ShardTransaction {
+                    data: vec![1; 32],
+                    ee_index: log_ee_index,
+                    gas_limit,
+                }],
+                ..Default::default()
+            },
+        };
+        let shard_block_receipt = simulation
+            .create_shard_block(create_shard_block_args)
+            .unwrap();
+        assert_eq!(
+            shard_block_receipt.transaction_receipts[0].gas_used,
+            log_receipt.gas_used
+        );
+
+        // The looping EE's state was never changed
+        let get_ee_state_args = simulation_args::GetExecutionEnvironmentState {
+            ee_index: loop_ee_index,
+            shard_index,
+            shard_slot_index: None,
+        };
+        let ee_state = simulation
+            .get_execution_environment_state(get_ee_state_args)
+            .unwrap();
+        assert_eq!(ee_state, [0; 32]);
+    }
+
+    #[test]
+    fn memory_growth_is_charged_per_page() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        // The EE grows its memory by 16 pages, and does nothing else
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state: [0; 32],
+            wasm_code: include_bytes!("../tests/grow_memory.wasm").to_vec(),
+        };
+        let ee_index = simulation
+            .create_execution_environment(simulation_args::CreateExecutionEnvironment {
+                ee,
+                ..Default::default()
+            })
+            .unwrap();
+        let pages_cost = 16 * gas::MEMORY_GROW_COST_PER_PAGE;
+        let create_shard_block_args = |gas_limit| simulation_args::CreateShardBlock {
+            shard_index: 0,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![simulation_args::ShardTransaction {
+                    data: Vec::new(),
+                    ee_index,
+                    gas_limit,
+                }],
+                ..Default::default()
+            },
+        };
+
+        let receipt = simulation
+            .create_shard_block(create_shard_block_args(GAS_LIMIT))
+            .unwrap();
+        let transaction_receipt = &receipt.transaction_receipts[0];
+        assert!(transaction_receipt.success);
+        assert!(transaction_receipt.gas_used > pages_cost);
+
+        // Enough gas for every instruction, but not for the pages
+        let receipt = simulation
+            .create_shard_block(create_shard_block_args(pages_cost))
+            .unwrap();
+        let transaction_receipt = &receipt.transaction_receipts[0];
+        assert!(!transaction_receipt.success);
+        assert!(transaction_receipt
+            .trap_message
+            .as_ref()
+            .unwrap()
+            .contains("out of gas"));
+    }
+
+    #[test]
+    fn shard_block_over_block_gas_limit_is_rejected() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state: [0; 32],
+            wasm_code: include_bytes!("../tests/do_nothing.wasm").to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
+        let ee_index = simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+
+        // Each transaction is within the block gas limit, but together they are over it
+        let max_gas = <MainnetEthSpec as EthSpec>::MaxGasPerShardBlock::to_u64();
+        let transaction = simulation_args::ShardTransaction {
+            data: Vec::new(),
+            ee_index,
+            gas_limit: max_gas / 2 + 1,
+        };
+        let shard_index = 0;
+        let create_shard_block_args = simulation_args::CreateShardBlock {
+            shard_index,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![transaction.clone(), transaction],
+                ..Default::default()
+            },
+        };
+        match simulation.create_shard_block(create_shard_block_args) {
+            Err(Error::BlockGasLimitExceeded { gas_limit, max }) => {
+                assert_eq!(gas_limit, max_gas + 2);
+                assert_eq!(max, max_gas);
+            }
+            other => panic!("expected block gas limit error, got: {:?}", other),
+        }
+
+        // The block must not have been stored
+        let get_shard_block_args = simulation_args::GetShardBlock {
+            shard_index,
+            shard_slot_index: 0,
+        };
+        assert!(simulation.get_shard_block(get_shard_block_args).is_err());
+    }
+
+    #[test]
+    fn ee_can_start_from_different_states_and_be_active_on_some_shards() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let mut shard_initial_states = BTreeMap::new();
+        shard_initial_states.insert(2, [2; 32]);
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee: simulation_args::ExecutionEnvironment {
+                initial_state: [1; 32],
+                wasm_code: include_bytes!("../tests/do_nothing.wasm").to_vec(),
+            },
+            shard_initial_states,
+            active_shards: Some(vec![0, 2]),
+        };
+        let ee_index = simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+
+        let ee_state = |simulation: &Simulation<MainnetEthSpec>, shard_index| {
+            simulation
+                .get_execution_environment_state(simulation_args::GetExecutionEnvironmentState {
+                    ee_index,
+                    shard_index,
+                    shard_slot_index: None,
+                })
+                .unwrap()
+        };
+        assert_eq!(ee_state(&simulation, 0), [1; 32]);
+        assert_eq!(ee_state(&simulation, 1), [1; 32]);
+        assert_eq!(ee_state(&simulation, 2), [2; 32]);
+
+        let create_shard_block_args = |shard_index| simulation_args::CreateShardBlock {
+            shard_index,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![simulation_args::ShardTransaction {
+                    data: Vec::new(),
+                    ee_index,
+                    gas_limit: 1_000_000,
+                }],
+                ..Default::default()
+            },
+        };
+        simulation
+            .create_shard_block(create_shard_block_args(0))
+            .unwrap();
+        simulation
+            .create_shard_block(create_shard_block_args(2))
+            .unwrap();
+        match simulation.create_shard_block(create_shard_block_args(1)) {
+            Err(Error::ExecutionEnvironmentInactive {
+                ee_index: 0,
+                shard_index: 1,
+            }) => {}
+            other => panic!(
+                "expected inactive execution environment error, got: {:?}",
+                other
+            ),
+        }
+        let get_shard_block_args = simulation_args::GetShardBlock {
+            shard_index: 1,
+            shard_slot_index: 0,
+        };
+        assert!(simulation.get_shard_block(get_shard_block_args).is_err());
+
+        // Shards that don't exist are rejected
+        let max_shards = <MainnetEthSpec as EthSpec>::MaxShards::to_u64();
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee: simulation_args::ExecutionEnvironment {
+                initial_state: [1; 32],
+                wasm_code: include_bytes!("../tests/do_nothing.wasm").to_vec(),
+            },
+            active_shards: Some(vec![max_shards]),
+            ..Default::default()
+        };
+        match simulation.create_execution_environment(create_ee_args) {
+            Err(Error::OutOfBounds {
+                what: WhatBound::Shard,
+                index,
+            }) => assert_eq!(index as u64, max_shards),
+            other => panic!("expected out of bounds error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn chain_config_limits_are_enforced() {
+        let chain_config = ChainConfig {
+            max_shards: 2,
+            max_execution_environments: 1,
+            max_ee_byte_code_size: 1024,
+            max_transactions_per_block: 1,
+            max_gas_per_shard_block: 100_000,
+        };
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new()
+            .with_chain_config(chain_config.clone())
+            .unwrap();
+        let spec = simulation.get_spec();
+        assert_eq!(spec.max_shards, 2);
+        assert_eq!(spec.max_transactions_per_block, 1);
+
+        let create_ee_args = |wasm_code: &[u8]| simulation_args::CreateExecutionEnvironment {
+            ee: simulation_args::ExecutionEnvironment {
+                initial_state: [0; 32],
+                wasm_code: wasm_code.to_vec(),
+            },
+            ..Default::default()
+        };
+        match simulation.create_execution_environment(create_ee_args(BAZAAR_WASM)) {
+            Err(Error::MaxLengthExceeded { .. }) => {}
+            other => panic!("expected max length error, got: {:?}", other),
+        }
+        let ee_index = simulation
+            .create_execution_environment(create_ee_args(include_bytes!(
+                "../tests/do_nothing.wasm"
+            )))
+            .unwrap();
+        match simulation.create_execution_environment(create_ee_args(include_bytes!(
+            "../tests/do_nothing.wasm"
+        ))) {
+            Err(Error::MaxLengthExceeded { .. }) => {}
+            other => panic!("expected max length error, got: {:?}", other),
+        }
+
+        let transaction = simulation_args::ShardTransaction {
+            data: Vec::new(),
+            ee_index,
+            gas_limit: 100_000,
+        };
+        let create_shard_block_args =
+            |shard_index, transactions| simulation_args::CreateShardBlock {
+                shard_index,
+                shard_block: simulation_args::ShardBlock {
+                    transactions,
+                    ..Default::default()
+                },
+            };
+        simulation
+            .create_shard_block(create_shard_block_args(1, vec![transaction.clone()]))
+            .unwrap();
+        match simulation.create_shard_block(create_shard_block_args(2, vec![transaction.clone()])) {
+            Err(Error::OutOfBounds {
+                what: WhatBound::Shard,
+                index: 2,
+            }) => {}
+            other => panic!("expected out of bounds error, got: {:?}", other),
+        }
+        match simulation.create_shard_block(create_shard_block_args(
+            0,
+            vec![transaction.clone(), transaction.clone()],
+        )) {
+            Err(Error::MaxLengthExceeded { .. }) => {}
+            other => panic!("expected max length error, got: {:?}", other),
+        }
+        let over_gas_limit = simulation_args::ShardTransaction {
+            gas_limit: 100_001,
+            ..transaction
+        };
+        match simulation.create_shard_block(create_shard_block_args(0, vec![over_gas_limit])) {
+            Err(Error::BlockGasLimitExceeded { max: 100_000, .. }) => {}
+            other => panic!("expected block gas limit error, got: {:?}", other),
+        }
+
+        // Shards beyond the chain config can't be queried either
+        let get_shard_state_args = simulation_args::GetShardState {
+            shard_index: 2,
+            shard_slot_index: None,
+        };
+        assert!(simulation.get_shard_state(get_shard_state_args).is_err());
+
+        // The chain config can't raise the spec's limits
+        let chain_config = ChainConfig {
+            max_shards: <MainnetEthSpec as EthSpec>::MaxShards::to_u64() + 1,
+            ..chain_config
+        };
+        match Simulation::<MainnetEthSpec>::new().with_chain_config(chain_config) {
+            Err(Error::ChainConfigExceedsSpec { .. }) => {}
+            other => panic!("expected chain config error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn chain_config_must_fit_the_simulation() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let wasm_code = include_bytes!("../tests/do_nothing.wasm").to_vec();
+        let code_size = wasm_code.len() as u64;
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee: simulation_args::ExecutionEnvironment {
+                initial_state: [0; 32],
+                wasm_code,
+            },
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            simulation
+                .create_execution_environment(create_ee_args.clone())
+                .unwrap();
+        }
+        let create_shard_block_args = simulation_args::CreateShardBlock {
+            shard_index: 3,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![simulation_args::ShardTransaction {
+                    data: Vec::new(),
+                    ee_index: 0,
+                    gas_limit: GAS_LIMIT,
+                }],
+                ..Default::default()
+            },
+        };
+        simulation
+            .create_shard_block(create_shard_block_args)
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("simulation.ssz");
+        simulation.save_snapshot(&path).unwrap();
+
+        let spec = ChainConfig::of::<MainnetEthSpec>();
+        let too_few_ees = ChainConfig {
+            max_execution_environments: 1,
+            ..spec.clone()
+        };
+        let too_little_code = ChainConfig {
+            max_ee_byte_code_size: code_size - 1,
+            ..spec.clone()
+        };
+        let too_few_shards = ChainConfig {
+            max_shards: 3,
+            ..spec
+        };
+        for chain_config in &[&too_few_ees, &too_little_code, &too_few_shards] {
+            let mut restored = Simulation::<MainnetEthSpec>::new()
+                .with_chain_config((*chain_config).clone())
+                .unwrap();
+            match restored.load_snapshot(&path) {
+                Err(Error::SimulationExceedsChainConfig { .. }) => {}
+                other => panic!("expected chain config error, got: {:?}", other),
+            }
+        }
+
+        match simulation.with_chain_config(too_few_ees) {
+            Err(Error::SimulationExceedsChainConfig { what, value, max }) => {
+                assert_eq!(
+                    (what.as_str(), value, max),
+                    ("execution environments", 2, 1)
+                );
+            }
+            other => panic!("expected chain config error, got: {:?}", other),
+        }
+        let mut restored: Simulation<MainnetEthSpec> = Simulation::new();
+        restored.load_snapshot(&path).unwrap();
+        match restored.with_chain_config(too_little_code) {
+            Err(Error::SimulationExceedsChainConfig { what, value, max }) => {
+                assert_eq!(
+                    (what.as_str(), value, max),
+                    (
+                        "bytes of execution environment code",
+                        code_size,
+                        code_size - 1
+                    )
+                );
+            }
+            other => panic!("expected chain config error, got: {:?}", other),
+        }
+        let mut restored: Simulation<MainnetEthSpec> = Simulation::new();
+        restored.load_snapshot(&path).unwrap();
+        match restored.with_chain_config(too_few_shards) {
+            Err(Error::SimulationExceedsChainConfig { what, value, max }) => {
+                assert_eq!((what.as_str(), value, max), ("shards", 4, 3));
+            }
+            other => panic!("expected chain config error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_ee_code_is_rejected() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let invalid_code: Vec<(&[u8], &str)> = vec![
+            (b"not wasm", "unable to parse"),
+            (include_bytes!("../tests/float.wasm"), "floating point"),
+            (
+                include_bytes!("../tests/unknown_import.wasm"),
+                "eth2_unknown",
+            ),
+            (include_bytes!("../tests/no_main.wasm"), "no 'main' export"),
+        ];
+
+        for (wasm_code, expected_message) in invalid_code {
+            let ee = simulation_args::ExecutionEnvironment {
+                initial_state: [0; 32],
+                wasm_code: wasm_code.to_vec(),
+            };
+            let create_ee_args = simulation_args::CreateExecutionEnvironment {
+                ee,
+                ..Default::default()
+            };
+            match simulation.create_execution_environment(create_ee_args) {
+                Err(e @ Error::InvalidExecutionEnvironmentCode { .. }) => {
+                    let message = e.to_string();
+                    assert!(
+                        message.contains(expected_message),
+                        "unexpected error: {}",
+                        message
+                    );
+                }
+                other => panic!("expected invalid code error, got: {:?}", other),
+            }
+        }
+
+        // None of the EEs were created
+        let get_ee_args = simulation_args::GetExecutionEnvironment { ee_index: 0 };
+        assert!(simulation.get_execution_environment(get_ee_args).is_err());
+    }
+
+    #[test]
+    fn compiled_ee_code_is_cached_by_code_hash() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let mut create_ee = |wasm_code: &[u8]| {
+            let ee = simulation_args::ExecutionEnvironment {
+                initial_state: [0; 32],
+                wasm_code: wasm_code.to_vec(),
+            };
+            let create_ee_args = simulation_args::CreateExecutionEnvironment {
+                ee,
+                ..Default::default()
+            };
+            simulation
+                .create_execution_environment(create_ee_args)
+                .unwrap()
+        };
+        // The second EE shares its code with the first, so it reuses the same module
+        let ee_index = create_ee(include_bytes!("../tests/do_nothing.wasm"));
+        let ee_index2 = create_ee(include_bytes!("../tests/do_nothing.wasm"));
+        create_ee(BAZAAR_WASM);
+        let expected_stats = simulation_args::ModuleCacheStats {
+            hits: 1,
+            misses: 2,
+            modules: 2,
+        };
+        assert_eq!(simulation.get_module_cache_stats(), expected_stats);
+
+        // Every transaction uses the module compiled when its EE was created
+        let transactions = [ee_index, ee_index2, ee_index]
+            .iter()
+            .map(|&ee_index| simulation_args::ShardTransaction {
+                data: Vec::new(),
+                ee_index,
+                gas_limit: GAS_LIMIT,
+            })
+            .collect();
+        let create_shard_block_args = simulation_args::CreateShardBlock {
+            shard_index: 0,
+            shard_block: simulation_args::ShardBlock {
+                transactions,
+                ..Default::default()
+            },
+        };
+        let receipt = simulation
+            .create_shard_block(create_shard_block_args)
+            .unwrap();
+        assert!(receipt
+            .transaction_receipts
+            .iter()
+            .all(|transaction_receipt| transaction_receipt.success));
+        let expected_stats = simulation_args::ModuleCacheStats {
+            hits: 4,
+            misses: 2,
+            modules: 2,
+        };
+        assert_eq!(simulation.get_module_cache_stats(), expected_stats);
+    }
+
+    #[test]
+    fn simulation_can_be_saved_and_restored_from_a_snapshot() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state: [0; 32],
+            wasm_code: include_bytes!("../tests/log.wasm").to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
+        let ee_index = simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap();
+        let create_beacon_block_args = simulation_args::CreateBeaconBlock {
+            beacon_block: simulation_args::BeaconBlock { slot: 2 },
+        };
+        simulation
+            .create_beacon_block(create_beacon_block_args)
+            .unwrap();
+        let create_shard_block = |data: u8| simulation_args::CreateShardBlock {
+            shard_index: 1,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![simulation_args::ShardTransaction {
+                    data: vec![data; 32],
+                    ee_index,
+                    gas_limit: GAS_LIMIT,
+                }],
+                ..Default::default()
+            },
+        };
+        simulation
+            .create_shard_block(create_shard_block(1))
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("simulation.ssz");
+        simulation.save_snapshot(&path).unwrap();
+        // A snapshot replaces the file all at once, and one that can't be saved leaves nothing
+        // behind
+        simulation.save_snapshot(&path).unwrap();
+        fs::create_dir(dir.path().join("taken")).unwrap();
+        match simulation.save_snapshot(dir.path().join("taken")) {
+            Err(Error::SnapshotIo { .. }) => {}
+            other => panic!("expected snapshot io error, got: {:?}", other),
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        let mut restored: Simulation<MainnetEthSpec> = Simulation::new();
+        restored.load_snapshot(&path).unwrap();
+
+        // Everything that was saved can be read back
+        assert_eq!(
+            restored.get_beacon_state_root().unwrap(),
+            simulation.get_beacon_state_root().unwrap()
+        );
+        let get_shard_block_args = simulation_args::GetShardBlock {
+            shard_index: 1,
+            shard_slot_index: 0,
+        };
+        let shard_block_root = simulation
+            .get_shard_block_root(get_shard_block_args)
+            .unwrap();
+        let get_shard_block_by_root_args =
+            simulation_args::GetShardBlockByRoot { shard_block_root };
+        assert!(restored
+            .get_shard_block_by_root(get_shard_block_by_root_args)
+            .is_ok());
+        let get_shard_block_receipts_args = || simulation_args::GetShardBlockReceipts {
+            shard_index: 1,
+            shard_slot_index: 0,
+        };
+        assert_eq!(
+            restored
+                .get_shard_block_receipts(get_shard_block_receipts_args())
+                .unwrap(),
+            simulation
+                .get_shard_block_receipts(get_shard_block_receipts_args())
+                .unwrap()
+        );
+        assert_eq!(
+            restored.get_logs(Default::default()).unwrap(),
+            simulation.get_logs(Default::default()).unwrap()
+        );
+
+        // The EE code was compiled when the snapshot was loaded
+        assert_eq!(restored.get_module_cache_stats().modules, 1);
+
+        // Both simulations carry on from the same place
+        let receipt = simulation
+            .create_shard_block(create_shard_block(2))
+            .unwrap();
+        let restored_receipt = restored.create_shard_block(create_shard_block(2)).unwrap();
+        assert_eq!(restored_receipt.shard_block_root, receipt.shard_block_root);
+        assert_eq!(
+            restored.get_beacon_state_root().unwrap(),
+            simulation.get_beacon_state_root().unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_snapshot_is_rejected() {
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let state_root = simulation.get_beacon_state_root().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("simulation.ssz");
+
+        match simulation.load_snapshot(&path) {
+            Err(Error::SnapshotIo { .. }) => {}
+            other => panic!("expected snapshot io error, got: {:?}", other),
+        }
+
+        // A snapshot from a later version of the simulation
+        let mut bytes = snapshot::encode(&MemoryStore::<MainnetEthSpec>::new());
+        bytes[..8].copy_from_slice(&(snapshot::SNAPSHOT_VERSION + 1).to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        match simulation.load_snapshot(&path) {
+            Err(Error::SnapshotVersionMismatch { version, expected }) => {
+                assert_eq!(version, snapshot::SNAPSHOT_VERSION + 1);
+                assert_eq!(expected, snapshot::SNAPSHOT_VERSION);
+            }
+            other => panic!("expected snapshot version error, got: {:?}", other),
+        }
+
+        // A truncated snapshot
+        let bytes = snapshot::encode(&MemoryStore::<MainnetEthSpec>::new());
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        match simulation.load_snapshot(&path) {
+            Err(Error::InvalidSnapshot { .. }) => {}
+            other => panic!("expected invalid snapshot error, got: {:?}", other),
+        }
+
+        // A snapshot whose shards are inconsistent
+        let mut missing_receipt = MemoryStore::<MainnetEthSpec>::new();
+        missing_receipt
+            .shard_post_states_by_shard
+            .insert(Shard::new(0), vec![ShardState::new()]);
+        let mut missing_ee_state = MemoryStore::<MainnetEthSpec>::new();
+        missing_ee_state.current_beacon_state.shard_states[0]
+            .execution_environment_states
+            .push(Root::default())
+            .unwrap();
+        for store in &[missing_receipt, missing_ee_state] {
+            fs::write(&path, snapshot::encode(store)).unwrap();
+            match simulation.load_snapshot(&path) {
+                Err(Error::InvalidSnapshot { .. }) => {}
+                other => panic!("expected invalid snapshot error, got: {:?}", other),
+            }
+        }
+
+        // The simulation is left as it was
+        assert_eq!(simulation.get_beacon_state_root().unwrap(), state_root);
+    }
+
+    #[test]
+    fn simulated_blocks_and_transactions_do_not_change_state() {
+        use simulation_args::ToBytes32;
+
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let initial_state = BAZAAR_INITIAL_STATE;
+        let initial_state = Vec::from_hex(initial_state).unwrap().to_bytes32().unwrap();
+        let expected_post_state = BAZAAR_POST_STATE;
+        let expected_post_state = Vec::from_hex(expected_post_state)
+            .unwrap()
+            .to_bytes32()
+            .unwrap();
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state,
+            wasm_code: BAZAAR_WASM.to_vec(),
+        };
+        let ee_index = simulation
+            .create_execution_environment(simulation_args::CreateExecutionEnvironment {
+                ee,
+                ..Default::default()
+            })
+            .unwrap();
+        let transaction = simulation_args::ShardTransaction {
+            data: Vec::from_hex(BAZAAR_DATA).unwrap(),
+            ee_index,
+            gas_limit: GAS_LIMIT,
+        };
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        let shard_index = 2;
+
+        let receipt = simulation
+            .simulate_transaction(simulation_args::SimulateTransaction {
+                shard_index,
+                transaction: transaction.clone(),
+            })
+            .unwrap();
+        assert!(receipt.success);
+        assert_eq!(receipt.pre_state_root, initial_state);
+        assert_eq!(receipt.post_state_root, expected_post_state);
+
+        let shard_block = simulation_args::ShardBlock {
+            transactions: vec![transaction],
+            ..Default::default()
+        };
+        let simulated = simulation
+            .simulate_shard_block(simulation_args::SimulateShardBlock {
+                shard_index,
+                shard_block: shard_block.clone(),
+            })
+            .unwrap();
+        assert_eq!(simulated.shard_index, shard_index);
+        assert_eq!(
+            simulated.execution_environment_states,
+            vec![expected_post_state]
+        );
+        assert_eq!(simulated.gas_used, receipt.gas_used);
+        assert_eq!(simulated.transaction_receipts.len(), 1);
+
+        // Nothing was stored, and the EE is still in its initial state
+        assert_eq!(
+            simulation.get_beacon_state_root().unwrap(),
+            beacon_state_root
+        );
+        assert!(simulation
+            .store
+            .shard_blocks(Shard::new(shard_index))
+            .unwrap()
+            .is_empty());
+        let get_ee_state_args = simulation_args::GetExecutionEnvironmentState {
+            ee_index,
+            shard_index,
+            shard_slot_index: None,
+        };
+        assert_eq!(
+            simulation
+                .get_execution_environment_state(get_ee_state_args)
+                .unwrap(),
+            initial_state
+        );
+
+        // Invalid blocks are rejected just like when they are created
+        let invalid_transaction = simulation_args::ShardTransaction {
+            data: Vec::new(),
+            ee_index: ee_index + 1,
+            gas_limit: GAS_LIMIT,
+        };
+        assert!(simulation
+            .simulate_transaction(simulation_args::SimulateTransaction {
+                shard_index,
+                transaction: invalid_transaction,
+            })
+            .is_err());
+
+        // Creating the block leads to the same state that was simulated
+        simulation
+            .create_shard_block(simulation_args::CreateShardBlock {
+                shard_index,
+                shard_block,
+            })
+            .unwrap();
+        let get_ee_state_args = simulation_args::GetExecutionEnvironmentState {
+            ee_index,
+            shard_index,
+            shard_slot_index: None,
+        };
+        assert_eq!(
+            simulation
+                .get_execution_environment_state(get_ee_state_args)
+                .unwrap(),
+            expected_post_state
+        );
+    }
+
+    #[test]
+    fn shard_blocks_created_together_match_blocks_created_one_at_a_time() {
+        fn new_simulation() -> Simulation<MainnetEthSpec> {
+            let mut simulation = Simulation::new();
+            let ee = simulation_args::ExecutionEnvironment {
+                initial_state: [0; 32],
+                wasm_code: include_bytes!("../tests/log.wasm").to_vec(),
+            };
+            simulation
+                .create_execution_environment(simulation_args::CreateExecutionEnvironment {
+                    ee,
+                    ..Default::default()
+                })
+                .unwrap();
+            simulation
+        }
+        // `log.wasm` sets the EE state root to the transaction data
+        fn block(shard_index: u64, ee_index: u64, data: u8) -> simulation_args::CreateShardBlock {
+            simulation_args::CreateShardBlock {
+                shard_index,
+                shard_block: simulation_args::ShardBlock {
+                    transactions: vec![simulation_args::ShardTransaction {
+                        data: vec![data; 32],
+                        ee_index,
+                        gas_limit: GAS_LIMIT,
+                    }],
+                    ..Default::default()
+                },
+            }
+        }
+        // Execution times are the only part of a receipt that can differ between runs
+        fn without_timings(
+            mut receipt: simulation_args::ShardBlockReceipt,
+        ) -> simulation_args::ShardBlockReceipt {
+            for transaction_receipt in receipt.transaction_receipts.iter_mut() {
+                transaction_receipt.execution_time_nanos = None;
+            }
+            receipt
+        }
+
+        let shard_blocks = vec![
+            block(0, 0, 1),
+            block(5, 0, 2),
+            block(0, 0, 3),
+            block(63, 0, 4),
+            block(5, 0, 5),
+        ];
+        let mut together = new_simulation();
+        let receipts = together
+            .create_shard_blocks(simulation_args::CreateShardBlocks {
+                shard_blocks: shard_blocks.clone(),
+            })
+            .unwrap();
+        assert_eq!(receipts.len(), shard_blocks.len());
+
+        let mut one_at_a_time = new_simulation();
+        for (shard_block, receipt) in shard_blocks.into_iter().zip(receipts) {
+            let expected = one_at_a_time.create_shard_block(shard_block).unwrap();
+            assert_eq!(without_timings(receipt), without_timings(expected));
+        }
+        assert_eq!(
+            together.get_beacon_state_root().unwrap(),
+            one_at_a_time.get_beacon_state_root().unwrap()
+        );
+        for shard_index in &[0, 5, 63] {
+            let a = || simulation_args::GetShardState {
+                shard_index: *shard_index,
+                shard_slot_index: None,
+            };
+            assert_eq!(
+                together.get_shard_state_root(a()).unwrap(),
+                one_at_a_time.get_shard_state_root(a()).unwrap()
+            );
+        }
+        let a = simulation_args::GetExecutionEnvironmentState {
+            ee_index: 0,
+            shard_index: 0,
+            shard_slot_index: None,
+        };
+        assert_eq!(
+            together.get_execution_environment_state(a).unwrap(),
+            [3; 32]
+        );
+
+        // A single invalid block stops all of them from being stored
+        let beacon_state_root = together.get_beacon_state_root().unwrap();
+        let shard_blocks = vec![block(1, 0, 6), block(2, 1, 7)];
+        assert!(together
+            .create_shard_blocks(simulation_args::CreateShardBlocks { shard_blocks })
+            .is_err());
+        assert_eq!(together.get_beacon_state_root().unwrap(), beacon_state_root);
+        assert!(together
+            .store
+            .shard_blocks(Shard::new(1))
+            .unwrap()
+            .is_empty());
+
+        // Blocks executed before the simulation changed can't be stored
+        let executed = together
+            .execute_shard_blocks(simulation_args::CreateShardBlocks {
+                shard_blocks: vec![block(1, 0, 8)],
+            })
+            .unwrap();
+        together.create_shard_block(block(1, 0, 9)).unwrap();
+        match together.commit_shard_blocks(executed) {
+            Err(Error::StaleShardBlocks) => {}
+            other => panic!("expected a stale shard blocks error, got {:?}", other),
+        }
+        assert_eq!(together.store.shard_blocks(Shard::new(1)).unwrap().len(), 1);
     }
 }
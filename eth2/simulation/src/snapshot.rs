@@ -0,0 +1,167 @@
+//! Saving and restoring everything in the store as a single SSZ-encoded snapshot.
+//!
+//! A snapshot is a `Snapshot` container. It starts with a version number, which is bumped whenever
+//! the layout of the container changes, so the version can always be read from the first 8 bytes
+//! even if the rest of the snapshot can't be decoded. It also names the spec it was created
+//! under, since the same bytes can't be decoded under another spec.
+//!
+//! The `HashMap`s in `MemoryStore` are stored as lists indexed by shard, and the index of shard blocks
+//! by root is rebuilt from the shard blocks rather than stored.
+use crate::store::{MemoryStore, StoreBackend};
+use crate::{Error, Result};
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use std::collections::HashMap;
+use typenum::Unsigned;
+use types::beacon_block::BeaconBlock;
+use types::beacon_state::BeaconState;
+use types::eth_spec::EthSpec;
+use types::shard_block::ShardBlock;
+use types::shard_block_receipt::ShardBlockReceipt;
+use types::shard_state::ShardState;
+use types::slot_epoch_root::{Shard, ShardSlot};
+
+/// The version of the snapshot layout written by this version of the simulation
+pub const SNAPSHOT_VERSION: u64 = 1;
+
+#[derive(DeriveDecode, DeriveEncode)]
+struct Snapshot<T>
+where
+    T: EthSpec,
+{
+    // Must stay the first field, see the module documentation
+    version: u64,
+    // The name of the spec, as returned by `EthSpec::spec_name`
+    spec: Vec<u8>,
+    current_beacon_state: BeaconState<T>,
+    beacon_blocks: Vec<BeaconBlock>,
+    // Each of these has one entry per shard, in shard order
+    shard_blocks: Vec<Vec<ShardBlock<T>>>,
+    shard_block_receipts: Vec<Vec<ShardBlockReceipt<T>>>,
+    shard_post_states: Vec<Vec<ShardState<T>>>,
+}
+
+/// Encode everything in `store` as a snapshot
+pub fn encode<T: EthSpec>(store: &dyn StoreBackend<T>) -> Vec<u8> {
+    let shards = || (0..T::MaxShards::to_u64()).map(Shard::new);
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        spec: T::spec_name().to_string().into_bytes(),
+        current_beacon_state: store.current_beacon_state().clone(),
+        beacon_blocks: store.beacon_blocks().to_vec(),
+        shard_blocks: shards()
+            .map(|shard| store.shard_blocks(shard).unwrap_or(&[]).to_vec())
+            .collect(),
+        shard_block_receipts: shards()
+            .map(|shard| store.shard_block_receipts(shard).unwrap_or(&[]).to_vec())
+            .collect(),
+        shard_post_states: shards()
+            .map(|shard| store.shard_post_states(shard).unwrap_or(&[]).to_vec())
+            .collect(),
+    };
+    snapshot.as_ssz_bytes()
+}
+
+/// Decode a snapshot created by `encode`, checking that it was created under the same spec
+pub fn decode<T: EthSpec>(bytes: &[u8]) -> Result<MemoryStore<T>> {
+    let version = bytes
+        .get(..8)
+        .and_then(|version| u64::from_ssz_bytes(version).ok())
+        .ok_or(Error::InvalidSnapshot {
+            reason: "snapshot is too short to contain a version".to_string(),
+        })?;
+    if version != SNAPSHOT_VERSION {
+        return Err(Error::SnapshotVersionMismatch {
+            version,
+            expected: SNAPSHOT_VERSION,
+        });
+    }
+
+    let snapshot = Snapshot::<T>::from_ssz_bytes(bytes).map_err(|e| Error::InvalidSnapshot {
+        reason: format!("unable to decode snapshot: {:?}", e),
+    })?;
+    let spec = String::from_utf8_lossy(&snapshot.spec).to_string();
+    let expected_spec = T::spec_name().to_string();
+    if spec != expected_spec {
+        return Err(Error::SnapshotSpecMismatch {
+            spec,
+            expected: expected_spec,
+        });
+    }
+
+    let shard_count = T::MaxShards::to_usize();
+    if snapshot.shard_blocks.len() != shard_count
+        || snapshot.shard_block_receipts.len() != shard_count
+        || snapshot.shard_post_states.len() != shard_count
+    {
+        return Err(Error::InvalidSnapshot {
+            reason: format!(
+                "snapshot must have an entry for each of the {} shards",
+                shard_count
+            ),
+        });
+    }
+
+    for (shard, shard_blocks) in snapshot.shard_blocks.iter().enumerate() {
+        if snapshot.shard_block_receipts[shard].len() != shard_blocks.len()
+            || snapshot.shard_post_states[shard].len() != shard_blocks.len()
+        {
+            return Err(Error::InvalidSnapshot {
+                reason: format!(
+                    "shard {} must have a receipt and a post state for each of its {} blocks",
+                    shard,
+                    shard_blocks.len()
+                ),
+            });
+        }
+    }
+    let beacon_state = &snapshot.current_beacon_state;
+    if beacon_state.shard_states.len() != shard_count {
+        return Err(Error::InvalidSnapshot {
+            reason: format!(
+                "beacon state must have a state for each of the {} shards",
+                shard_count
+            ),
+        });
+    }
+    let ee_count = beacon_state.execution_environments.len();
+    for (shard, shard_state) in beacon_state.shard_states.iter().enumerate() {
+        if shard_state.execution_environment_states.len() != ee_count {
+            return Err(Error::InvalidSnapshot {
+                reason: format!(
+                    "shard {} must have a state for each of the {} execution environments",
+                    shard, ee_count
+                ),
+            });
+        }
+    }
+
+    let shard_blocks_by_shard = by_shard(snapshot.shard_blocks);
+    let mut shard_block_slots_by_root = HashMap::new();
+    for (shard, shard_blocks) in shard_blocks_by_shard.iter() {
+        for (shard_slot, shard_block) in shard_blocks.iter().enumerate() {
+            shard_block_slots_by_root.insert(
+                shard_block.canonical_root(),
+                (*shard, ShardSlot::new(shard_slot as u64)),
+            );
+        }
+    }
+
+    Ok(MemoryStore {
+        current_beacon_state: snapshot.current_beacon_state,
+        beacon_blocks: snapshot.beacon_blocks,
+        shard_blocks_by_shard,
+        shard_block_slots_by_root,
+        shard_block_receipts_by_shard: by_shard(snapshot.shard_block_receipts),
+        shard_post_states_by_shard: by_shard(snapshot.shard_post_states),
+    })
+}
+
+/// Key lists that are in shard order by their shard
+fn by_shard<V>(lists: Vec<Vec<V>>) -> HashMap<Shard, Vec<V>> {
+    lists
+        .into_iter()
+        .enumerate()
+        .map(|(shard, list)| (Shard::new(shard as u64), list))
+        .collect()
+}
@@ -0,0 +1,698 @@
+//! A `StoreBackend` that persists everything to a directory, so that it survives a restart.
+//!
+//! Reads are served from a `MemoryStore`, which is loaded from the directory when the store is
+//! opened. Writes go to disk before they are applied in memory:
+//!
+//! - The current beacon state is in `beacon_state.ssz`. It is replaced by writing a new file and
+//!   renaming it over the old one, so the file is always complete.
+//! - Beacon blocks and shard blocks are appended to `beacon_blocks.<generation>.ssz` and
+//!   `shard_blocks.<generation>.ssz`. Each record is its length as a little-endian `u32`, followed
+//!   by its SSZ encoding. A beacon block record holds one block, while a shard block record holds
+//!   all the shard blocks that were added at once, each with its receipt and post state.
+//!
+//! Adding a shard block only appends to the shard blocks file, rather than rewriting the whole
+//! beacon state. So the beacon state file records how many shard blocks it already includes, and
+//! the post states of any later ones are applied to it when the store is opened. A record left
+//! incomplete by a crash is discarded, along with all the shard blocks in it.
+//!
+//! Adding a beacon block appends it and then rewrites the beacon state file, which records how
+//! many beacon blocks go with it. A block appended by a process that stopped before the beacon
+//! state it led to was saved is discarded.
+//!
+//! Replacing the whole store writes the block files of a new generation next to the current ones,
+//! and only then the beacon state file, which records the generation it goes with. So the store
+//! switches to the new files all at once, when the beacon state file is renamed into place.
+use super::{write_atomically, MemoryStore, ShardBlockRecord, StoreBackend};
+use crate::{Error, Result, StoreIo, WhatBound};
+use snafu::ResultExt;
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use typenum::Unsigned;
+use types::beacon_block::BeaconBlock;
+use types::beacon_state::BeaconState;
+use types::eth_spec::EthSpec;
+use types::shard_block::ShardBlock;
+use types::shard_block_receipt::ShardBlockReceipt;
+use types::shard_state::ShardState;
+use types::slot_epoch_root::{Root, Shard, ShardSlot};
+
+const BEACON_STATE_FILE: &str = "beacon_state.ssz";
+const BEACON_BLOCKS_FILE: &str = "beacon_blocks";
+const SHARD_BLOCKS_FILE: &str = "shard_blocks";
+
+/// The version of the layout of the files in the directory, bumped whenever it changes
+const VERSION: u64 = 1;
+
+#[derive(DeriveDecode, DeriveEncode)]
+struct BeaconStateFile<T>
+where
+    T: EthSpec,
+{
+    // Must stay the first field, so that it can be read even if the rest of the file can't
+    version: u64,
+    // The name of the spec, as returned by `EthSpec::spec_name`
+    spec: Vec<u8>,
+    // The generation of the block files that go with the beacon state
+    generation: u64,
+    // The number of records at the start of the beacon blocks file that go with `beacon_state`
+    beacon_block_count: u64,
+    // The number of shard blocks at the start of the shard blocks file that `beacon_state` includes
+    shard_block_count: u64,
+    beacon_state: BeaconState<T>,
+}
+
+#[derive(Debug)]
+pub struct DiskStore<T>
+where
+    T: EthSpec,
+{
+    dir: PathBuf,
+    memory: MemoryStore<T>,
+    beacon_blocks_file: File,
+    shard_blocks_file: File,
+    // The generation of the block files in use
+    generation: u64,
+    // The number of blocks in each of the block files
+    beacon_block_count: u64,
+    shard_block_count: u64,
+}
+
+impl<T: EthSpec> DiskStore<T> {
+    /// Open the store in `dir`, creating the directory and an empty store if they don't exist
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).context(StoreIo { path: &dir })?;
+
+        let mut memory = MemoryStore::new();
+        let state_path = dir.join(BEACON_STATE_FILE);
+        let file = if state_path.exists() {
+            let bytes = fs::read(&state_path).context(StoreIo { path: &state_path })?;
+            decode_beacon_state_file::<T>(&state_path, &bytes)?
+        } else {
+            let file = beacon_state_file(&memory.current_beacon_state, 0, 0, 0);
+            write_atomically(&state_path, &file.as_ssz_bytes())
+                .context(StoreIo { path: &state_path })?;
+            file
+        };
+        let BeaconStateFile {
+            generation,
+            beacon_block_count,
+            shard_block_count: included_shard_block_count,
+            beacon_state,
+            ..
+        } = file;
+        // Left behind if the process stopped part way through replacing the store, either before
+        // the new generation was switched to or before the old one was removed
+        remove_block_files(&dir, generation + 1);
+        if generation > 0 {
+            remove_block_files(&dir, generation - 1);
+        }
+
+        let path = block_file_path(&dir, BEACON_BLOCKS_FILE, generation);
+        let (beacon_blocks_file, mut records) = open_log(&path)?;
+        if beacon_block_count > records.len() as u64 {
+            return Err(Error::InvalidStore {
+                path,
+                reason: format!(
+                    "the beacon state goes with {} beacon blocks, but only {} were found",
+                    beacon_block_count,
+                    records.len()
+                ),
+            });
+        }
+        // Blocks past the ones the beacon state goes with were appended by a process that stopped
+        // before it saved the beacon state they led to
+        if records.len() as u64 > beacon_block_count {
+            records.truncate(beacon_block_count as usize);
+            let length = records.iter().map(|record| 4 + record.len() as u64).sum();
+            beacon_blocks_file
+                .set_len(length)
+                .context(StoreIo { path: &path })?;
+        }
+        for record in records {
+            memory.beacon_blocks.push(decode_record(&path, &record)?);
+        }
+
+        let path = block_file_path(&dir, SHARD_BLOCKS_FILE, generation);
+        let (shard_blocks_file, batches) = open_log(&path)?;
+        let mut records = Vec::new();
+        for batch in batches {
+            let batch: Vec<ShardBlockRecord<T>> = decode_record(&path, &batch)?;
+            records.extend(batch);
+        }
+        let shard_block_count = records.len() as u64;
+        if included_shard_block_count > shard_block_count {
+            return Err(Error::InvalidStore {
+                path,
+                reason: format!(
+                    "the beacon state includes {} shard blocks, but only {} were found",
+                    included_shard_block_count, shard_block_count
+                ),
+            });
+        }
+        // The beacon state file is newer than the shard blocks it includes, so it replaces the
+        // shard states they set, and only later shard blocks are applied to it
+        let newer_records = records.split_off(included_shard_block_count as usize);
+        memory.put_shard_blocks(records)?;
+        memory.current_beacon_state = beacon_state;
+        memory.put_shard_blocks(newer_records)?;
+
+        Ok(Self {
+            dir,
+            memory,
+            beacon_blocks_file,
+            shard_blocks_file,
+            generation,
+            beacon_block_count,
+            shard_block_count,
+        })
+    }
+
+    /// Replace the beacon state file with `state` and the number of records in the block files
+    fn write_beacon_state(&self, state: &BeaconState<T>) -> Result<()> {
+        let file = beacon_state_file(
+            state,
+            self.generation,
+            self.beacon_block_count,
+            self.shard_block_count,
+        );
+        let path = self.dir.join(BEACON_STATE_FILE);
+        write_atomically(&path, &file.as_ssz_bytes()).context(StoreIo { path })
+    }
+}
+
+impl<T: EthSpec> StoreBackend<T> for DiskStore<T> {
+    fn current_beacon_state(&self) -> &BeaconState<T> {
+        self.memory.current_beacon_state()
+    }
+
+    fn beacon_blocks(&self) -> &[BeaconBlock] {
+        self.memory.beacon_blocks()
+    }
+
+    fn shard_blocks(&self, shard: Shard) -> Option<&[ShardBlock<T>]> {
+        self.memory.shard_blocks(shard)
+    }
+
+    fn shard_block_receipts(&self, shard: Shard) -> Option<&[ShardBlockReceipt<T>]> {
+        self.memory.shard_block_receipts(shard)
+    }
+
+    fn shard_post_states(&self, shard: Shard) -> Option<&[ShardState<T>]> {
+        self.memory.shard_post_states(shard)
+    }
+
+    fn shard_block_slot_by_root(&self, root: &Root) -> Option<(Shard, ShardSlot)> {
+        self.memory.shard_block_slot_by_root(root)
+    }
+
+    fn put_beacon_state(&mut self, state: BeaconState<T>) -> Result<()> {
+        self.write_beacon_state(&state)?;
+        self.memory.put_beacon_state(state)
+    }
+
+    fn put_beacon_block(&mut self, block: BeaconBlock, state: BeaconState<T>) -> Result<()> {
+        let path = block_file_path(&self.dir, BEACON_BLOCKS_FILE, self.generation);
+        let previous_length = append(&mut self.beacon_blocks_file, &path, &block.as_ssz_bytes())?;
+        self.beacon_block_count += 1;
+        if let Err(e) = self.write_beacon_state(&state) {
+            // Best effort, if this fails too the block is discarded when the store is opened
+            let _ = self.beacon_blocks_file.set_len(previous_length);
+            self.beacon_block_count -= 1;
+            return Err(e);
+        }
+        self.memory.put_beacon_block(block, state)
+    }
+
+    /// Appends all the blocks as a single record, so that they are either all kept or all
+    /// discarded if the process stops part way through.
+    fn put_shard_blocks(&mut self, records: Vec<ShardBlockRecord<T>>) -> Result<()> {
+        for record in records.iter() {
+            let shard_index: usize = record.block.shard.into();
+            if shard_index >= T::MaxShards::to_usize() {
+                return Err(Error::OutOfBounds {
+                    what: WhatBound::Shard,
+                    index: shard_index,
+                });
+            }
+        }
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let path = block_file_path(&self.dir, SHARD_BLOCKS_FILE, self.generation);
+        append(&mut self.shard_blocks_file, &path, &records.as_ssz_bytes())?;
+        self.shard_block_count += records.len() as u64;
+        self.memory.put_shard_blocks(records)
+    }
+
+    /// Writes the block files of a new generation, then switches to them by rewriting the beacon
+    /// state file. If the process stops part way through, the store opens with either the old or
+    /// the new contents.
+    fn replace(&mut self, store: MemoryStore<T>) -> Result<()> {
+        let mut beacon_blocks = Vec::new();
+        for block in store.beacon_blocks.iter() {
+            push_record(&mut beacon_blocks, &block.as_ssz_bytes());
+        }
+
+        let mut records = Vec::new();
+        for shard in 0..T::MaxShards::to_u64() {
+            let shard = Shard::new(shard);
+            let blocks = store.shard_blocks(shard).unwrap_or(&[]);
+            let receipts = store.shard_block_receipts(shard).unwrap_or(&[]);
+            let post_states = store.shard_post_states(shard).unwrap_or(&[]);
+            for ((block, receipt), post_state) in blocks.iter().zip(receipts).zip(post_states) {
+                records.push(ShardBlockRecord {
+                    block: block.clone(),
+                    receipt: receipt.clone(),
+                    post_state: post_state.clone(),
+                });
+            }
+        }
+        let mut shard_blocks = Vec::new();
+        if !records.is_empty() {
+            push_record(&mut shard_blocks, &records.as_ssz_bytes());
+        }
+        let shard_block_count = records.len() as u64;
+
+        let generation = self.generation + 1;
+        let beacon_block_count = store.beacon_blocks.len() as u64;
+        let beacon_blocks_path = block_file_path(&self.dir, BEACON_BLOCKS_FILE, generation);
+        write_atomically(&beacon_blocks_path, &beacon_blocks).context(StoreIo {
+            path: &beacon_blocks_path,
+        })?;
+        let shard_blocks_path = block_file_path(&self.dir, SHARD_BLOCKS_FILE, generation);
+        write_atomically(&shard_blocks_path, &shard_blocks).context(StoreIo {
+            path: &shard_blocks_path,
+        })?;
+        let file = beacon_state_file(
+            &store.current_beacon_state,
+            generation,
+            beacon_block_count,
+            shard_block_count,
+        );
+        let state_path = self.dir.join(BEACON_STATE_FILE);
+        write_atomically(&state_path, &file.as_ssz_bytes())
+            .context(StoreIo { path: &state_path })?;
+
+        self.beacon_blocks_file = open_log(&beacon_blocks_path)?.0;
+        self.shard_blocks_file = open_log(&shard_blocks_path)?.0;
+        remove_block_files(&self.dir, self.generation);
+        self.generation = generation;
+        self.beacon_block_count = beacon_block_count;
+        self.shard_block_count = shard_block_count;
+        self.memory.replace(store)
+    }
+}
+
+fn decode_beacon_state_file<T: EthSpec>(path: &Path, bytes: &[u8]) -> Result<BeaconStateFile<T>> {
+    let invalid = |reason: String| Error::InvalidStore {
+        path: path.to_path_buf(),
+        reason,
+    };
+
+    let version = bytes
+        .get(..8)
+        .and_then(|version| u64::from_ssz_bytes(version).ok())
+        .ok_or_else(|| invalid("file is too short to contain a version".to_string()))?;
+    if version != VERSION {
+        return Err(invalid(format!(
+            "version {} is not supported, expected version {}",
+            version, VERSION
+        )));
+    }
+
+    let file = BeaconStateFile::<T>::from_ssz_bytes(bytes)
+        .map_err(|e| invalid(format!("unable to decode beacon state: {:?}", e)))?;
+    let spec = String::from_utf8_lossy(&file.spec).to_string();
+    if spec != T::spec_name().to_string() {
+        return Err(invalid(format!(
+            "store was created with the {} spec, but the simulation uses the {} spec",
+            spec,
+            T::spec_name()
+        )));
+    }
+    Ok(file)
+}
+
+fn beacon_state_file<T: EthSpec>(
+    state: &BeaconState<T>,
+    generation: u64,
+    beacon_block_count: u64,
+    shard_block_count: u64,
+) -> BeaconStateFile<T> {
+    BeaconStateFile {
+        version: VERSION,
+        spec: T::spec_name().to_string().into_bytes(),
+        generation,
+        beacon_block_count,
+        shard_block_count,
+        beacon_state: state.clone(),
+    }
+}
+
+fn block_file_path(dir: &Path, name: &str, generation: u64) -> PathBuf {
+    dir.join(format!("{}.{}.ssz", name, generation))
+}
+
+/// Remove the block files of a generation that is no longer used, if they exist
+fn remove_block_files(dir: &Path, generation: u64) {
+    for name in &[BEACON_BLOCKS_FILE, SHARD_BLOCKS_FILE] {
+        // Best effort, files that are left behind are only unused
+        let _ = fs::remove_file(block_file_path(dir, name, generation));
+    }
+}
+
+/// Open an append-only file, creating it if it doesn't exist, and read the records in it. An
+/// incomplete record at the end of the file is removed.
+fn open_log(path: &Path) -> Result<(File, Vec<Vec<u8>>)> {
+    let file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .context(StoreIo { path })?;
+    let bytes = fs::read(path).context(StoreIo { path })?;
+
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while let Some(length_bytes) = bytes.get(offset..offset + 4) {
+        let mut length = [0; 4];
+        length.copy_from_slice(length_bytes);
+        let start = offset + 4;
+        let end = start + u32::from_le_bytes(length) as usize;
+        match bytes.get(start..end) {
+            Some(record) => records.push(record.to_vec()),
+            None => break,
+        }
+        offset = end;
+    }
+    if offset < bytes.len() {
+        file.set_len(offset as u64).context(StoreIo { path })?;
+    }
+
+    Ok((file, records))
+}
+
+fn decode_record<V: Decode>(path: &Path, record: &[u8]) -> Result<V> {
+    V::from_ssz_bytes(record).map_err(|e| Error::InvalidStore {
+        path: path.to_path_buf(),
+        reason: format!("unable to decode record: {:?}", e),
+    })
+}
+
+fn push_record(log: &mut Vec<u8>, record: &[u8]) {
+    log.extend_from_slice(&(record.len() as u32).to_le_bytes());
+    log.extend_from_slice(record);
+}
+
+/// Append a record to the end of an append-only file, returning the length of the file before
+/// it was appended. The file is left unchanged if the record can't be written.
+fn append(file: &mut File, path: &Path, record: &[u8]) -> Result<u64> {
+    let previous_length = file.metadata().context(StoreIo { path })?.len();
+    let mut bytes = Vec::with_capacity(record.len() + 4);
+    push_record(&mut bytes, record);
+
+    if let Err(e) = file.write_all(&bytes).and_then(|_| file.sync_data()) {
+        let _ = file.set_len(previous_length);
+        return Err(e).context(StoreIo { path });
+    }
+    Ok(previous_length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Simulation;
+    use types::eth_spec::MainnetEthSpec;
+    use types::slot_epoch_root::Slot;
+
+    fn create_ee(simulation: &mut Simulation<MainnetEthSpec>) -> u64 {
+        let ee = simulation_args::ExecutionEnvironment {
+            initial_state: [0; 32],
+            wasm_code: include_bytes!("../../tests/log.wasm").to_vec(),
+        };
+        let create_ee_args = simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        };
+        simulation
+            .create_execution_environment(create_ee_args)
+            .unwrap()
+    }
+
+    fn create_shard_block(simulation: &mut Simulation<MainnetEthSpec>, ee_index: u64, data: u8) {
+        let create_shard_block_args = simulation_args::CreateShardBlock {
+            shard_index: 0,
+            shard_block: simulation_args::ShardBlock {
+                transactions: vec![simulation_args::ShardTransaction {
+                    data: vec![data; 32],
+                    ee_index,
+                    gas_limit: 1_000_000,
+                }],
+                ..Default::default()
+            },
+        };
+        simulation
+            .create_shard_block(create_shard_block_args)
+            .unwrap();
+    }
+
+    fn open(dir: &Path) -> Simulation<MainnetEthSpec> {
+        Simulation::with_store(DiskStore::open(dir).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn simulation_is_restored_when_store_is_reopened() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut simulation = open(dir.path());
+        let ee_index = create_ee(&mut simulation);
+        create_shard_block(&mut simulation, ee_index, 1);
+        let create_beacon_block_args = simulation_args::CreateBeaconBlock {
+            beacon_block: simulation_args::BeaconBlock { slot: 1 },
+        };
+        simulation
+            .create_beacon_block(create_beacon_block_args)
+            .unwrap();
+        simulation.advance_slot().unwrap();
+        // Creating an EE rewrites the beacon state after a shard block was appended, and the
+        // shard block after it is only appended
+        let ee_index2 = create_ee(&mut simulation);
+        create_shard_block(&mut simulation, ee_index2, 2);
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        let logs = simulation.get_logs(Default::default()).unwrap();
+        drop(simulation);
+
+        let mut simulation = open(dir.path());
+        assert_eq!(
+            simulation.get_beacon_state_root().unwrap(),
+            beacon_state_root
+        );
+        assert_eq!(simulation.get_logs(Default::default()).unwrap(), logs);
+        let get_shard_block_args = simulation_args::GetShardBlock {
+            shard_index: 0,
+            shard_slot_index: 1,
+        };
+        let shard_block_root = simulation
+            .get_shard_block_root(get_shard_block_args)
+            .unwrap();
+        let get_shard_block_by_root_args =
+            simulation_args::GetShardBlockByRoot { shard_block_root };
+        assert!(simulation
+            .get_shard_block_by_root(get_shard_block_by_root_args)
+            .is_ok());
+
+        // The reopened store can be written to, and is restored again
+        create_shard_block(&mut simulation, ee_index, 3);
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        drop(simulation);
+        let simulation = open(dir.path());
+        assert_eq!(
+            simulation.get_beacon_state_root().unwrap(),
+            beacon_state_root
+        );
+    }
+
+    #[test]
+    fn incomplete_record_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut simulation = open(dir.path());
+        let ee_index = create_ee(&mut simulation);
+        create_shard_block(&mut simulation, ee_index, 1);
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        drop(simulation);
+
+        // As if the process stopped part way through appending a record
+        let path = block_file_path(dir.path(), SHARD_BLOCKS_FILE, 0);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[100, 0, 0, 0, 1, 2, 3]).unwrap();
+        drop(file);
+
+        let mut simulation = open(dir.path());
+        assert_eq!(
+            simulation.get_beacon_state_root().unwrap(),
+            beacon_state_root
+        );
+        create_shard_block(&mut simulation, ee_index, 2);
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        drop(simulation);
+        let simulation = open(dir.path());
+        assert_eq!(
+            simulation.get_beacon_state_root().unwrap(),
+            beacon_state_root
+        );
+    }
+
+    #[test]
+    fn shard_blocks_added_together_are_kept_or_discarded_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut simulation = open(dir.path());
+        let ee_index = create_ee(&mut simulation);
+        let create_shard_blocks_args = |data: u8| simulation_args::CreateShardBlocks {
+            shard_blocks: [0, 0, 1]
+                .iter()
+                .map(|&shard_index| simulation_args::CreateShardBlock {
+                    shard_index,
+                    shard_block: simulation_args::ShardBlock {
+                        transactions: vec![simulation_args::ShardTransaction {
+                            data: vec![data; 32],
+                            ee_index,
+                            gas_limit: 1_000_000,
+                        }],
+                        ..Default::default()
+                    },
+                })
+                .collect(),
+        };
+        simulation
+            .create_shard_blocks(create_shard_blocks_args(1))
+            .unwrap();
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        simulation
+            .create_shard_blocks(create_shard_blocks_args(2))
+            .unwrap();
+        drop(simulation);
+
+        // As if the process stopped part way through appending the second batch
+        let path = block_file_path(dir.path(), SHARD_BLOCKS_FILE, 0);
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(file.metadata().unwrap().len() - 1).unwrap();
+        drop(file);
+
+        let mut store = DiskStore::<MainnetEthSpec>::open(dir.path()).unwrap();
+        let shard = Shard::new(0);
+        assert_eq!(store.shard_blocks(shard).unwrap().len(), 2);
+        let record = ShardBlockRecord {
+            block: store.shard_blocks(shard).unwrap()[1].clone(),
+            receipt: store.shard_block_receipts(shard).unwrap()[1].clone(),
+            post_state: store.shard_post_states(shard).unwrap()[1].clone(),
+        };
+
+        // A batch with a block that can't be added adds none of them
+        let mut outside = record.clone();
+        outside.block.shard = Shard::new(<MainnetEthSpec as EthSpec>::MaxShards::to_u64());
+        match store.put_shard_blocks(vec![record, outside]) {
+            Err(Error::OutOfBounds { .. }) => {}
+            other => panic!("expected out of bounds error, got {:?}", other),
+        }
+        assert_eq!(store.shard_blocks(shard).unwrap().len(), 2);
+        drop(store);
+
+        let simulation = open(dir.path());
+        assert_eq!(
+            simulation.get_beacon_state_root().unwrap(),
+            beacon_state_root
+        );
+    }
+
+    #[test]
+    fn beacon_block_without_its_beacon_state_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut simulation = open(dir.path());
+        let create_beacon_block_args = |slot| simulation_args::CreateBeaconBlock {
+            beacon_block: simulation_args::BeaconBlock { slot },
+        };
+        simulation
+            .create_beacon_block(create_beacon_block_args(1))
+            .unwrap();
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        drop(simulation);
+
+        // As if the process stopped after appending a block, but before saving the beacon state
+        let path = block_file_path(dir.path(), BEACON_BLOCKS_FILE, 0);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        let mut record = Vec::new();
+        push_record(&mut record, &BeaconBlock::new(Slot::new(2)).as_ssz_bytes());
+        file.write_all(&record).unwrap();
+        drop(file);
+
+        let mut simulation = open(dir.path());
+        assert_eq!(
+            simulation.get_beacon_state_root().unwrap(),
+            beacon_state_root
+        );
+        simulation
+            .create_beacon_block(create_beacon_block_args(2))
+            .unwrap();
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        drop(simulation);
+        let simulation = open(dir.path());
+        assert_eq!(
+            simulation.get_beacon_state_root().unwrap(),
+            beacon_state_root
+        );
+    }
+
+    #[test]
+    fn replaced_store_is_restored_when_store_is_reopened() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut simulation: Simulation<MainnetEthSpec> = Simulation::new();
+        let ee_index = create_ee(&mut simulation);
+        create_shard_block(&mut simulation, ee_index, 1);
+        let snapshot_path = dir.path().join("snapshot.ssz");
+        simulation.save_snapshot(&snapshot_path).unwrap();
+
+        let store_dir = dir.path().join("store");
+        let mut disk_simulation = open(&store_dir);
+        create_ee(&mut disk_simulation);
+        disk_simulation.load_snapshot(&snapshot_path).unwrap();
+        create_shard_block(&mut disk_simulation, ee_index, 2);
+        drop(disk_simulation);
+
+        create_shard_block(&mut simulation, ee_index, 2);
+        let disk_simulation = open(&store_dir);
+        assert_eq!(
+            disk_simulation.get_beacon_state_root().unwrap(),
+            simulation.get_beacon_state_root().unwrap()
+        );
+        // Only the block files of the new generation are kept
+        assert!(!block_file_path(&store_dir, SHARD_BLOCKS_FILE, 0).exists());
+        assert!(block_file_path(&store_dir, SHARD_BLOCKS_FILE, 1).exists());
+    }
+
+    #[test]
+    fn interrupted_replace_keeps_the_old_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut simulation = open(dir.path());
+        let ee_index = create_ee(&mut simulation);
+        create_shard_block(&mut simulation, ee_index, 1);
+        let beacon_state_root = simulation.get_beacon_state_root().unwrap();
+        drop(simulation);
+
+        // As if the process stopped after writing the block files of the next generation, but
+        // before switching to them
+        for name in &[BEACON_BLOCKS_FILE, SHARD_BLOCKS_FILE] {
+            fs::write(block_file_path(dir.path(), name, 1), [1, 0, 0, 0, 1]).unwrap();
+        }
+
+        let simulation = open(dir.path());
+        assert_eq!(
+            simulation.get_beacon_state_root().unwrap(),
+            beacon_state_root
+        );
+        assert!(!block_file_path(dir.path(), SHARD_BLOCKS_FILE, 1).exists());
+    }
+}
@@ -0,0 +1,261 @@
+//! Storage for everything the simulation keeps track of.
+//!
+//! The simulation only reads and writes through the `StoreBackend` trait, so that where the data
+//! lives can be chosen when the simulation is created. `MemoryStore` keeps everything in memory,
+//! and `DiskStore` also persists it to a directory so that it survives a restart.
+mod disk;
+
+pub use disk::DiskStore;
+
+use crate::{Error, Result, WhatBound};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use typenum::Unsigned;
+use types::beacon_block::BeaconBlock;
+use types::beacon_state::BeaconState;
+use types::eth_spec::EthSpec;
+use types::shard_block::ShardBlock;
+use types::shard_block_receipt::ShardBlockReceipt;
+use types::shard_state::ShardState;
+use types::slot_epoch_root::{Root, Shard, ShardSlot};
+
+/// The reads and writes the simulation makes to its storage
+///
+/// A write that fails leaves the store as it was. Stores are `Sync` so that a simulation can be
+/// read from several threads at once.
+pub trait StoreBackend<T: EthSpec>: Debug + Send + Sync {
+    fn current_beacon_state(&self) -> &BeaconState<T>;
+
+    /// All beacon blocks that have been processed, in slot order
+    fn beacon_blocks(&self) -> &[BeaconBlock];
+
+    /// The shard blocks on `shard` in shard slot order, or `None` if the shard doesn't exist
+    fn shard_blocks(&self, shard: Shard) -> Option<&[ShardBlock<T>]>;
+
+    /// The receipts of the shard blocks on `shard`, at the same index as their blocks
+    fn shard_block_receipts(&self, shard: Shard) -> Option<&[ShardBlockReceipt<T>]>;
+
+    /// The state of `shard` after each of its shard blocks, at the same index as their blocks
+    fn shard_post_states(&self, shard: Shard) -> Option<&[ShardState<T>]>;
+
+    /// The location of the shard block with the given root
+    fn shard_block_slot_by_root(&self, root: &Root) -> Option<(Shard, ShardSlot)>;
+
+    fn put_beacon_state(&mut self, state: BeaconState<T>) -> Result<()>;
+
+    /// Add a beacon block, along with the beacon state after it was processed
+    fn put_beacon_block(&mut self, block: BeaconBlock, state: BeaconState<T>) -> Result<()>;
+
+    /// Add the next shard block on a shard along with its receipt. `post_state` becomes the
+    /// current state of the shard.
+    fn put_shard_block(
+        &mut self,
+        block: ShardBlock<T>,
+        receipt: ShardBlockReceipt<T>,
+        post_state: ShardState<T>,
+    ) -> Result<()> {
+        self.put_shard_blocks(vec![ShardBlockRecord {
+            block,
+            receipt,
+            post_state,
+        }])
+    }
+
+    /// Add shard blocks in order, as `put_shard_block` does for each of them. Either all of them
+    /// are added, or none are.
+    fn put_shard_blocks(&mut self, records: Vec<ShardBlockRecord<T>>) -> Result<()>;
+
+    /// Replace everything in the store, eg. with a snapshot
+    fn replace(&mut self, store: MemoryStore<T>) -> Result<()>;
+}
+
+/// A shard block as it is stored, along with its receipt and the shard state after it
+#[derive(Debug, Clone, DeriveDecode, DeriveEncode)]
+pub struct ShardBlockRecord<T>
+where
+    T: EthSpec,
+{
+    pub block: ShardBlock<T>,
+    pub receipt: ShardBlockReceipt<T>,
+    pub post_state: ShardState<T>,
+}
+
+/// Contains arbitrary state stored by the simulation
+/// This struct need not adhere to any official Eth2 spec -- it will store internal
+/// simulation state in whatever manner is most convenient.
+/// However, some types that ARE listed in a spec (eg. BeaconState) will still be spec-compliant
+/// even if they happen to be stored in MemoryStore.
+#[derive(Debug)]
+pub struct MemoryStore<T>
+where
+    T: EthSpec,
+{
+    pub current_beacon_state: BeaconState<T>,
+
+    // All beacon blocks that have been processed, in slot order
+    pub beacon_blocks: Vec<BeaconBlock>,
+
+    // A mapping from shard to the shard blocks contained in the shard
+    // HashMap<Shard, Vec<ShardBlock>> is used instead of Vec<Vec<ShardBlock>> because the former
+    // is easier to read and immediately understand what is being stored.
+    pub shard_blocks_by_shard: HashMap<Shard, Vec<ShardBlock<T>>>,
+
+    // A mapping from shard block root to the location of the block in shard_blocks_by_shard
+    pub shard_block_slots_by_root: HashMap<Root, (Shard, ShardSlot)>,
+
+    // A mapping from shard to the receipts of the shard blocks contained in the shard
+    // The receipt for a block is stored at the same index as the block in shard_blocks_by_shard
+    pub shard_block_receipts_by_shard: HashMap<Shard, Vec<ShardBlockReceipt<T>>>,
+
+    // A mapping from shard to the state of the shard after each of its shard blocks was applied
+    // The post state for a block is stored at the same index as the block in shard_blocks_by_shard
+    pub shard_post_states_by_shard: HashMap<Shard, Vec<ShardState<T>>>,
+}
+
+impl<T: EthSpec> MemoryStore<T> {
+    pub fn new() -> Self {
+        // Initialize shard blocks storage for all shards
+        let mut shard_blocks_by_shard = HashMap::new();
+        let mut shard_block_receipts_by_shard = HashMap::new();
+        let mut shard_post_states_by_shard = HashMap::new();
+        for shard in 0..T::MaxShards::to_u64() {
+            let shard = Shard::new(shard);
+            shard_blocks_by_shard.insert(shard, Vec::new());
+            shard_block_receipts_by_shard.insert(shard, Vec::new());
+            shard_post_states_by_shard.insert(shard, Vec::new());
+        }
+        Self {
+            current_beacon_state: BeaconState::new(),
+            beacon_blocks: Vec::new(),
+            shard_blocks_by_shard,
+            shard_block_slots_by_root: HashMap::new(),
+            shard_block_receipts_by_shard,
+            shard_post_states_by_shard,
+        }
+    }
+
+    /// Add a shard block to a shard that is known to exist
+    fn push_shard_block(&mut self, record: ShardBlockRecord<T>) {
+        let ShardBlockRecord {
+            block,
+            receipt,
+            post_state,
+        } = record;
+        let shard = block.shard;
+        let shard_index: usize = shard.into();
+        self.current_beacon_state.shard_states[shard_index] = post_state.clone();
+
+        self.shard_block_slots_by_root
+            .insert(receipt.shard_block_root, (shard, block.slot));
+        self.shard_blocks_by_shard
+            .entry(shard)
+            .or_default()
+            .push(block);
+        self.shard_block_receipts_by_shard
+            .entry(shard)
+            .or_default()
+            .push(receipt);
+        self.shard_post_states_by_shard
+            .entry(shard)
+            .or_default()
+            .push(post_state);
+    }
+}
+
+impl<T: EthSpec> StoreBackend<T> for MemoryStore<T> {
+    fn current_beacon_state(&self) -> &BeaconState<T> {
+        &self.current_beacon_state
+    }
+
+    fn beacon_blocks(&self) -> &[BeaconBlock] {
+        &self.beacon_blocks
+    }
+
+    fn shard_blocks(&self, shard: Shard) -> Option<&[ShardBlock<T>]> {
+        self.shard_blocks_by_shard.get(&shard).map(Vec::as_slice)
+    }
+
+    fn shard_block_receipts(&self, shard: Shard) -> Option<&[ShardBlockReceipt<T>]> {
+        self.shard_block_receipts_by_shard
+            .get(&shard)
+            .map(Vec::as_slice)
+    }
+
+    fn shard_post_states(&self, shard: Shard) -> Option<&[ShardState<T>]> {
+        self.shard_post_states_by_shard
+            .get(&shard)
+            .map(Vec::as_slice)
+    }
+
+    fn shard_block_slot_by_root(&self, root: &Root) -> Option<(Shard, ShardSlot)> {
+        self.shard_block_slots_by_root.get(root).copied()
+    }
+
+    fn put_beacon_state(&mut self, state: BeaconState<T>) -> Result<()> {
+        self.current_beacon_state = state;
+        Ok(())
+    }
+
+    fn put_beacon_block(&mut self, block: BeaconBlock, state: BeaconState<T>) -> Result<()> {
+        self.beacon_blocks.push(block);
+        self.current_beacon_state = state;
+        Ok(())
+    }
+
+    fn put_shard_blocks(&mut self, records: Vec<ShardBlockRecord<T>>) -> Result<()> {
+        // Check every block before adding any of them
+        for record in records.iter() {
+            let shard_index: usize = record.block.shard.into();
+            if shard_index >= self.current_beacon_state.shard_states.len() {
+                return Err(Error::OutOfBounds {
+                    what: WhatBound::Shard,
+                    index: shard_index,
+                });
+            }
+        }
+        for record in records {
+            self.push_shard_block(record);
+        }
+        Ok(())
+    }
+
+    fn replace(&mut self, store: MemoryStore<T>) -> Result<()> {
+        *self = store;
+        Ok(())
+    }
+}
+
+/// Replace the file at `path` with `bytes`, so that the file is either unchanged or completely
+/// written, even if the process stops part way through
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    // The temporary file is next to the final one, so that renaming it is atomic, and its name is
+    // unique, so that concurrent writes, eg. of two snapshots, don't write to the same one
+    static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+
+    let written = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    // The rename is only durable once the directory it was made in is synced
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir).and_then(|dir| dir.sync_all())
+}
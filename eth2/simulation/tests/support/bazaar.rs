@@ -0,0 +1,9 @@
+//! The bazaar EE and the transaction that `phase2_bazaar.yaml` runs with it, for tests of both the
+//! simulation and the server. The scout tests check that these match the test vector.
+
+pub const BAZAAR_WASM: &[u8] = include_bytes!("../phase2_bazaar.wasm");
+pub const BAZAAR_INITIAL_STATE: &str =
+    "22ea9b045f8792170b45ec629c98e1b92bc6a19cd8d0e9f37baaadf2564142f4";
+pub const BAZAAR_POST_STATE: &str =
+    "29505fd952857b5766c759bcb4af58eb8df5a91043540c1398dd987a503127fc";
+pub const BAZAAR_DATA: &str = "5c0000005000000001000000000000000000000000000000000000000000000000000000000000000000000000000000020000000000000001010101010101010101010101010101010101010101010101010101010101010400000000000000";
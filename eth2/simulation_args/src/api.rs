@@ -0,0 +1,122 @@
+//! A trait for the interface of a simulation, so that the same code can drive a simulation in
+//! this process or one running on a server.
+use crate::{
+    CreateBeaconBlock, CreateExecutionEnvironment, CreateShardBlock, CreateShardBlocks,
+    ExecutionEnvironment, ExecutionEnvironmentStateProof, GetExecutionEnvironment,
+    GetExecutionEnvironmentState, GetExecutionEnvironmentStateProof, GetLogs, GetShardBlock,
+    GetShardBlockByRoot, GetShardBlockReceipts, GetShardState, LoadSnapshot, LogEntry,
+    ModuleCacheStats, SaveSnapshot, ShardBlock, ShardBlockReceipt, ShardState, SimulateShardBlock,
+    SimulateTransaction, SimulatedShardBlock, Spec, TransactionReceipt,
+};
+use std::error::Error as StdError;
+
+/// Re-exported so that implementations of `SimulationApi` don't need their own dependency on it
+pub use async_trait::async_trait;
+
+/// The interface of a simulation, whether it runs in this process or on a `simulation_server`
+///
+/// It is implemented by `simulation::Simulation`, by the server's dispatch `Handle` and by
+/// `simulation_client::SimulationClient`, so code that is generic over `SimulationApi` runs the
+/// same scenario against any of them. Every method takes `&mut self`, since sending a request
+/// through a `Handle` needs it even when the simulation is only read.
+#[async_trait]
+pub trait SimulationApi {
+    /// The error returned when a call fails
+    type Error: StdError;
+
+    /// Advance the beacon chain to the next slot without a beacon block, return the new slot
+    async fn advance_slot(&mut self) -> Result<u64, Self::Error>;
+
+    /// Add a new beacon block, advancing the beacon chain to the block's slot, return the slot
+    async fn create_beacon_block(&mut self, a: CreateBeaconBlock) -> Result<u64, Self::Error>;
+
+    /// Add a new execution environment, return EE index
+    async fn create_execution_environment(
+        &mut self,
+        a: CreateExecutionEnvironment,
+    ) -> Result<u64, Self::Error>;
+
+    /// Add a new shard block and execute its transactions, return its receipt
+    async fn create_shard_block(
+        &mut self,
+        a: CreateShardBlock,
+    ) -> Result<ShardBlockReceipt, Self::Error>;
+
+    /// Add blocks to several shards at once, return a receipt for each block
+    async fn create_shard_blocks(
+        &mut self,
+        a: CreateShardBlocks,
+    ) -> Result<Vec<ShardBlockReceipt>, Self::Error>;
+
+    /// Get the root of the current beacon state
+    async fn get_beacon_state_root(&mut self) -> Result<[u8; 32], Self::Error>;
+
+    /// Get an EE that was previously added
+    async fn get_execution_environment(
+        &mut self,
+        a: GetExecutionEnvironment,
+    ) -> Result<ExecutionEnvironment, Self::Error>;
+
+    /// Get the state of an EE on a shard
+    async fn get_execution_environment_state(
+        &mut self,
+        a: GetExecutionEnvironmentState,
+    ) -> Result<[u8; 32], Self::Error>;
+
+    /// Get a proof of the state of an EE on a shard against the current beacon state root
+    async fn get_execution_environment_state_proof(
+        &mut self,
+        a: GetExecutionEnvironmentStateProof,
+    ) -> Result<ExecutionEnvironmentStateProof, Self::Error>;
+
+    /// Get the logs emitted by transactions that match the filter
+    async fn get_logs(&mut self, a: GetLogs) -> Result<Vec<LogEntry>, Self::Error>;
+
+    /// Get the statistics of the compiled EE code cache
+    async fn get_module_cache_stats(&mut self) -> Result<ModuleCacheStats, Self::Error>;
+
+    /// Get a shard block that was previously added
+    async fn get_shard_block(&mut self, a: GetShardBlock) -> Result<ShardBlock, Self::Error>;
+
+    /// Get a shard block that was previously added by its root
+    async fn get_shard_block_by_root(
+        &mut self,
+        a: GetShardBlockByRoot,
+    ) -> Result<ShardBlock, Self::Error>;
+
+    /// Get the root of a shard block that was previously added
+    async fn get_shard_block_root(&mut self, a: GetShardBlock) -> Result<[u8; 32], Self::Error>;
+
+    /// Get the receipt of a shard block that was previously added
+    async fn get_shard_block_receipts(
+        &mut self,
+        a: GetShardBlockReceipts,
+    ) -> Result<ShardBlockReceipt, Self::Error>;
+
+    /// Get the specified shard state
+    async fn get_shard_state(&mut self, a: GetShardState) -> Result<ShardState, Self::Error>;
+
+    /// Get the root of the specified shard state
+    async fn get_shard_state_root(&mut self, a: GetShardState) -> Result<[u8; 32], Self::Error>;
+
+    /// Get the spec the simulation runs and the limits it enforces
+    async fn get_spec(&mut self) -> Result<Spec, Self::Error>;
+
+    /// Replace the whole simulation with a snapshot saved by `save_snapshot`
+    async fn load_snapshot(&mut self, a: LoadSnapshot) -> Result<(), Self::Error>;
+
+    /// Save the whole simulation to a file
+    async fn save_snapshot(&mut self, a: SaveSnapshot) -> Result<(), Self::Error>;
+
+    /// Run a shard block without storing it or changing any state
+    async fn simulate_shard_block(
+        &mut self,
+        a: SimulateShardBlock,
+    ) -> Result<SimulatedShardBlock, Self::Error>;
+
+    /// Run a single transaction without storing it or changing any state
+    async fn simulate_transaction(
+        &mut self,
+        a: SimulateTransaction,
+    ) -> Result<TransactionReceipt, Self::Error>;
+}
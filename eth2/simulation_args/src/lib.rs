@@ -3,17 +3,33 @@
 /// types.
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
+use std::collections::BTreeMap;
 use std::convert::{TryFrom, TryInto};
 use std::fmt;
+use std::path::PathBuf;
+use typenum::Unsigned;
+
+mod api;
+mod proof;
+
+pub use api::{async_trait, SimulationApi};
+pub use proof::{
+    execution_environment_state_generalized_index, verify_execution_environment_state_proof,
+    verify_merkle_proof,
+};
+// Needed to compute and verify proofs against the spec the simulation is running
+pub use types::eth_spec::{EthSpec, EthSpecId, MainnetEthSpec, MinimalEthSpec};
 
 mod internal_types {
-    pub use ssz_types::{Error, VariableList};
+    pub use ssz_types::{BitVector, Error, VariableList};
+    pub use types::beacon_block::BeaconBlock;
     pub use types::eth_spec::EthSpec;
     pub use types::execution_environment::ExecutionEnvironment;
-    pub use types::shard_block::ShardBlock;
+    pub use types::shard_block::{ShardBlock, ShardBlockBody};
+    pub use types::shard_block_receipt::{Log, ShardBlockReceipt, TransactionReceipt};
     pub use types::shard_state::ShardState;
     pub use types::shard_transaction::ShardTransaction;
-    pub use types::slot_epoch_root::Root;
+    pub use types::slot_epoch_root::{Root, Shard, ShardSlot, ValidatorIndex};
 }
 
 /// Shorthand for result types returned from the Simulation simulation.
@@ -57,15 +73,30 @@ pub enum Error {
     InvalidBytes32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CreateBeaconBlock {
+    pub beacon_block: BeaconBlock,
+}
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct CreateExecutionEnvironment {
     pub ee: ExecutionEnvironment,
+    /// States the EE starts from on particular shards instead of `ee.initial_state`
+    #[serde(default, with = "map_base64_arrs")]
+    pub shard_initial_states: BTreeMap<u64, [u8; 32]>,
+    /// Shards the EE is deployed to, all of them if not set
+    #[serde(default)]
+    pub active_shards: Option<Vec<u64>>,
 }
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CreateShardBlock {
     pub shard_index: u64,
     pub shard_block: ShardBlock,
 }
+/// Blocks for several shards, which are executed in parallel
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CreateShardBlocks {
+    pub shard_blocks: Vec<CreateShardBlock>,
+}
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetExecutionEnvironment {
     pub ee_index: u64,
@@ -74,6 +105,26 @@ pub struct GetExecutionEnvironment {
 pub struct GetExecutionEnvironmentState {
     pub ee_index: u64,
     pub shard_index: u64,
+    // If specified, get the EE state as it was after the shard block at this slot
+    // Otherwise, get the current EE state
+    pub shard_slot_index: Option<u64>,
+}
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetExecutionEnvironmentStateProof {
+    pub ee_index: u64,
+    pub shard_index: u64,
+}
+/// Every filter is optional, leaving them all out returns every log in the simulation
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct GetLogs {
+    pub shard_index: Option<u64>,
+    pub ee_index: Option<u64>,
+    // Only return logs from shard blocks in this range of shard slots, inclusive
+    pub from_shard_slot_index: Option<u64>,
+    pub to_shard_slot_index: Option<u64>,
+    // Only return logs whose topics start with these topics
+    #[serde(default, with = "vec_base64_arrs")]
+    pub topics: Vec<[u8; 32]>,
 }
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetShardBlock {
@@ -81,8 +132,43 @@ pub struct GetShardBlock {
     pub shard_slot_index: u64,
 }
 #[derive(Debug, Deserialize, Serialize)]
+pub struct GetShardBlockByRoot {
+    #[serde(with = "base64_arr")]
+    pub shard_block_root: [u8; 32],
+}
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetShardBlockReceipts {
+    pub shard_index: u64,
+    pub shard_slot_index: u64,
+}
+#[derive(Debug, Deserialize, Serialize)]
 pub struct GetShardState {
     pub shard_index: u64,
+    // If specified, get the shard state as it was after the shard block at this slot
+    // Otherwise, get the current shard state
+    pub shard_slot_index: Option<u64>,
+}
+/// The path is a plain file name in the snapshot directory of the machine running the simulation
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LoadSnapshot {
+    pub path: PathBuf,
+}
+/// The path is a plain file name in the snapshot directory of the machine running the simulation
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SaveSnapshot {
+    pub path: PathBuf,
+}
+/// Runs a shard block against the current shard state without storing it or changing any state
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SimulateShardBlock {
+    pub shard_index: u64,
+    pub shard_block: ShardBlock,
+}
+/// Runs a transaction against the current state of its EE without changing any state
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SimulateTransaction {
+    pub shard_index: u64,
+    pub transaction: ShardTransaction,
 }
 
 /// Defines custom serialization for basic return types
@@ -98,7 +184,11 @@ pub enum CustomSerializedReturnTypes {
 
 // Interface structs
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct BeaconBlock {
+    pub slot: u64,
+}
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct ExecutionEnvironment {
     #[serde(with = "base64_arr")]
     pub initial_state: [u8; 32],
@@ -106,13 +196,70 @@ pub struct ExecutionEnvironment {
     #[serde(with = "base64_vec")]
     pub wasm_code: Vec<u8>,
 }
+/// A Merkle proof that an EE on a shard has a particular state root, taken against the current
+/// beacon state
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ExecutionEnvironmentStateProof {
+    pub ee_index: u64,
+    pub shard_index: u64,
+    #[serde(with = "base64_arr")]
+    pub ee_state_root: [u8; 32],
+    // The root of the beacon state the proof was taken from
+    #[serde(with = "base64_arr")]
+    pub beacon_state_root: [u8; 32],
+    // Sibling nodes on the path from the EE state root up to the beacon state root
+    #[serde(with = "vec_base64_arrs")]
+    pub branch: Vec<[u8; 32]>,
+    pub generalized_index: u64,
+}
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Log {
+    #[serde(with = "vec_base64_arrs")]
+    pub topics: Vec<[u8; 32]>,
+    #[serde(with = "base64_vec")]
+    pub data: Vec<u8>,
+}
+/// A log along with where it was emitted
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LogEntry {
+    pub shard_index: u64,
+    pub shard_slot_index: u64,
+    // The index of the transaction that emitted the log within its shard block
+    pub transaction_index: u64,
+    pub ee_index: u64,
+    pub log: Log,
+}
+/// How often the simulation's cache of compiled EE code has been used
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ModuleCacheStats {
+    // Lookups that found the code already compiled
+    pub hits: u64,
+    // Lookups that had to compile the code
+    pub misses: u64,
+    // The number of distinct pieces of code that are compiled
+    pub modules: u64,
+}
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct ShardTransaction {
     pub data: Vec<u8>,
     pub ee_index: u64,
+    // The most gas the transaction may use, it fails without changing the EE state if it runs out
+    pub gas_limit: u64,
 }
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ShardBlock {
+    // The header fields are filled in by the simulation when the block is created, so they can be
+    // left out of the block passed to `CreateShardBlock`
+    #[serde(default, with = "base64_arr")]
+    pub shard_parent_root: [u8; 32],
+    #[serde(default, with = "base64_arr")]
+    pub beacon_parent_root: [u8; 32],
+    #[serde(default)]
+    pub slot: u64,
+    #[serde(default)]
+    pub shard: u64,
+    #[serde(default)]
+    pub proposer_index: u64,
     pub transactions: Vec<ShardTransaction>,
 }
 
@@ -122,8 +269,85 @@ pub struct ShardState {
     pub execution_environment_states: Vec<[u8; 32]>,
 }
 
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TransactionReceipt {
+    pub ee_index: u64,
+    #[serde(with = "base64_arr")]
+    pub pre_state_root: [u8; 32],
+    #[serde(with = "base64_arr")]
+    pub post_state_root: [u8; 32],
+    pub success: bool,
+    pub trap_message: Option<String>,
+    // How long the transaction took to run. Only known in the receipt returned when the block
+    // is run, since timings differ between runs and aren't stored.
+    #[serde(default)]
+    pub execution_time_nanos: Option<u64>,
+    pub gas_used: u64,
+    pub logs: Vec<Log>,
+}
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ShardBlockReceipt {
+    pub shard_index: u64,
+    pub shard_slot_index: u64,
+    #[serde(with = "base64_arr")]
+    pub shard_block_root: [u8; 32],
+    pub beacon_slot: u64,
+    pub gas_used: u64,
+    pub transaction_receipts: Vec<TransactionReceipt>,
+}
+/// The outcome of a shard block that was run without being stored
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SimulatedShardBlock {
+    pub shard_index: u64,
+    // The EE states on the shard that the block would lead to
+    #[serde(with = "vec_base64_arrs")]
+    pub execution_environment_states: Vec<[u8; 32]>,
+    pub gas_used: u64,
+    pub transaction_receipts: Vec<TransactionReceipt>,
+}
+/// The spec a simulation runs, and the limits it puts on EEs and blocks, which may be lower than
+/// the spec's
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Spec {
+    pub name: EthSpecId,
+    pub max_execution_environments: u64,
+    // The largest EE code in bytes
+    pub max_ee_byte_code_size: u64,
+    pub max_transactions_per_block: u64,
+    // The most gas the transactions in a shard block may use altogether
+    pub max_gas_per_shard_block: u64,
+    pub max_shards: u64,
+}
+
+impl Spec {
+    /// Describe the spec `T` with its own limits
+    pub fn of<T: EthSpec>() -> Self {
+        Self {
+            name: T::spec_name(),
+            max_execution_environments: T::MaxExecutionEnvironments::to_u64(),
+            max_ee_byte_code_size: T::MaxEEByteCodeSize::to_u64(),
+            max_transactions_per_block: T::MaxTransactionsPerBlock::to_u64(),
+            max_gas_per_shard_block: T::MaxGasPerShardBlock::to_u64(),
+            max_shards: T::MaxShards::to_u64(),
+        }
+    }
+}
+
 // Conversions to/from interface structs <--> internal structs
 
+impl From<internal_types::BeaconBlock> for BeaconBlock {
+    fn from(value: internal_types::BeaconBlock) -> Self {
+        Self {
+            slot: value.slot.into(),
+        }
+    }
+}
+impl From<BeaconBlock> for internal_types::BeaconBlock {
+    fn from(value: BeaconBlock) -> Self {
+        Self::new(value.slot.into())
+    }
+}
+
 impl<T: internal_types::EthSpec> From<internal_types::ExecutionEnvironment<T>>
     for ExecutionEnvironment
 {
@@ -143,10 +367,18 @@ impl<T: internal_types::EthSpec> TryFrom<ExecutionEnvironment>
     fn try_from(value: ExecutionEnvironment) -> Result<Self, Self::Error> {
         let initial_state: internal_types::Root = internal_types::Root::from(value.initial_state);
         let wasm_code = internal_types::VariableList::new(value.wasm_code).context(SszTypesError)?;
+        // Deployed to every shard until told otherwise
+        let mut active_shards = internal_types::BitVector::new();
+        for shard_index in 0..active_shards.len() {
+            active_shards
+                .set(shard_index, true)
+                .context(SszTypesError)?;
+        }
 
         Ok(Self {
             initial_state,
             wasm_code,
+            active_shards,
         })
     }
 }
@@ -155,7 +387,11 @@ impl From<internal_types::ShardTransaction> for ShardTransaction {
     fn from(value: internal_types::ShardTransaction) -> Self {
         let data: Vec<u8> = value.data.into();
         let ee_index: u64 = value.ee_index.into();
-        Self { data, ee_index }
+        Self {
+            data,
+            ee_index,
+            gas_limit: value.gas_limit,
+        }
     }
 }
 impl TryFrom<ShardTransaction> for internal_types::ShardTransaction {
@@ -163,18 +399,30 @@ impl TryFrom<ShardTransaction> for internal_types::ShardTransaction {
     fn try_from(value: ShardTransaction) -> Result<Self, Self::Error> {
         let ee_index = value.ee_index.into();
         let data = internal_types::VariableList::new(value.data).context(SszTypesError)?;
-        Ok(Self { data, ee_index })
+        Ok(Self {
+            data,
+            ee_index,
+            gas_limit: value.gas_limit,
+        })
     }
 }
 
 impl<T: internal_types::EthSpec> From<internal_types::ShardBlock<T>> for ShardBlock {
     fn from(value: internal_types::ShardBlock<T>) -> Self {
         let transactions: Vec<ShardTransaction> = value
+            .body
             .transactions
             .into_iter()
             .map(|t| -> ShardTransaction { t.clone().into() })
             .collect();
-        Self { transactions }
+        Self {
+            shard_parent_root: value.shard_parent_root.into(),
+            beacon_parent_root: value.beacon_parent_root.into(),
+            slot: value.slot.into(),
+            shard: value.shard.into(),
+            proposer_index: value.proposer_index.into(),
+            transactions,
+        }
     }
 }
 impl<T: internal_types::EthSpec> TryFrom<ShardBlock> for internal_types::ShardBlock<T> {
@@ -186,7 +434,14 @@ impl<T: internal_types::EthSpec> TryFrom<ShardBlock> for internal_types::ShardBl
             transactions.push(transaction);
         }
         let transactions = internal_types::VariableList::new(transactions).context(SszTypesError)?;
-        Ok(Self { transactions })
+        Ok(Self::new(
+            internal_types::Root::from(value.shard_parent_root),
+            internal_types::Root::from(value.beacon_parent_root),
+            internal_types::ShardSlot::new(value.slot),
+            internal_types::Shard::new(value.shard),
+            internal_types::ValidatorIndex::new(value.proposer_index),
+            internal_types::ShardBlockBody { transactions },
+        ))
     }
 }
 
@@ -218,6 +473,54 @@ impl<T: internal_types::EthSpec> TryFrom<ShardState> for internal_types::ShardSt
     }
 }
 
+impl From<internal_types::Log> for Log {
+    fn from(value: internal_types::Log) -> Self {
+        let topics: Vec<[u8; 32]> = value.topics.iter().map(|topic| (*topic).into()).collect();
+        Self {
+            topics,
+            data: value.data.to_vec(),
+        }
+    }
+}
+
+impl From<internal_types::TransactionReceipt> for TransactionReceipt {
+    fn from(value: internal_types::TransactionReceipt) -> Self {
+        let trap_message = if value.success {
+            None
+        } else {
+            Some(String::from_utf8_lossy(&value.trap_message).into_owned())
+        };
+        Self {
+            ee_index: value.ee_index.into(),
+            pre_state_root: value.pre_state_root.into(),
+            post_state_root: value.post_state_root.into(),
+            success: value.success,
+            trap_message,
+            execution_time_nanos: None,
+            gas_used: value.gas_used,
+            logs: value.logs.iter().map(|log| log.clone().into()).collect(),
+        }
+    }
+}
+
+impl<T: internal_types::EthSpec> From<internal_types::ShardBlockReceipt<T>> for ShardBlockReceipt {
+    fn from(value: internal_types::ShardBlockReceipt<T>) -> Self {
+        let transaction_receipts: Vec<TransactionReceipt> = value
+            .transaction_receipts
+            .into_iter()
+            .map(|r| -> TransactionReceipt { r.clone().into() })
+            .collect();
+        Self {
+            shard_index: value.shard.into(),
+            shard_slot_index: value.shard_slot.into(),
+            shard_block_root: value.shard_block_root.into(),
+            beacon_slot: value.beacon_slot.into(),
+            gas_used: value.gas_used,
+            transaction_receipts,
+        }
+    }
+}
+
 // Serialization helpers
 
 pub trait ToBytes32 {
@@ -283,6 +586,49 @@ mod vec_base64_arrs {
     }
 }
 
+/// Serializes a `BTreeMap<u64, [u8; 32]>` with base64 values, for use with
+/// `#[serde(with = "simulation_args::map_base64_arrs")]`
+pub mod map_base64_arrs {
+    use super::ToBytes32;
+    use serde::de::{Deserialize, Deserializer, Error, Unexpected};
+    use serde::ser::{SerializeMap, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S>(map: &BTreeMap<u64, [u8; 32]>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut ser_map = serializer.serialize_map(Some(map.len()))?;
+        for (key, bytes_arr) in map {
+            ser_map.serialize_entry(key, &base64::encode(bytes_arr.as_ref()))?;
+        }
+        ser_map.end()
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<BTreeMap<u64, [u8; 32]>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let string_map: BTreeMap<u64, String> = BTreeMap::deserialize(deserializer)?;
+
+        string_map
+            .into_iter()
+            .map(|(key, s)| {
+                let vec_u8 = base64::decode(&s).map_err(|_| {
+                    D::Error::invalid_value(Unexpected::Str(&s), &"base64 encoded bytes")
+                })?;
+                let bytes_arr = vec_u8.to_bytes32().map_err(|_| {
+                    D::Error::invalid_value(
+                        Unexpected::Bytes(&vec_u8),
+                        &"exactly 32 base64 encoded bytes",
+                    )
+                })?;
+                Ok((key, bytes_arr))
+            })
+            .collect()
+    }
+}
+
 mod base64_vec {
     use serde::de::{Deserialize, Deserializer, Error as _, Unexpected};
     use serde::Serializer;
@@ -307,7 +653,8 @@ mod base64_vec {
     }
 }
 
-mod base64_arr {
+/// Serializes a `[u8; 32]` as base64, for use with `#[serde(with = "simulation_args::base64_arr")]`
+pub mod base64_arr {
     use serde::de::{Deserializer, Error as _, Unexpected};
 
     use super::ToBytes32;
@@ -0,0 +1,138 @@
+//! Verification of Merkle proofs returned by the simulation.
+//!
+//! These only depend on `simulation_args` types and the spec, so proofs can be checked without
+//! trusting the simulation that produced them.
+use crate::ExecutionEnvironmentStateProof;
+use eth2_hashing::hash;
+use typenum::Unsigned;
+use types::eth_spec::EthSpec;
+
+// `BeaconState` fields are (slot, shard_states, execution_environments), so its tree has 4 leaves
+const BEACON_STATE_LEAF_COUNT: u64 = 4;
+const BEACON_STATE_SHARD_STATES_INDEX: u64 = 1;
+
+/// Returns the generalized index of
+/// `BeaconState.shard_states[shard_index].execution_environment_states[ee_index]`, or `None` if
+/// either index is beyond the limits of the spec
+pub fn execution_environment_state_generalized_index<T: EthSpec>(
+    shard_index: u64,
+    ee_index: u64,
+) -> Option<u64> {
+    let max_shards = T::MaxShards::to_u64();
+    let max_execution_environments = T::MaxExecutionEnvironments::to_u64();
+    if shard_index >= max_shards || ee_index >= max_execution_environments {
+        return None;
+    }
+
+    // BeaconState.shard_states
+    let index = BEACON_STATE_LEAF_COUNT + BEACON_STATE_SHARD_STATES_INDEX;
+    // The contents of a list are on the left, its length is mixed in on the right
+    let index = index.checked_mul(2)?;
+    let index = index
+        .checked_mul(max_shards.next_power_of_two())?
+        .checked_add(shard_index)?;
+    // `ShardState` only has a single field, execution_environment_states, so its root is the
+    // root of that field and it adds no depth to the tree
+    let index = index.checked_mul(2)?;
+    index
+        .checked_mul(max_execution_environments.next_power_of_two())?
+        .checked_add(ee_index)
+}
+
+/// Check that `leaf` is at `generalized_index` of the tree with the given `root`
+/// `branch` holds the sibling of each node on the path from the leaf to the root, starting at
+/// the leaf.
+pub fn verify_merkle_proof(
+    leaf: &[u8; 32],
+    branch: &[[u8; 32]],
+    generalized_index: u64,
+    root: &[u8; 32],
+) -> bool {
+    // The depth of a generalized index is the position of its highest set bit
+    if generalized_index == 0 || branch.len() != 63 - generalized_index.leading_zeros() as usize {
+        return false;
+    }
+
+    let mut node = leaf.to_vec();
+    for (depth, sibling) in branch.iter().enumerate() {
+        let mut preimage = Vec::with_capacity(64);
+        if (generalized_index >> depth) & 1 == 1 {
+            preimage.extend_from_slice(sibling);
+            preimage.extend_from_slice(&node);
+        } else {
+            preimage.extend_from_slice(&node);
+            preimage.extend_from_slice(sibling);
+        }
+        node = hash(&preimage);
+    }
+    node[..] == root[..]
+}
+
+/// Check that `proof` shows the EE it claims to be for has the state root in the proof, against a
+/// beacon state root that the caller trusts
+pub fn verify_execution_environment_state_proof<T: EthSpec>(
+    proof: &ExecutionEnvironmentStateProof,
+    beacon_state_root: &[u8; 32],
+) -> bool {
+    let generalized_index =
+        execution_environment_state_generalized_index::<T>(proof.shard_index, proof.ee_index);
+    generalized_index == Some(proof.generalized_index)
+        && verify_merkle_proof(
+            &proof.ee_state_root,
+            &proof.branch,
+            proof.generalized_index,
+            beacon_state_root,
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::eth_spec::MainnetEthSpec;
+
+    fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut preimage = left.to_vec();
+        preimage.extend_from_slice(right);
+        let mut node = [0; 32];
+        node.copy_from_slice(&hash(&preimage));
+        node
+    }
+
+    #[test]
+    fn can_verify_merkle_proof() {
+        // A tree with four leaves
+        let leaves = [[1; 32], [2; 32], [3; 32], [4; 32]];
+        let left = hash_pair(&leaves[0], &leaves[1]);
+        let right = hash_pair(&leaves[2], &leaves[3]);
+        let root = hash_pair(&left, &right);
+
+        // The third leaf has generalized index 6
+        let branch = [leaves[3], left];
+        assert!(verify_merkle_proof(&leaves[2], &branch, 6, &root));
+
+        // Wrong leaf, index, branch length or root
+        assert!(!verify_merkle_proof(&leaves[3], &branch, 6, &root));
+        assert!(!verify_merkle_proof(&leaves[2], &branch, 7, &root));
+        assert!(!verify_merkle_proof(&leaves[2], &branch[..1], 6, &root));
+        assert!(!verify_merkle_proof(&leaves[2], &branch, 6, &left));
+        assert!(!verify_merkle_proof(&leaves[2], &branch, 0, &root));
+    }
+
+    #[test]
+    fn generalized_index_is_within_spec_limits() {
+        // 2 levels for the BeaconState, 1 + 6 for the shard_states list (64 shards) and
+        // 1 + 16 for the execution_environment_states list (65536 EEs)
+        let first = execution_environment_state_generalized_index::<MainnetEthSpec>(0, 0).unwrap();
+        assert_eq!(63 - first.leading_zeros(), 26);
+        assert_eq!(first, (((5 * 2) << 6) * 2) << 16);
+
+        let last =
+            execution_environment_state_generalized_index::<MainnetEthSpec>(63, 65535).unwrap();
+        assert_eq!(last, (((((5 * 2) << 6) + 63) * 2) << 16) + 65535);
+
+        assert!(execution_environment_state_generalized_index::<MainnetEthSpec>(64, 0).is_none());
+        assert!(
+            execution_environment_state_generalized_index::<MainnetEthSpec>(0, 65536).is_none()
+        );
+    }
+}
@@ -0,0 +1,45 @@
+// Makes use of patterns and code found in https://github.com/sigp/lighthouse
+use crate::slot_epoch_root::{Root, Slot};
+use serde::{Deserialize, Serialize};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use tree_hash::TreeHash;
+use tree_hash_derive::TreeHash as DeriveTreeHash;
+
+/// A block of the `BeaconChain`.
+/// Full spec is here: https://github.com/ethereum/eth2.0-specs/blob/dev/specs/phase0/beacon-chain.md#beaconblock
+#[derive(
+    Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode, DeriveTreeHash,
+)]
+pub struct BeaconBlock {
+    pub slot: Slot,
+    //    parent_root: Root,
+    //    state_root: Root,
+    //    body: BeaconBlockBody,
+    //    signature: BLSSignature,
+}
+
+impl BeaconBlock {
+    pub fn new(slot: Slot) -> Self {
+        Self { slot }
+    }
+
+    /// Returns the `hash_tree_root` of the block
+    pub fn canonical_root(&self) -> Root {
+        Root::from_slice(&self.tree_hash_root()[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // Can't use these traits unless they're imported
+    use ssz::{Decode, Encode};
+
+    #[test]
+    fn can_encode_and_decode_ssz() {
+        let original = BeaconBlock::new(Slot::new(5));
+        let serialized: Vec<u8> = original.as_ssz_bytes();
+        let deserialized = BeaconBlock::from_ssz_bytes(&serialized).unwrap();
+        assert_eq!(original, deserialized);
+    }
+}
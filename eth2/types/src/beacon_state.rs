@@ -6,6 +6,7 @@ use crate::slot_epoch_root::Slot;
 use serde::{Deserialize, Serialize};
 use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
 use ssz_types::VariableList;
+use tree_hash_derive::TreeHash as DeriveTreeHash;
 // Traits must be in scope in order to use items on the trait
 use typenum::marker_traits::Unsigned;
 
@@ -13,7 +14,9 @@ use typenum::marker_traits::Unsigned;
 /// Full spec is here: https://github.com/ethereum/eth2.0-specs/blob/dev/specs/phase0/beacon-chain.md#beaconstate
 /// SSZ spec is here: https://github.com/ethereum/eth2.0-specs/blob/dev/ssz/simple-serialize.md
 
-#[derive(Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode)]
+#[derive(
+    Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode, DeriveTreeHash,
+)]
 #[serde(bound = "T: EthSpec")]
 pub struct BeaconState<T>
 where
@@ -21,7 +24,7 @@ where
 {
     // Versioning
     //    genesis_time: u64,
-    slot: Slot,
+    pub slot: Slot,
     //    fork: Fork,
 
     // History
@@ -3,8 +3,38 @@
 // repository, updated to use explicit imports, and added additional values to the spec definition.
 use crate::beacon_state::BeaconState;
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
-use typenum::{Unsigned, U1024, U262144, U64, U65536};
+use std::fmt::{self, Debug};
+use std::str::FromStr;
+use typenum::{Unsigned, U10000000, U100000000, U1024, U16, U262144, U4, U64, U65536};
+
+/// Identifies a specification, so that data created under one spec isn't used with another.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EthSpecId {
+    Mainnet,
+    Minimal,
+}
+
+impl fmt::Display for EthSpecId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EthSpecId::Mainnet => write!(f, "mainnet"),
+            EthSpecId::Minimal => write!(f, "minimal"),
+        }
+    }
+}
+
+impl FromStr for EthSpecId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mainnet" => Ok(EthSpecId::Mainnet),
+            "minimal" => Ok(EthSpecId::Minimal),
+            _ => Err(format!("unknown spec {:?}, expected mainnet or minimal", s)),
+        }
+    }
+}
 
 pub trait EthSpec: 'static + Default + Sync + Send + Clone + Debug + PartialEq {
     /*
@@ -13,6 +43,8 @@ pub trait EthSpec: 'static + Default + Sync + Send + Clone + Debug + PartialEq {
     type MaxExecutionEnvironments: Unsigned + Clone + Sync + Send + Debug + PartialEq + Default;
     type MaxEEByteCodeSize: Unsigned + Clone + Sync + Send + Debug + PartialEq + Default;
     type MaxTransactionsPerBlock: Unsigned + Clone + Sync + Send + Debug + PartialEq + Default;
+    // The most gas the transactions in a shard block may use altogether
+    type MaxGasPerShardBlock: Unsigned + Clone + Sync + Send + Debug + PartialEq + Default;
 
     //    /*
     //     * Constants
@@ -63,6 +95,8 @@ pub trait EthSpec: 'static + Default + Sync + Send + Clone + Debug + PartialEq {
     //    type EarlyDerivedSecretPenaltyMaxFutureEpochs: Unsigned + Clone + Sync + Send + Debug + PartialEq;
 
     type MaxShards: Unsigned + Clone + Sync + Send + Debug + PartialEq;
+
+    fn spec_name() -> EthSpecId;
 }
 
 /// Ethereum Foundation specifications.
@@ -75,6 +109,7 @@ impl EthSpec for MainnetEthSpec {
     type MaxExecutionEnvironments = U65536;
     type MaxEEByteCodeSize = U262144;
     type MaxTransactionsPerBlock = U1024;
+    type MaxGasPerShardBlock = U100000000;
     //    type JustificationBitsLength = U4;
     //    type MaxValidatorsPerCommittee = U2048;
     //    type GenesisEpoch = U0;
@@ -92,6 +127,28 @@ impl EthSpec for MainnetEthSpec {
     //    type MaxVoluntaryExits = U16;
     //    type MaxPendingAttestations = U4096; // 128 max attestations * 32 slots per epoch
     type MaxShards = U64;
+
+    fn spec_name() -> EthSpecId {
+        EthSpecId::Mainnet
+    }
 }
 
 pub type FoundationBeaconState = BeaconState<MainnetEthSpec>;
+
+/// Smaller limits than mainnet, so that tests create and hash less state.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct MinimalEthSpec;
+
+impl EthSpec for MinimalEthSpec {
+    type MaxExecutionEnvironments = U64;
+    type MaxEEByteCodeSize = U65536;
+    type MaxTransactionsPerBlock = U16;
+    type MaxGasPerShardBlock = U10000000;
+    type MaxShards = U4;
+
+    fn spec_name() -> EthSpecId {
+        EthSpecId::Minimal
+    }
+}
+
+pub type MinimalBeaconState = BeaconState<MinimalEthSpec>;
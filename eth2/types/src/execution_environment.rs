@@ -2,13 +2,23 @@ use crate::eth_spec::EthSpec;
 use crate::slot_epoch_root::Root;
 use serde::{Deserialize, Serialize};
 use ssz_derive::{Decode, Encode};
-use ssz_types::VariableList;
+use ssz_types::{BitVector, VariableList};
+use tree_hash_derive::TreeHash;
 
-#[derive(Debug, PartialEq, Clone, Deserialize, Serialize, Encode, Decode)]
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize, Encode, Decode, TreeHash)]
 pub struct ExecutionEnvironment<T>
 where
     T: EthSpec,
 {
     pub initial_state: Root,
     pub wasm_code: VariableList<u8, T::MaxEEByteCodeSize>,
+    /// Shards the EE is deployed to; transactions for it are rejected on the others
+    pub active_shards: BitVector<T::MaxShards>,
+}
+
+impl<T: EthSpec> ExecutionEnvironment<T> {
+    /// Whether the EE can run transactions on the shard
+    pub fn is_active_on(&self, shard_index: usize) -> bool {
+        self.active_shards.get(shard_index).unwrap_or(false)
+    }
 }
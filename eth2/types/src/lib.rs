@@ -1,11 +1,13 @@
 #[macro_use]
 pub mod u64_macros;
 
+pub mod beacon_block;
 pub mod beacon_state;
 pub mod eth_spec;
 pub mod execution_environment;
 pub mod slot_epoch_root;
 
 pub mod shard_block;
+pub mod shard_block_receipt;
 pub mod shard_state;
 pub mod shard_transaction;
@@ -1,13 +1,221 @@
+// Makes use of patterns and code found in https://github.com/sigp/lighthouse
 use crate::eth_spec::EthSpec;
 use crate::shard_transaction::ShardTransaction;
+use crate::slot_epoch_root::{Root, Shard, ShardSlot, ValidatorIndex};
 use serde::{Deserialize, Serialize};
 use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
-use ssz_types::VariableList;
+use ssz_types::{FixedVector, VariableList};
+use tree_hash::TreeHash;
+use tree_hash_derive::TreeHash as DeriveTreeHash;
+use typenum::U96;
 
+/// Stand-in for a `BLSSignature`, signatures aren't simulated yet so this is always zeroed
+pub type Signature = FixedVector<u8, U96>;
+
+/// A block of a shard chain.
+/// Full spec is here: https://github.com/ethereum/eth2.0-specs/blob/dev/specs/phase1/beacon-chain.md#shardblock
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode)]
+#[serde(bound = "T: EthSpec")]
 pub struct ShardBlock<T>
+where
+    T: EthSpec,
+{
+    pub shard_parent_root: Root,
+    pub beacon_parent_root: Root,
+    pub slot: ShardSlot,
+    pub shard: Shard,
+    pub proposer_index: ValidatorIndex,
+    pub body: ShardBlockBody<T>,
+    // Not part of the block root, the spec keeps the signature in a separate `SignedShardBlock`
+    pub signature: Signature,
+}
+
+/// The contents of a `ShardBlock`.
+/// The spec leaves the body as opaque bytes, here it holds the transactions executed by EEs.
+#[derive(
+    Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode, DeriveTreeHash,
+)]
+#[serde(bound = "T: EthSpec")]
+pub struct ShardBlockBody<T>
 where
     T: EthSpec,
 {
     pub transactions: VariableList<ShardTransaction, T::MaxTransactionsPerBlock>,
 }
+
+/// A `ShardBlock` with the body replaced by its root.
+/// Has the same root as the block it was created from.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode)]
+pub struct ShardBlockHeader {
+    pub shard_parent_root: Root,
+    pub beacon_parent_root: Root,
+    pub slot: ShardSlot,
+    pub shard: Shard,
+    pub proposer_index: ValidatorIndex,
+    pub body_root: Root,
+    pub signature: Signature,
+}
+
+impl<T: EthSpec> ShardBlock<T> {
+    /// Create an unsigned block on top of the given parents
+    pub fn new(
+        shard_parent_root: Root,
+        beacon_parent_root: Root,
+        slot: ShardSlot,
+        shard: Shard,
+        proposer_index: ValidatorIndex,
+        body: ShardBlockBody<T>,
+    ) -> Self {
+        Self {
+            shard_parent_root,
+            beacon_parent_root,
+            slot,
+            shard,
+            proposer_index,
+            body,
+            signature: Signature::from(vec![]),
+        }
+    }
+
+    /// Returns the `hash_tree_root` of the block
+    pub fn canonical_root(&self) -> Root {
+        Root::from_slice(&self.tree_hash_root()[..])
+    }
+
+    /// Returns a full `ShardBlockHeader` of this block
+    pub fn block_header(&self) -> ShardBlockHeader {
+        ShardBlockHeader {
+            shard_parent_root: self.shard_parent_root,
+            beacon_parent_root: self.beacon_parent_root,
+            slot: self.slot,
+            shard: self.shard,
+            proposer_index: self.proposer_index,
+            body_root: Root::from_slice(&self.body.tree_hash_root()[..]),
+            signature: self.signature.clone(),
+        }
+    }
+}
+
+impl ShardBlockHeader {
+    /// Returns the `hash_tree_root` of the header
+    pub fn canonical_root(&self) -> Root {
+        Root::from_slice(&self.tree_hash_root()[..])
+    }
+}
+
+// `TreeHash` is implemented by hand for the block and header, rather than derived, so that the
+// signature is left out of the root (`#[tree_hash(skip_hashing)]` isn't reliably picked up by
+// tree_hash_derive 0.1)
+impl TreeHash for ShardBlockHeader {
+    fn tree_hash_type() -> tree_hash::TreeHashType {
+        tree_hash::TreeHashType::Container
+    }
+
+    fn tree_hash_packed_encoding(&self) -> Vec<u8> {
+        unreachable!("Struct should never be packed.")
+    }
+
+    fn tree_hash_packing_factor() -> usize {
+        unreachable!("Struct should never be packed.")
+    }
+
+    fn tree_hash_root(&self) -> Vec<u8> {
+        let mut leaves = Vec::with_capacity(6 * tree_hash::HASHSIZE);
+        leaves.append(&mut self.shard_parent_root.tree_hash_root());
+        leaves.append(&mut self.beacon_parent_root.tree_hash_root());
+        leaves.append(&mut self.slot.tree_hash_root());
+        leaves.append(&mut self.shard.tree_hash_root());
+        leaves.append(&mut self.proposer_index.tree_hash_root());
+        leaves.append(&mut self.body_root.tree_hash_root());
+        tree_hash::merkle_root(&leaves, 0)
+    }
+}
+
+// A container's root only depends on the roots of its fields, so the block has the same root as
+// its header
+impl<T: EthSpec> TreeHash for ShardBlock<T> {
+    fn tree_hash_type() -> tree_hash::TreeHashType {
+        tree_hash::TreeHashType::Container
+    }
+
+    fn tree_hash_packed_encoding(&self) -> Vec<u8> {
+        unreachable!("Struct should never be packed.")
+    }
+
+    fn tree_hash_packing_factor() -> usize {
+        unreachable!("Struct should never be packed.")
+    }
+
+    fn tree_hash_root(&self) -> Vec<u8> {
+        self.block_header().tree_hash_root()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eth_spec::MainnetEthSpec;
+    // Can't use these traits unless they're imported
+    use ssz::{Decode, Encode};
+
+    fn test_block() -> ShardBlock<MainnetEthSpec> {
+        let transaction = ShardTransaction {
+            data: VariableList::from(vec![1, 2, 3]),
+            ee_index: crate::slot_epoch_root::EeIndex::new(0),
+            gas_limit: 100,
+        };
+        let body = ShardBlockBody {
+            transactions: VariableList::from(vec![transaction]),
+        };
+        ShardBlock::new(
+            Root::from([1; 32]),
+            Root::from([2; 32]),
+            ShardSlot::new(3),
+            Shard::new(4),
+            ValidatorIndex::new(5),
+            body,
+        )
+    }
+
+    #[test]
+    fn can_encode_and_decode_ssz() {
+        let original = test_block();
+        let serialized: Vec<u8> = original.as_ssz_bytes();
+        let deserialized: ShardBlock<MainnetEthSpec> =
+            ShardBlock::from_ssz_bytes(&serialized).unwrap();
+        assert_eq!(original, deserialized);
+    }
+
+    #[test]
+    fn header_has_same_root_as_block() {
+        let block = test_block();
+        assert_eq!(
+            block.block_header().canonical_root(),
+            block.canonical_root()
+        );
+
+        // Both should match the root of a container holding the block's fields, minus the
+        // signature
+        let mut leaves = Vec::new();
+        leaves.append(&mut block.shard_parent_root.tree_hash_root());
+        leaves.append(&mut block.beacon_parent_root.tree_hash_root());
+        leaves.append(&mut block.slot.tree_hash_root());
+        leaves.append(&mut block.shard.tree_hash_root());
+        leaves.append(&mut block.proposer_index.tree_hash_root());
+        leaves.append(&mut block.body.tree_hash_root());
+        let expected_root = Root::from_slice(&tree_hash::merkle_root(&leaves, 0));
+        assert_eq!(block.canonical_root(), expected_root);
+    }
+
+    #[test]
+    fn root_ignores_signature() {
+        let block = test_block();
+        let mut signed_block = block.clone();
+        signed_block.signature = Signature::from(vec![5; 96]);
+        assert_eq!(block.canonical_root(), signed_block.canonical_root());
+
+        let mut other_block = block.clone();
+        other_block.shard = Shard::new(0);
+        assert_ne!(block.canonical_root(), other_block.canonical_root());
+    }
+}
@@ -0,0 +1,62 @@
+use crate::eth_spec::EthSpec;
+use crate::slot_epoch_root::{EeIndex, Root, Shard, ShardSlot, Slot};
+use serde::{Deserialize, Serialize};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use ssz_types::VariableList;
+use tree_hash_derive::TreeHash as DeriveTreeHash;
+// TODO: Replace this with a spec value if trap messages ever become part of the spec
+// Currently this is arbitrarily set to 1KB max size
+use typenum::{U1024, U4, U4096};
+
+// TODO: Replace these with spec values if logs ever become part of the spec
+// Currently the topic limit matches EVM logs, and the other limits are arbitrary
+pub type MaxLogTopics = U4;
+pub type MaxLogDataSize = U4096;
+pub type MaxLogsPerTransaction = U1024;
+
+/// An event emitted by an EE through the `eth2_log` host function, eg. to record a change to its
+/// virtual state
+#[derive(
+    Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode, DeriveTreeHash,
+)]
+pub struct Log {
+    pub topics: VariableList<Root, MaxLogTopics>,
+    pub data: VariableList<u8, MaxLogDataSize>,
+}
+
+/// The outcome of executing a single `ShardTransaction`
+#[derive(
+    Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode, DeriveTreeHash,
+)]
+pub struct TransactionReceipt {
+    pub ee_index: EeIndex,
+    pub pre_state_root: Root,
+    // Equal to `pre_state_root` if the transaction failed
+    pub post_state_root: Root,
+    pub success: bool,
+    // Empty if the transaction succeeded
+    pub trap_message: VariableList<u8, U1024>,
+    // Equal to the transaction's gas limit if it ran out of gas
+    pub gas_used: u64,
+    // Logs emitted by the transaction, in the order they were emitted
+    // Empty if the transaction failed
+    pub logs: VariableList<Log, MaxLogsPerTransaction>,
+}
+
+/// The outcome of executing every transaction in a `ShardBlock`, in transaction order
+#[derive(
+    Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode, DeriveTreeHash,
+)]
+pub struct ShardBlockReceipt<T>
+where
+    T: EthSpec,
+{
+    pub shard: Shard,
+    pub shard_slot: ShardSlot,
+    pub shard_block_root: Root,
+    // The beacon chain slot at which the shard block was created
+    pub beacon_slot: Slot,
+    // The total gas used by every transaction in the block
+    pub gas_used: u64,
+    pub transaction_receipts: VariableList<TransactionReceipt, T::MaxTransactionsPerBlock>,
+}
@@ -3,8 +3,19 @@ use crate::slot_epoch_root::Root;
 use serde::{Deserialize, Serialize};
 use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
 use ssz_types::VariableList;
+use tree_hash_derive::TreeHash as DeriveTreeHash;
 
-#[derive(Default, Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode)]
+#[derive(
+    Default,
+    Debug,
+    PartialEq,
+    Clone,
+    Deserialize,
+    Serialize,
+    DeriveDecode,
+    DeriveEncode,
+    DeriveTreeHash,
+)]
 pub struct ShardState<T>
 where
     T: EthSpec,
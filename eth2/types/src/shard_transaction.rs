@@ -2,12 +2,17 @@ use crate::slot_epoch_root::EeIndex;
 use serde::{Deserialize, Serialize};
 use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
 use ssz_types::VariableList;
+use tree_hash_derive::TreeHash as DeriveTreeHash;
 // TODO: Replace this with the actual max # of bytes a ShardTransaction can include
 // Currently this is arbitrarily set to 256KB max size
 use typenum::U262144;
 
-#[derive(Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode)]
+#[derive(
+    Debug, PartialEq, Clone, Deserialize, Serialize, DeriveDecode, DeriveEncode, DeriveTreeHash,
+)]
 pub struct ShardTransaction {
     pub data: VariableList<u8, U262144>,
     pub ee_index: EeIndex,
+    // The most gas the transaction may use before it is stopped
+    pub gas_limit: u64,
 }
@@ -35,11 +35,16 @@ pub struct ShardSlot(u64);
 #[serde(transparent)]
 pub struct EeIndex(u64);
 
+#[derive(Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ValidatorIndex(u64);
+
 impl_common!(Slot);
 impl_common!(Epoch);
 impl_common!(Shard);
 impl_common!(ShardSlot);
 impl_common!(EeIndex);
+impl_common!(ValidatorIndex);
 
 construct_fixed_hash! {
     #[derive(Serialize, Deserialize)]
@@ -64,6 +69,12 @@ impl EeIndex {
     }
 }
 
+impl ValidatorIndex {
+    pub fn new(validator_index: u64) -> Self {
+        Self(validator_index)
+    }
+}
+
 impl Shard {
     pub fn new(shard: u64) -> Self {
         Self(shard)
@@ -134,3 +145,21 @@ impl Encode for Root {
         buf.extend_from_slice(self.as_bytes());
     }
 }
+
+impl tree_hash::TreeHash for Root {
+    fn tree_hash_type() -> tree_hash::TreeHashType {
+        tree_hash::TreeHashType::Vector
+    }
+
+    fn tree_hash_packed_encoding(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn tree_hash_packing_factor() -> usize {
+        1
+    }
+
+    fn tree_hash_root(&self) -> Vec<u8> {
+        tree_hash::merkle_root(self.as_bytes(), 0)
+    }
+}
@@ -1,5 +1,6 @@
-use crate::{Parse, Reqwest, Result};
-use reqwest::Client as HttpClient;
+use crate::{Error, Parse, Reqwest, Result};
+use reqwest::{Client as HttpClient, Response};
+use simulation_args::{async_trait, SimulationApi};
 use snafu::ResultExt;
 use url::Url;
 
@@ -18,6 +19,36 @@ impl SimulationClient {
         }
     }
 
+    pub async fn advance_slot(&self) -> Result<u64> {
+        let url = self.base_url.join("/advance-slot").context(Parse)?;
+
+        let response = self.http_client.post(url).send().await.context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<u64>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
+    pub async fn create_beacon_block(&self, a: simulation_args::CreateBeaconBlock) -> Result<u64> {
+        let url = self.base_url.join("/create-beacon-block").context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<u64>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
     pub async fn create_execution_environment(
         &self,
         a: simulation_args::CreateExecutionEnvironment,
@@ -27,35 +58,79 @@ impl SimulationClient {
             .join("/create-execution-environment")
             .context(Parse)?;
 
-        let res = self
+        let response = self
             .http_client
             .post(url)
             .json(&a)
             .send()
             .await
-            .context(Reqwest)?
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
             .json::<u64>()
             .await
             .context(Reqwest)?;
 
         Ok(res)
     }
-    pub async fn create_shard_block(&self, a: simulation_args::CreateShardBlock) -> Result<u64> {
+    pub async fn create_shard_block(
+        &self,
+        a: simulation_args::CreateShardBlock,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
         let url = self.base_url.join("/create-shard-block").context(Parse)?;
 
-        let res = self
+        let response = self
             .http_client
             .post(url)
             .json(&a)
             .send()
             .await
-            .context(Reqwest)?
-            .json::<u64>()
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::ShardBlockReceipt>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
+    pub async fn create_shard_blocks(
+        &self,
+        a: simulation_args::CreateShardBlocks,
+    ) -> Result<Vec<simulation_args::ShardBlockReceipt>> {
+        let url = self.base_url.join("/create-shard-blocks").context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<Vec<simulation_args::ShardBlockReceipt>>()
             .await
             .context(Reqwest)?;
 
         Ok(res)
     }
+    pub async fn get_beacon_state_root(&self) -> Result<[u8; 32]> {
+        let url = self
+            .base_url
+            .join("/get-beacon-state-root")
+            .context(Parse)?;
+
+        let response = self.http_client.post(url).send().await.context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::CustomSerializedReturnTypes>()
+            .await
+            .context(Reqwest)?;
+
+        let simulation_args::CustomSerializedReturnTypes::Base64EncodedRoot(root) = res;
+        Ok(root)
+    }
     pub async fn get_execution_environment(
         &self,
         a: simulation_args::GetExecutionEnvironment,
@@ -65,13 +140,15 @@ impl SimulationClient {
             .join("/get-execution-environment")
             .context(Parse)?;
 
-        let res = self
+        let response = self
             .http_client
             .post(url)
             .json(&a)
             .send()
             .await
-            .context(Reqwest)?
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
             .json::<simulation_args::ExecutionEnvironment>()
             .await
             .context(Reqwest)?;
@@ -87,13 +164,15 @@ impl SimulationClient {
             .join("/get-execution-environment-state")
             .context(Parse)?;
 
-        let res = self
+        let response = self
             .http_client
             .post(url)
             .json(&a)
             .send()
             .await
-            .context(Reqwest)?
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
             .json::<simulation_args::CustomSerializedReturnTypes>()
             .await
             .context(Reqwest)?;
@@ -101,42 +180,432 @@ impl SimulationClient {
         let simulation_args::CustomSerializedReturnTypes::Base64EncodedRoot(root) = res;
         Ok(root)
     }
+    pub async fn get_execution_environment_state_proof(
+        &self,
+        a: simulation_args::GetExecutionEnvironmentStateProof,
+    ) -> Result<simulation_args::ExecutionEnvironmentStateProof> {
+        let url = self
+            .base_url
+            .join("/get-execution-environment-state-proof")
+            .context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::ExecutionEnvironmentStateProof>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
+    pub async fn get_logs(
+        &self,
+        a: simulation_args::GetLogs,
+    ) -> Result<Vec<simulation_args::LogEntry>> {
+        let url = self.base_url.join("/get-logs").context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<Vec<simulation_args::LogEntry>>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
+    pub async fn get_module_cache_stats(&self) -> Result<simulation_args::ModuleCacheStats> {
+        let url = self
+            .base_url
+            .join("/get-module-cache-stats")
+            .context(Parse)?;
+
+        let response = self.http_client.post(url).send().await.context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::ModuleCacheStats>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
     pub async fn get_shard_block(
         &self,
         a: simulation_args::GetShardBlock,
     ) -> Result<simulation_args::ShardBlock> {
         let url = self.base_url.join("/get-shard-block").context(Parse)?;
 
-        let res = self
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::ShardBlock>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
+    pub async fn get_shard_block_by_root(
+        &self,
+        a: simulation_args::GetShardBlockByRoot,
+    ) -> Result<simulation_args::ShardBlock> {
+        let url = self
+            .base_url
+            .join("/get-shard-block-by-root")
+            .context(Parse)?;
+
+        let response = self
             .http_client
             .post(url)
             .json(&a)
             .send()
             .await
-            .context(Reqwest)?
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
             .json::<simulation_args::ShardBlock>()
             .await
             .context(Reqwest)?;
 
         Ok(res)
     }
+    pub async fn get_shard_block_root(
+        &self,
+        a: simulation_args::GetShardBlock,
+    ) -> Result<[u8; 32]> {
+        let url = self.base_url.join("/get-shard-block-root").context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::CustomSerializedReturnTypes>()
+            .await
+            .context(Reqwest)?;
+
+        let simulation_args::CustomSerializedReturnTypes::Base64EncodedRoot(root) = res;
+        Ok(root)
+    }
+    pub async fn get_shard_block_receipts(
+        &self,
+        a: simulation_args::GetShardBlockReceipts,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
+        let url = self
+            .base_url
+            .join("/get-shard-block-receipts")
+            .context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::ShardBlockReceipt>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
     pub async fn get_shard_state(
         &self,
         a: simulation_args::GetShardState,
     ) -> Result<simulation_args::ShardState> {
         let url = self.base_url.join("/get-shard-state").context(Parse)?;
 
-        let res = self
+        let response = self
             .http_client
             .post(url)
             .json(&a)
             .send()
             .await
-            .context(Reqwest)?
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
             .json::<simulation_args::ShardState>()
             .await
             .context(Reqwest)?;
 
         Ok(res)
     }
+    pub async fn get_shard_state_root(
+        &self,
+        a: simulation_args::GetShardState,
+    ) -> Result<[u8; 32]> {
+        let url = self.base_url.join("/get-shard-state-root").context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::CustomSerializedReturnTypes>()
+            .await
+            .context(Reqwest)?;
+
+        let simulation_args::CustomSerializedReturnTypes::Base64EncodedRoot(root) = res;
+        Ok(root)
+    }
+    pub async fn get_spec(&self) -> Result<simulation_args::Spec> {
+        let url = self.base_url.join("/get-spec").context(Parse)?;
+
+        let response = self.http_client.post(url).send().await.context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::Spec>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
+    pub async fn load_snapshot(&self, a: simulation_args::LoadSnapshot) -> Result<()> {
+        let url = self.base_url.join("/admin/load-snapshot").context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        check_status(response).await?;
+
+        Ok(())
+    }
+    pub async fn save_snapshot(&self, a: simulation_args::SaveSnapshot) -> Result<()> {
+        let url = self.base_url.join("/admin/save-snapshot").context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        check_status(response).await?;
+
+        Ok(())
+    }
+    pub async fn simulate_shard_block(
+        &self,
+        a: simulation_args::SimulateShardBlock,
+    ) -> Result<simulation_args::SimulatedShardBlock> {
+        let url = self.base_url.join("/simulate-shard-block").context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::SimulatedShardBlock>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
+    pub async fn simulate_transaction(
+        &self,
+        a: simulation_args::SimulateTransaction,
+    ) -> Result<simulation_args::TransactionReceipt> {
+        let url = self.base_url.join("/simulate-transaction").context(Parse)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .json(&a)
+            .send()
+            .await
+            .context(Reqwest)?;
+        let res = check_status(response)
+            .await?
+            .json::<simulation_args::TransactionReceipt>()
+            .await
+            .context(Reqwest)?;
+
+        Ok(res)
+    }
+}
+
+#[async_trait]
+impl SimulationApi for SimulationClient {
+    type Error = Error;
+
+    async fn advance_slot(&mut self) -> Result<u64> {
+        SimulationClient::advance_slot(self).await
+    }
+
+    async fn create_beacon_block(&mut self, a: simulation_args::CreateBeaconBlock) -> Result<u64> {
+        SimulationClient::create_beacon_block(self, a).await
+    }
+
+    async fn create_execution_environment(
+        &mut self,
+        a: simulation_args::CreateExecutionEnvironment,
+    ) -> Result<u64> {
+        SimulationClient::create_execution_environment(self, a).await
+    }
+
+    async fn create_shard_block(
+        &mut self,
+        a: simulation_args::CreateShardBlock,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
+        SimulationClient::create_shard_block(self, a).await
+    }
+
+    async fn create_shard_blocks(
+        &mut self,
+        a: simulation_args::CreateShardBlocks,
+    ) -> Result<Vec<simulation_args::ShardBlockReceipt>> {
+        SimulationClient::create_shard_blocks(self, a).await
+    }
+
+    async fn get_beacon_state_root(&mut self) -> Result<[u8; 32]> {
+        SimulationClient::get_beacon_state_root(self).await
+    }
+
+    async fn get_execution_environment(
+        &mut self,
+        a: simulation_args::GetExecutionEnvironment,
+    ) -> Result<simulation_args::ExecutionEnvironment> {
+        SimulationClient::get_execution_environment(self, a).await
+    }
+
+    async fn get_execution_environment_state(
+        &mut self,
+        a: simulation_args::GetExecutionEnvironmentState,
+    ) -> Result<[u8; 32]> {
+        SimulationClient::get_execution_environment_state(self, a).await
+    }
+
+    async fn get_execution_environment_state_proof(
+        &mut self,
+        a: simulation_args::GetExecutionEnvironmentStateProof,
+    ) -> Result<simulation_args::ExecutionEnvironmentStateProof> {
+        SimulationClient::get_execution_environment_state_proof(self, a).await
+    }
+
+    async fn get_logs(
+        &mut self,
+        a: simulation_args::GetLogs,
+    ) -> Result<Vec<simulation_args::LogEntry>> {
+        SimulationClient::get_logs(self, a).await
+    }
+
+    async fn get_module_cache_stats(&mut self) -> Result<simulation_args::ModuleCacheStats> {
+        SimulationClient::get_module_cache_stats(self).await
+    }
+
+    async fn get_shard_block(
+        &mut self,
+        a: simulation_args::GetShardBlock,
+    ) -> Result<simulation_args::ShardBlock> {
+        SimulationClient::get_shard_block(self, a).await
+    }
+
+    async fn get_shard_block_by_root(
+        &mut self,
+        a: simulation_args::GetShardBlockByRoot,
+    ) -> Result<simulation_args::ShardBlock> {
+        SimulationClient::get_shard_block_by_root(self, a).await
+    }
+
+    async fn get_shard_block_root(
+        &mut self,
+        a: simulation_args::GetShardBlock,
+    ) -> Result<[u8; 32]> {
+        SimulationClient::get_shard_block_root(self, a).await
+    }
+
+    async fn get_shard_block_receipts(
+        &mut self,
+        a: simulation_args::GetShardBlockReceipts,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
+        SimulationClient::get_shard_block_receipts(self, a).await
+    }
+
+    async fn get_shard_state(
+        &mut self,
+        a: simulation_args::GetShardState,
+    ) -> Result<simulation_args::ShardState> {
+        SimulationClient::get_shard_state(self, a).await
+    }
+
+    async fn get_shard_state_root(
+        &mut self,
+        a: simulation_args::GetShardState,
+    ) -> Result<[u8; 32]> {
+        SimulationClient::get_shard_state_root(self, a).await
+    }
+
+    async fn get_spec(&mut self) -> Result<simulation_args::Spec> {
+        SimulationClient::get_spec(self).await
+    }
+
+    async fn load_snapshot(&mut self, a: simulation_args::LoadSnapshot) -> Result<()> {
+        SimulationClient::load_snapshot(self, a).await
+    }
+
+    async fn save_snapshot(&mut self, a: simulation_args::SaveSnapshot) -> Result<()> {
+        SimulationClient::save_snapshot(self, a).await
+    }
+
+    async fn simulate_shard_block(
+        &mut self,
+        a: simulation_args::SimulateShardBlock,
+    ) -> Result<simulation_args::SimulatedShardBlock> {
+        SimulationClient::simulate_shard_block(self, a).await
+    }
+
+    async fn simulate_transaction(
+        &mut self,
+        a: simulation_args::SimulateTransaction,
+    ) -> Result<simulation_args::TransactionReceipt> {
+        SimulationClient::simulate_transaction(self, a).await
+    }
+}
+
+/// Turns an error response from the server into `Error::Server`, so that the server's message
+/// isn't lost trying to decode it as the expected return value
+async fn check_status(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let message = response.text().await.context(Reqwest)?;
+    Err(Error::Server {
+        status: status.as_u16(),
+        message,
+    })
 }
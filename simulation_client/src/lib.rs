@@ -20,6 +20,9 @@ pub enum Error {
         backtrace: Backtrace,
         source: ReqwestError,
     },
+
+    #[snafu(display("server returned error {}: {}", status, message))]
+    Server { status: u16, message: String },
     // #[snafu(display("error with HTTP request"))]
     // HTTP,
 }
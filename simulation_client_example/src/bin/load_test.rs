@@ -0,0 +1,91 @@
+/// Sends many concurrent requests to an already-running `simulation_server` instance and reports
+/// how long they took, so that changes to how the server handles requests can be measured.
+///
+/// Every request is a `get-beacon-state-root` query, which does almost no work in the simulation,
+/// so the latencies are dominated by the cost of getting a request into and out of the server.
+use simulation_client::{Result, SimulationClient};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use url::Url;
+
+#[derive(Debug, StructOpt)]
+struct Opt {
+    #[structopt(
+        short = "b",
+        long = "base_url",
+        default_value = "http://127.0.0.1:8999"
+    )]
+    /// IP address and port of the server to send requests to.
+    base_url: Url,
+
+    #[structopt(short = "n", long = "requests", default_value = "10000")]
+    /// Total number of requests to send.
+    requests: usize,
+
+    #[structopt(short = "c", long = "concurrency", default_value = "32")]
+    /// Number of requests to have in flight at once.
+    concurrency: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let Opt {
+        base_url,
+        requests,
+        concurrency,
+    } = Opt::from_args();
+    let simulation_client = Arc::new(SimulationClient::new(base_url));
+    // Requests are handed out one at a time, so that every worker stays busy until the end
+    let next_request = Arc::new(AtomicUsize::new(0));
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..concurrency)
+        .map(|_| {
+            let simulation_client = Arc::clone(&simulation_client);
+            let next_request = Arc::clone(&next_request);
+            tokio::spawn(async move {
+                let mut latencies = Vec::new();
+                while next_request.fetch_add(1, Ordering::Relaxed) < requests {
+                    let sent = Instant::now();
+                    simulation_client.get_beacon_state_root().await?;
+                    latencies.push(sent.elapsed());
+                }
+                Ok(latencies)
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(requests);
+    for worker in workers {
+        let worker_latencies: Result<Vec<Duration>> = worker.await.expect("worker panicked");
+        latencies.extend(worker_latencies?);
+    }
+    let elapsed = start.elapsed();
+    if latencies.is_empty() {
+        println!("no requests were sent");
+        return Ok(());
+    }
+
+    latencies.sort();
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    let total: Duration = latencies.iter().sum();
+    println!(
+        "{} requests, {} at a time, in {:?} ({:.0} requests/s)",
+        latencies.len(),
+        concurrency,
+        elapsed,
+        latencies.len() as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "latency: mean {:?}, p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+        total / latencies.len() as u32,
+        percentile(50),
+        percentile(90),
+        percentile(99),
+        percentile(100)
+    );
+
+    Ok(())
+}
@@ -5,9 +5,12 @@
 /// requires the `simulation_args` library in its Cargo.toml.
 /// Note: `simulation_client` works by sending HTTP requests to `simulation_server`, but has
 /// the exact same interface as if you had called methods on a `eth2::simulation::Simulation`
-/// instance directly.
+/// instance directly: both implement `simulation_args::SimulationApi`, so the scenario below
+/// could be run against a `Simulation` without changes.
 use hex::FromHex;
-use simulation_args::{ToBytes32};
+use simulation_args::{
+    verify_execution_environment_state_proof, MainnetEthSpec, SimulationApi, ToBytes32,
+};
 use simulation_client::{Result, SimulationClient};
 use structopt::StructOpt;
 use url::Url;
@@ -28,7 +31,7 @@ struct Opt {
 async fn main() -> Result<()> {
     // Initialize the client
     let opt = Opt::from_args();
-    let simulation_client = SimulationClient::new(opt.base_url);
+    let mut simulation_client = SimulationClient::new(opt.base_url);
 
     let initial_state = "22ea9b045f8792170b45ec629c98e1b92bc6a19cd8d0e9f37baaadf2564142f4";
     let initial_state = Vec::from_hex(initial_state).unwrap().to_bytes32().unwrap();
@@ -43,7 +46,7 @@ async fn main() -> Result<()> {
     let shard_index = 0;
 
     run_block_and_verify_state(
-        simulation_client,
+        &mut simulation_client,
         include_bytes!("../wasm_code/phase2_bazaar.wasm"),
         initial_state,
         data,
@@ -54,52 +57,73 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-// Note that this looks very similar to `test_block_with_single_transaction`, but uses
-// SimulationClient instead of Simulation
-async fn run_block_and_verify_state(
-    simulation_client: SimulationClient,
+// Note that this looks very similar to `test_block_with_single_transaction`, but works with
+// anything that implements SimulationApi, so it runs over HTTP or in-process alike
+async fn run_block_and_verify_state<A: SimulationApi>(
+    simulation: &mut A,
     wasm_code: &[u8],
     initial_state: [u8; 32],
     data: Vec<u8>,
     expected_post_state: [u8; 32],
     shard_index: u64,
-) -> Result<()> {
+) -> std::result::Result<(), A::Error> {
     // Create EE with the specified code and initial state
     let ee = simulation_args::ExecutionEnvironment {
         initial_state,
         wasm_code: wasm_code.to_vec(),
     };
-    let create_ee_args = simulation_args::CreateExecutionEnvironment { ee };
-    let ee_index = simulation_client
+    let create_ee_args = simulation_args::CreateExecutionEnvironment {
+        ee,
+        ..Default::default()
+    };
+    let ee_index = simulation
         .create_execution_environment(create_ee_args)
         .await?;
 
     println!("created new ee, it has index: {}", ee_index);
 
-    // Set up a shard transaction with the specified data
-    let shard_transaction = simulation_args::ShardTransaction { data, ee_index };
+    // Set up a shard transaction with the specified data, and enough gas to run it
+    let shard_transaction = simulation_args::ShardTransaction {
+        data,
+        ee_index,
+        gas_limit: 1_000_000,
+    };
 
     // Create a shard block with the one transaction in it
     let shard_block = simulation_args::ShardBlock {
         transactions: vec![shard_transaction],
+        ..Default::default()
     };
     let create_shard_block_args = simulation_args::CreateShardBlock {
         shard_index,
         shard_block,
     };
     // This creates the block and runs all the transactions inside it
-    let shard_block_index = simulation_client
+    let shard_block_receipt = simulation
         .create_shard_block(create_shard_block_args)
         .await?;
 
-    println!("create a new shard block, it has index: {}", shard_block_index);
+    println!(
+        "create a new shard block, it has index: {}",
+        shard_block_receipt.shard_slot_index
+    );
+    for transaction_receipt in shard_block_receipt.transaction_receipts.iter() {
+        println!(
+            "transaction on ee {} succeeded: {}, took {}ns and used {} gas",
+            transaction_receipt.ee_index,
+            transaction_receipt.success,
+            transaction_receipt.execution_time_nanos.unwrap_or_default(),
+            transaction_receipt.gas_used
+        );
+    }
 
     // Get back the EE state to make sure it matches the expected_post_state
     let get_ee_state_args = simulation_args::GetExecutionEnvironmentState {
         ee_index,
         shard_index,
+        shard_slot_index: None,
     };
-    let ee_post_state = simulation_client
+    let ee_post_state = simulation
         .get_execution_environment_state(get_ee_state_args)
         .await?;
     assert_eq!(
@@ -108,5 +132,21 @@ async fn run_block_and_verify_state(
     );
     println!("good news! the actual state of the ee after running the shard block transactions MATCHES the expected state");
 
+    // Check the EE state against the beacon state root, rather than trusting the server
+    let beacon_state_root = simulation.get_beacon_state_root().await?;
+    let get_proof_args = simulation_args::GetExecutionEnvironmentStateProof {
+        ee_index,
+        shard_index,
+    };
+    let proof = simulation
+        .get_execution_environment_state_proof(get_proof_args)
+        .await?;
+    assert_eq!(proof.ee_state_root, expected_post_state);
+    assert!(
+        verify_execution_environment_state_proof::<MainnetEthSpec>(&proof, &beacon_state_root),
+        "ee state proof should be valid against the beacon state root"
+    );
+    println!("the ee state was also proven against the beacon state root");
+
     Ok(())
 }
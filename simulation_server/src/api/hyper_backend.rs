@@ -0,0 +1,271 @@
+//! The API served with hyper, which builds on stable Rust.
+//!
+//! This mirrors the Rocket routes: every route takes a POST, arguments are read from a JSON body,
+//! and results are written as JSON. Bodies that aren't valid JSON are rejected with 400, and ones
+//! that don't have the expected shape with 422, as Rocket's `Json` guard does.
+use super::client_error_message;
+use crate::dispatch::{simulation_args, Handle, Result as DispatchResult};
+use hyper::body::HttpBody;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use simulation_args::CustomSerializedReturnTypes::Base64EncodedRoot;
+use snafu::{ResultExt, Snafu};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+
+/// Shorthand for result types returned from the API server.
+pub type Result<V, E = Error> = std::result::Result<V, E>;
+
+/// Errors arising from the API server.
+#[derive(Debug, Snafu)]
+pub enum Error {
+    Hyper { source: hyper::Error },
+}
+
+/// The largest request body that is read, the same as Rocket's default limit for JSON
+const BODY_LIMIT: usize = 1 << 20;
+
+/// Serve the API on `bind`
+pub async fn run(bind: SocketAddr, handle: Handle) -> Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let handle = handle.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| route(handle.clone(), request))) }
+    });
+
+    Server::try_bind(&bind)
+        .context(Hyper)?
+        .serve(make_service)
+        .await
+        .context(Hyper)
+}
+
+async fn route(handle: Handle, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::POST {
+        return Ok(status_response(StatusCode::NOT_FOUND));
+    }
+    let path = request.uri().path().to_string();
+    let body = request.into_body();
+
+    let response = match path.as_str() {
+        "/advance-slot" => call(handle, |mut h| async move { h.advance_slot().await }).await,
+        "/create-beacon-block" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.create_beacon_block(args).await
+            })
+            .await
+        }
+        "/create-execution-environment" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.create_execution_environment(args).await
+            })
+            .await
+        }
+        "/create-shard-block" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.create_shard_block(args).await
+            })
+            .await
+        }
+        "/create-shard-blocks" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.create_shard_blocks(args).await
+            })
+            .await
+        }
+        "/get-beacon-state-root" => {
+            call(handle, |mut h| async move {
+                h.get_beacon_state_root().await.map(Base64EncodedRoot)
+            })
+            .await
+        }
+        "/get-execution-environment" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.get_execution_environment(args).await
+            })
+            .await
+        }
+        "/get-execution-environment-state" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.get_execution_environment_state(args)
+                    .await
+                    .map(Base64EncodedRoot)
+            })
+            .await
+        }
+        "/get-execution-environment-state-proof" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.get_execution_environment_state_proof(args).await
+            })
+            .await
+        }
+        "/get-logs" => {
+            call_with_args(
+                handle,
+                body,
+                |mut h, args| async move { h.get_logs(args).await },
+            )
+            .await
+        }
+        "/get-module-cache-stats" => {
+            call(
+                handle,
+                |mut h| async move { h.get_module_cache_stats().await },
+            )
+            .await
+        }
+        "/get-shard-block" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.get_shard_block(args).await
+            })
+            .await
+        }
+        "/get-shard-block-by-root" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.get_shard_block_by_root(args).await
+            })
+            .await
+        }
+        "/get-shard-block-root" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.get_shard_block_root(args).await.map(Base64EncodedRoot)
+            })
+            .await
+        }
+        "/get-shard-block-receipts" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.get_shard_block_receipts(args).await
+            })
+            .await
+        }
+        "/get-shard-state" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.get_shard_state(args).await
+            })
+            .await
+        }
+        "/get-shard-state-root" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.get_shard_state_root(args).await.map(Base64EncodedRoot)
+            })
+            .await
+        }
+        "/get-spec" => call(handle, |mut h| async move { h.get_spec().await }).await,
+        "/simulate-shard-block" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.simulate_shard_block(args).await
+            })
+            .await
+        }
+        "/simulate-transaction" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.simulate_transaction(args).await
+            })
+            .await
+        }
+        // Admin routes act on the whole simulation, and read or write files on the server, so
+        // they are only served if there is a directory for those files
+        "/admin/load-snapshot" | "/admin/save-snapshot" if !handle.has_snapshot_dir() => {
+            status_response(StatusCode::NOT_FOUND)
+        }
+        "/admin/load-snapshot" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.load_snapshot(args).await
+            })
+            .await
+        }
+        "/admin/save-snapshot" => {
+            call_with_args(handle, body, |mut h, args| async move {
+                h.save_snapshot(args).await
+            })
+            .await
+        }
+        _ => status_response(StatusCode::NOT_FOUND),
+    };
+    Ok(response)
+}
+
+/// Respond to a route that takes no arguments
+async fn call<V, F, R>(handle: Handle, request: F) -> Response<Body>
+where
+    V: Serialize,
+    F: FnOnce(Handle) -> R,
+    R: Future<Output = DispatchResult<V>>,
+{
+    respond(request(handle).await)
+}
+
+/// Respond to a route that takes its arguments from the JSON body of the request
+async fn call_with_args<A, V, F, R>(handle: Handle, body: Body, request: F) -> Response<Body>
+where
+    A: DeserializeOwned,
+    V: Serialize,
+    F: FnOnce(Handle, A) -> R,
+    R: Future<Output = DispatchResult<V>>,
+{
+    match read_json(body).await {
+        Ok(args) => respond(request(handle, args).await),
+        Err(response) => response,
+    }
+}
+
+/// Read the arguments to a route from a request body, or the response rejecting the request if
+/// they can't be read
+async fn read_json<A: DeserializeOwned>(mut body: Body) -> Result<A, Response<Body>> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| status_response(StatusCode::BAD_REQUEST))?;
+        if bytes.len() + chunk.len() > BODY_LIMIT {
+            return Err(status_response(StatusCode::BAD_REQUEST));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    serde_json::from_slice(&bytes).map_err(|e| {
+        if e.is_data() {
+            status_response(StatusCode::UNPROCESSABLE_ENTITY)
+        } else {
+            status_response(StatusCode::BAD_REQUEST)
+        }
+    })
+}
+
+fn respond<V: Serialize>(res: DispatchResult<V>) -> Response<Body> {
+    match res {
+        Ok(value) => match serde_json::to_vec(&value) {
+            Ok(json) => content_response(StatusCode::OK, "application/json", json),
+            Err(_) => status_response(StatusCode::INTERNAL_SERVER_ERROR),
+        },
+        Err(error) => match client_error_message(&error) {
+            Some(message) => content_response(
+                StatusCode::BAD_REQUEST,
+                "text/plain; charset=utf-8",
+                message,
+            ),
+            None => status_response(StatusCode::INTERNAL_SERVER_ERROR),
+        },
+    }
+}
+
+fn content_response<B: Into<Body>>(
+    status: StatusCode,
+    content_type: &'static str,
+    body: B,
+) -> Response<Body> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+/// A response with no content other than its status
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
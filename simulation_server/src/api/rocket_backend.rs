@@ -0,0 +1,343 @@
+use super::client_error_message;
+use crate::dispatch::{simulation_args, Error as DispatchError, Handle, Result as DispatchResult};
+use rocket::config;
+use rocket::http::Status;
+use rocket::response::{self, status, Responder};
+use rocket::{post, routes, Request, State};
+use rocket_contrib::json::Json;
+use snafu::{ResultExt, Snafu};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use tokio::runtime;
+use tokio::task::{self, JoinError};
+
+/// Shorthand for result types returned from the API server.
+pub type Result<V, E = Error> = std::result::Result<V, E>;
+
+/// Errors arising from the API server.
+#[derive(Debug, Snafu)]
+pub enum Error {
+    Config { source: config::ConfigError },
+    Join { source: JoinError },
+}
+
+impl<'r> Responder<'r> for DispatchError {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        match client_error_message(&self) {
+            Some(message) => status::BadRequest(Some(message)).respond_to(request),
+            None => Err(Status::InternalServerError),
+        }
+    }
+}
+
+/// Runs requests on the runtime the simulation server was started in
+///
+/// Rocket handles each request on one of its own worker threads, which waits for the response
+/// here. Sharing the server's runtime means a request doesn't have to start a runtime of its own.
+#[derive(Debug)]
+struct Bridge {
+    handle: Handle,
+    runtime: runtime::Handle,
+}
+
+impl Bridge {
+    /// Spawn the future returned by `request` onto the runtime and wait for its result
+    fn call<V, F, R>(&self, request: F) -> DispatchResult<V>
+    where
+        V: Send + 'static,
+        F: FnOnce(Handle) -> R,
+        R: Future<Output = DispatchResult<V>> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let request = request(self.handle.clone());
+        self.runtime.spawn(async move {
+            // The request thread is always waiting, so the result can't fail to send
+            let _ = sender.send(request.await);
+        });
+        // The runtime only drops the request without running it when it's shutting down
+        receiver.recv().map_err(|_| DispatchError::Terminated)?
+    }
+}
+
+/// Serve the API on `bind`. Rocket handles requests on threads of its own, so it is launched from
+/// a blocking thread, and requests are bridged back to the runtime this is called from.
+pub async fn run(bind: SocketAddr, handle: Handle) -> Result<()> {
+    let runtime = runtime::Handle::current();
+    task::spawn_blocking(move || launch(bind, handle, runtime))
+        .await
+        .context(Join)?
+}
+
+fn launch(bind: SocketAddr, handle: Handle, runtime: runtime::Handle) -> Result<()> {
+    let config = config::Config::build(config::Environment::Development)
+        .address(format!("{}", bind.ip()))
+        .port(bind.port())
+        .finalize()
+        .context(Config)?;
+
+    let rocket = rocket::custom(config)
+        .mount(
+            "/",
+            routes![
+                advance_slot,
+                create_beacon_block,
+                create_execution_environment,
+                create_shard_block,
+                create_shard_blocks,
+                get_beacon_state_root,
+                get_execution_environment,
+                get_execution_environment_state,
+                get_execution_environment_state_proof,
+                get_logs,
+                get_module_cache_stats,
+                get_shard_block,
+                get_shard_block_by_root,
+                get_shard_block_root,
+                get_shard_block_receipts,
+                get_shard_state,
+                get_shard_state_root,
+                get_spec,
+                simulate_shard_block,
+                simulate_transaction,
+            ],
+        );
+    // Admin routes act on the whole simulation, and read or write files on the server, so they
+    // are only served if there is a directory for those files
+    let rocket = if handle.has_snapshot_dir() {
+        rocket.mount("/admin", routes![load_snapshot, save_snapshot])
+    } else {
+        rocket
+    };
+    rocket.manage(Bridge { handle, runtime }).launch();
+
+    Ok(())
+}
+
+#[post("/advance-slot")]
+fn advance_slot(bridge: State<Bridge>) -> DispatchResult<Json<u64>> {
+    let slot = bridge.call(|mut handle| async move { handle.advance_slot().await })?;
+    Ok(Json(slot))
+}
+
+#[post("/create-beacon-block", data = "<args>")]
+fn create_beacon_block(
+    args: Json<simulation_args::CreateBeaconBlock>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<u64>> {
+    let args = args.into_inner();
+    let slot = bridge.call(|mut handle| async move { handle.create_beacon_block(args).await })?;
+    Ok(Json(slot))
+}
+
+#[post("/create-execution-environment", data = "<args>")]
+fn create_execution_environment(
+    args: Json<simulation_args::CreateExecutionEnvironment>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<u64>> {
+    let args = args.into_inner();
+
+    let ee_index =
+        bridge.call(|mut handle| async move { handle.create_execution_environment(args).await })?;
+
+    Ok(Json(ee_index))
+}
+
+#[post("/create-shard-block", data = "<args>")]
+fn create_shard_block(
+    args: Json<simulation_args::CreateShardBlock>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::ShardBlockReceipt>> {
+    let args = args.into_inner();
+    let shard_block_receipt =
+        bridge.call(|mut handle| async move { handle.create_shard_block(args).await })?;
+    Ok(Json(shard_block_receipt))
+}
+
+#[post("/create-shard-blocks", data = "<args>")]
+fn create_shard_blocks(
+    args: Json<simulation_args::CreateShardBlocks>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<Vec<simulation_args::ShardBlockReceipt>>> {
+    let args = args.into_inner();
+    let shard_block_receipts =
+        bridge.call(|mut handle| async move { handle.create_shard_blocks(args).await })?;
+    Ok(Json(shard_block_receipts))
+}
+
+#[post("/get-beacon-state-root")]
+fn get_beacon_state_root(
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::CustomSerializedReturnTypes>> {
+    let root = bridge.call(|mut handle| async move { handle.get_beacon_state_root().await })?;
+    let encodeable_root = simulation_args::CustomSerializedReturnTypes::Base64EncodedRoot(root);
+    Ok(Json(encodeable_root))
+}
+
+#[post("/get-execution-environment", data = "<args>")]
+fn get_execution_environment(
+    args: Json<simulation_args::GetExecutionEnvironment>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::ExecutionEnvironment>> {
+    let args = args.into_inner();
+    let ee =
+        bridge.call(|mut handle| async move { handle.get_execution_environment(args).await })?;
+    Ok(Json(ee))
+}
+
+#[post("/get-execution-environment-state", data = "<args>")]
+fn get_execution_environment_state(
+    args: Json<simulation_args::GetExecutionEnvironmentState>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::CustomSerializedReturnTypes>> {
+    let args = args.into_inner();
+    let ee_state_root = bridge
+        .call(|mut handle| async move { handle.get_execution_environment_state(args).await })?;
+    let encodeable_ee_state_root =
+        simulation_args::CustomSerializedReturnTypes::Base64EncodedRoot(ee_state_root);
+    Ok(Json(encodeable_ee_state_root))
+}
+
+#[post("/get-execution-environment-state-proof", data = "<args>")]
+fn get_execution_environment_state_proof(
+    args: Json<simulation_args::GetExecutionEnvironmentStateProof>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::ExecutionEnvironmentStateProof>> {
+    let args = args.into_inner();
+    let proof = bridge.call(|mut handle| async move {
+        handle.get_execution_environment_state_proof(args).await
+    })?;
+    Ok(Json(proof))
+}
+
+#[post("/get-logs", data = "<args>")]
+fn get_logs(
+    args: Json<simulation_args::GetLogs>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<Vec<simulation_args::LogEntry>>> {
+    let args = args.into_inner();
+    let log_entries = bridge.call(|mut handle| async move { handle.get_logs(args).await })?;
+    Ok(Json(log_entries))
+}
+
+#[post("/get-module-cache-stats")]
+fn get_module_cache_stats(
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::ModuleCacheStats>> {
+    let stats = bridge.call(|mut handle| async move { handle.get_module_cache_stats().await })?;
+    Ok(Json(stats))
+}
+
+#[post("/get-shard-block", data = "<args>")]
+fn get_shard_block(
+    args: Json<simulation_args::GetShardBlock>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::ShardBlock>> {
+    let args = args.into_inner();
+    let shard_block =
+        bridge.call(|mut handle| async move { handle.get_shard_block(args).await })?;
+    Ok(Json(shard_block))
+}
+
+#[post("/get-shard-block-by-root", data = "<args>")]
+fn get_shard_block_by_root(
+    args: Json<simulation_args::GetShardBlockByRoot>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::ShardBlock>> {
+    let args = args.into_inner();
+    let shard_block =
+        bridge.call(|mut handle| async move { handle.get_shard_block_by_root(args).await })?;
+    Ok(Json(shard_block))
+}
+
+#[post("/get-shard-block-root", data = "<args>")]
+fn get_shard_block_root(
+    args: Json<simulation_args::GetShardBlock>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::CustomSerializedReturnTypes>> {
+    let args = args.into_inner();
+    let root = bridge.call(|mut handle| async move { handle.get_shard_block_root(args).await })?;
+    let encodeable_root = simulation_args::CustomSerializedReturnTypes::Base64EncodedRoot(root);
+    Ok(Json(encodeable_root))
+}
+
+#[post("/get-shard-block-receipts", data = "<args>")]
+fn get_shard_block_receipts(
+    args: Json<simulation_args::GetShardBlockReceipts>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::ShardBlockReceipt>> {
+    let args = args.into_inner();
+    let shard_block_receipt =
+        bridge.call(|mut handle| async move { handle.get_shard_block_receipts(args).await })?;
+    Ok(Json(shard_block_receipt))
+}
+
+#[post("/get-shard-state", data = "<args>")]
+fn get_shard_state(
+    args: Json<simulation_args::GetShardState>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::ShardState>> {
+    let args = args.into_inner();
+    let shard_state =
+        bridge.call(|mut handle| async move { handle.get_shard_state(args).await })?;
+    Ok(Json(shard_state))
+}
+
+#[post("/get-shard-state-root", data = "<args>")]
+fn get_shard_state_root(
+    args: Json<simulation_args::GetShardState>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::CustomSerializedReturnTypes>> {
+    let args = args.into_inner();
+    let root = bridge.call(|mut handle| async move { handle.get_shard_state_root(args).await })?;
+    let encodeable_root = simulation_args::CustomSerializedReturnTypes::Base64EncodedRoot(root);
+    Ok(Json(encodeable_root))
+}
+
+#[post("/get-spec")]
+fn get_spec(bridge: State<Bridge>) -> DispatchResult<Json<simulation_args::Spec>> {
+    let spec = bridge.call(|mut handle| async move { handle.get_spec().await })?;
+    Ok(Json(spec))
+}
+
+#[post("/load-snapshot", data = "<args>")]
+fn load_snapshot(
+    args: Json<simulation_args::LoadSnapshot>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<()>> {
+    let args = args.into_inner();
+    bridge.call(|mut handle| async move { handle.load_snapshot(args).await })?;
+    Ok(Json(()))
+}
+
+#[post("/save-snapshot", data = "<args>")]
+fn save_snapshot(
+    args: Json<simulation_args::SaveSnapshot>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<()>> {
+    let args = args.into_inner();
+    bridge.call(|mut handle| async move { handle.save_snapshot(args).await })?;
+    Ok(Json(()))
+}
+
+#[post("/simulate-shard-block", data = "<args>")]
+fn simulate_shard_block(
+    args: Json<simulation_args::SimulateShardBlock>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::SimulatedShardBlock>> {
+    let args = args.into_inner();
+    let simulated_shard_block =
+        bridge.call(|mut handle| async move { handle.simulate_shard_block(args).await })?;
+    Ok(Json(simulated_shard_block))
+}
+
+#[post("/simulate-transaction", data = "<args>")]
+fn simulate_transaction(
+    args: Json<simulation_args::SimulateTransaction>,
+    bridge: State<Bridge>,
+) -> DispatchResult<Json<simulation_args::TransactionReceipt>> {
+    let args = args.into_inner();
+    let transaction_receipt =
+        bridge.call(|mut handle| async move { handle.simulate_transaction(args).await })?;
+    Ok(Json(transaction_receipt))
+}
@@ -0,0 +1,47 @@
+use simulation::op_log;
+use simulation::{Result, Simulation};
+use std::path::PathBuf;
+use std::process;
+use structopt::StructOpt;
+use types::eth_spec::{EthSpec, EthSpecId, MainnetEthSpec, MinimalEthSpec};
+
+#[derive(StructOpt, Debug)]
+/// Re-execute an operation log from genesis, and report the first operation that leads to
+/// different post-state roots than the ones recorded in the log.
+///
+/// The log records the spec, chain config and genesis the server was started with, so it is
+/// replayed under the same ones.
+struct Opt {
+    #[structopt(parse(from_os_str))]
+    /// Operation log written by `simulation_server --op-log`.
+    op_log: PathBuf,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    let entries = op_log::read(&opt.op_log)?;
+    let header = match op_log::header(&entries) {
+        Some(header) => header.clone(),
+        None => {
+            println!("The operation log is empty");
+            return Ok(());
+        }
+    };
+    match header.spec {
+        EthSpecId::Mainnet => replay::<MainnetEthSpec>(&header, entries),
+        EthSpecId::Minimal => replay::<MinimalEthSpec>(&header, entries),
+    }
+}
+
+fn replay<T: EthSpec>(header: &op_log::Header, entries: Vec<op_log::Entry>) -> Result<()> {
+    let mut simulation: Simulation<T> = header.simulation()?;
+
+    match op_log::replay(&mut simulation, entries)? {
+        Some(divergence) => {
+            eprintln!("{}", divergence);
+            process::exit(1);
+        }
+        None => println!("Every recorded post-state root matches"),
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use simulation::{scout, ChainConfig, Result};
+use std::path::PathBuf;
+use std::process;
+use structopt::StructOpt;
+use types::eth_spec::{EthSpec, EthSpecId, MainnetEthSpec, MinimalEthSpec};
+
+#[derive(StructOpt, Debug)]
+/// Run scout-style YAML test vectors, each in a new simulation, and report the transactions that
+/// trapped and the EE states that don't match the expected post-states.
+struct Opt {
+    #[structopt(parse(from_os_str), required = true)]
+    /// YAML test vector files.
+    test_vectors: Vec<PathBuf>,
+
+    #[structopt(long = "spec", default_value = "mainnet")]
+    /// Spec preset to run the test vectors under: mainnet or minimal.
+    spec: EthSpecId,
+
+    #[structopt(long = "chain-config", parse(from_os_str))]
+    /// Chain config file with lower limits than the spec's. The spec's limits apply if not set.
+    chain_config: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    let passed = match opt.spec {
+        EthSpecId::Mainnet => run_test_vectors::<MainnetEthSpec>(&opt)?,
+        EthSpecId::Minimal => run_test_vectors::<MinimalEthSpec>(&opt)?,
+    };
+
+    if !passed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Run every test vector and report the outcome, return whether they all passed
+fn run_test_vectors<T: EthSpec>(opt: &Opt) -> Result<bool> {
+    let chain_config = match &opt.chain_config {
+        Some(path) => ChainConfig::read::<T, _>(path)?,
+        None => ChainConfig::of::<T>(),
+    };
+    let mut passed = true;
+
+    for path in &opt.test_vectors {
+        let failures = scout::run_with_chain_config::<T>(path, chain_config.clone())?;
+        if failures.is_empty() {
+            println!("{}: ok", path.display());
+        } else {
+            passed = false;
+            eprintln!("{}: failed", path.display());
+            for failure in failures {
+                eprintln!("  {}", failure);
+            }
+        }
+    }
+    Ok(passed)
+}
@@ -1,25 +1,76 @@
 use simulation_server::{Result, SimulationServerBuilder, SimulationServer};
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use structopt::StructOpt;
-use types::eth_spec::MainnetEthSpec;
+use types::eth_spec::{EthSpec, EthSpecId, MainnetEthSpec, MinimalEthSpec};
 
 #[derive(StructOpt, Debug)]
 struct Opt {
     #[structopt(short = "b", long = "bind", default_value = "127.0.0.1:8999")]
     /// IP address and port to listen on for API requests.
     bind: SocketAddr,
+
+    #[structopt(long = "spec", default_value = "mainnet")]
+    /// Spec preset that sets the limits of the simulation: mainnet or minimal.
+    spec: EthSpecId,
+
+    #[structopt(long = "chain-config", parse(from_os_str))]
+    /// Chain config file with lower limits than the spec's. The spec's limits apply if neither it
+    /// nor the genesis sets them.
+    chain_config: Option<PathBuf>,
+
+    #[structopt(long = "data-dir", parse(from_os_str))]
+    /// Directory to persist the simulation in. Everything is kept in memory if not set.
+    data_dir: Option<PathBuf>,
+
+    #[structopt(long = "genesis", parse(from_os_str))]
+    /// Genesis file to start the simulation from. Without it, the simulation starts empty.
+    genesis: Option<PathBuf>,
+
+    #[structopt(long = "op-log", parse(from_os_str), conflicts_with = "data-dir")]
+    /// File to record every operation that changes the simulation in. Operations already in the
+    /// file are replayed at startup.
+    op_log: Option<PathBuf>,
+
+    #[structopt(long = "snapshot-dir", parse(from_os_str))]
+    /// Directory that clients can save snapshots to and load them from. The admin routes are
+    /// disabled if not set.
+    snapshot_dir: Option<PathBuf>,
 }
 
-impl Into<SimulationServerBuilder<MainnetEthSpec>> for Opt {
-    fn into(self) -> SimulationServerBuilder<MainnetEthSpec> {
-        SimulationServer::builder().bind(self.bind)
+impl<T: EthSpec> Into<SimulationServerBuilder<T>> for Opt {
+    fn into(self) -> SimulationServerBuilder<T> {
+        let mut builder = SimulationServer::builder().bind(self.bind);
+        if let Some(chain_config) = self.chain_config {
+            builder = builder.chain_config(chain_config);
+        }
+        if let Some(data_dir) = self.data_dir {
+            builder = builder.data_dir(data_dir);
+        }
+        if let Some(genesis) = self.genesis {
+            builder = builder.genesis(genesis);
+        }
+        if let Some(op_log) = self.op_log {
+            builder = builder.op_log(op_log);
+        }
+        if let Some(snapshot_dir) = self.snapshot_dir {
+            builder = builder.snapshot_dir(snapshot_dir);
+        }
+        builder
     }
 }
 
-fn main() -> Result<()> {
-    let opt = Opt::from_args();
-    let builder: SimulationServerBuilder<MainnetEthSpec> = opt.into();
+fn run<T: EthSpec>(opt: Opt) -> Result<()> {
+    let builder: SimulationServerBuilder<T> = opt.into();
     let simulation_server = builder.build();
 
     simulation_server.run()
 }
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    match opt.spec {
+        EthSpecId::Mainnet => run::<MainnetEthSpec>(opt),
+        EthSpecId::Minimal => run::<MinimalEthSpec>(opt),
+    }
+}
@@ -1,8 +1,14 @@
 use eth2_types::eth_spec::EthSpec;
+use futures_util::future;
+use simulation::op_log::{self, OpLog};
 pub use simulation::{Error as SimulationError, Simulation};
 pub use simulation_args;
+use simulation_args::{async_trait, SimulationApi};
 use snafu::{OptionExt, ResultExt, Snafu};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::{self, JoinError};
 use types as eth2_types;
 
 /// Shorthand for result types returned from Dispatch.
@@ -20,17 +26,49 @@ pub enum Error {
     // Called "Sim" instead of "Simulation" to prevent name collision because Snafu auto-generates
     // context selectors with the same name as the enum values
     Sim { source: SimulationError },
+    /// Operation can't be recorded in the operation log, so it isn't allowed while there is one.
+    #[snafu(display("{} can't be recorded in the operation log", operation))]
+    NotLoggable { operation: &'static str },
+    /// An operation panicked while changing the simulation, so it may be left inconsistent.
+    Poisoned,
+    /// A task running part of an operation or query panicked.
+    Join { source: JoinError },
     /// Operation was cancelled because the simulation is shutting down.
     Terminated,
+    /// Snapshots can't be saved or loaded, since the server has no snapshot directory.
+    #[snafu(display("snapshots are disabled, since the server has no snapshot directory"))]
+    SnapshotsDisabled,
+    /// A snapshot was named with something other than a plain file name.
+    #[snafu(display("snapshot name {} must be a plain file name", name.display()))]
+    InvalidSnapshotName { name: PathBuf },
 }
 
+/// Operations that change the simulation. These are applied one at a time, in the order they
+/// arrive.
 #[derive(Debug)]
 enum Operation {
+    AdvanceSlot(Sender<Result<u64>>),
+    CreateBeaconBlock(simulation_args::CreateBeaconBlock, Sender<Result<u64>>),
     CreateExecutionEnvironment(
         simulation_args::CreateExecutionEnvironment,
         Sender<Result<u64>>,
     ),
-    CreateShardBlock(simulation_args::CreateShardBlock, Sender<Result<u64>>),
+    CreateShardBlock(
+        simulation_args::CreateShardBlock,
+        Sender<Result<simulation_args::ShardBlockReceipt>>,
+    ),
+    CreateShardBlocks(
+        simulation_args::CreateShardBlocks,
+        Sender<Result<Vec<simulation_args::ShardBlockReceipt>>>,
+    ),
+    LoadSnapshot(simulation_args::LoadSnapshot, Sender<Result<()>>),
+}
+
+/// Operations that only read the simulation. These are answered concurrently, with each one
+/// seeing the simulation as it was either before or after any operation, never part way through.
+#[derive(Debug)]
+enum Query {
+    GetBeaconStateRoot(Sender<Result<[u8; 32]>>),
     GetExecutionEnvironment(
         simulation_args::GetExecutionEnvironment,
         Sender<Result<simulation_args::ExecutionEnvironment>>,
@@ -39,14 +77,43 @@ enum Operation {
         simulation_args::GetExecutionEnvironmentState,
         Sender<Result<[u8; 32]>>,
     ),
+    GetExecutionEnvironmentStateProof(
+        simulation_args::GetExecutionEnvironmentStateProof,
+        Sender<Result<simulation_args::ExecutionEnvironmentStateProof>>,
+    ),
+    GetLogs(
+        simulation_args::GetLogs,
+        Sender<Result<Vec<simulation_args::LogEntry>>>,
+    ),
+    GetModuleCacheStats(Sender<Result<simulation_args::ModuleCacheStats>>),
     GetShardBlock(
         simulation_args::GetShardBlock,
         Sender<Result<simulation_args::ShardBlock>>,
     ),
+    GetShardBlockRoot(simulation_args::GetShardBlock, Sender<Result<[u8; 32]>>),
+    GetShardBlockByRoot(
+        simulation_args::GetShardBlockByRoot,
+        Sender<Result<simulation_args::ShardBlock>>,
+    ),
+    GetShardBlockReceipts(
+        simulation_args::GetShardBlockReceipts,
+        Sender<Result<simulation_args::ShardBlockReceipt>>,
+    ),
     GetShardState(
         simulation_args::GetShardState,
         Sender<Result<simulation_args::ShardState>>,
     ),
+    GetShardStateRoot(simulation_args::GetShardState, Sender<Result<[u8; 32]>>),
+    GetSpec(Sender<Result<simulation_args::Spec>>),
+    SaveSnapshot(simulation_args::SaveSnapshot, Sender<Result<()>>),
+    SimulateShardBlock(
+        simulation_args::SimulateShardBlock,
+        Sender<Result<simulation_args::SimulatedShardBlock>>,
+    ),
+    SimulateTransaction(
+        simulation_args::SimulateTransaction,
+        Sender<Result<simulation_args::TransactionReceipt>>,
+    ),
 }
 
 #[derive(Debug)]
@@ -54,70 +121,380 @@ pub struct Dispatch<T>
 where
     T: EthSpec,
 {
-    simulation: Simulation<T>,
+    // Queries hold the read lock while they run. Operations only hold the write lock while they
+    // change the simulation, so long running ones don't hold up queries while they execute.
+    simulation: Arc<RwLock<Simulation<T>>>,
+    // Operations that change the simulation are recorded here before they are applied
+    op_log: Option<OpLog>,
     receiver: Receiver<Operation>,
+    query_receiver: Receiver<Query>,
 }
 
 impl<T: EthSpec> Dispatch<T> {
-    pub fn new(simulation: Simulation<T>) -> (Self, Handle) {
+    pub fn new(
+        simulation: Simulation<T>,
+        op_log: Option<OpLog>,
+        snapshot_dir: Option<PathBuf>,
+    ) -> (Self, Handle) {
         let (sender, receiver) = channel(1);
-        let handle = Handle { sender };
+        let (query_sender, query_receiver) = channel(1);
+        let handle = Handle {
+            sender,
+            query_sender,
+            snapshot_dir: snapshot_dir.map(Arc::from),
+        };
 
         let me: Dispatch<T> = Dispatch {
-            simulation,
+            simulation: Arc::new(RwLock::new(simulation)),
+            op_log,
             receiver,
+            query_receiver,
         };
 
         (me, handle)
     }
 
-    pub async fn run(mut self) -> Result<()> {
+    pub async fn run(self) -> Result<()> {
         eprintln!("Simulation Running: {:?}", std::thread::current().id());
+        let Dispatch {
+            simulation,
+            op_log,
+            receiver,
+            query_receiver,
+        } = self;
+        let queries = answer_queries(Arc::clone(&simulation), query_receiver);
+        let operations = Operations {
+            simulation,
+            op_log: op_log.map(|op_log| Arc::new(Mutex::new(op_log))),
+            receiver,
+        };
+        future::try_join(queries, operations.run()).await.map(drop)
+    }
+}
+
+/// The part of `Dispatch` that applies operations
+struct Operations<T>
+where
+    T: EthSpec,
+{
+    simulation: Arc<RwLock<Simulation<T>>>,
+    // Shared with the blocking tasks that apply operations, which run one at a time
+    op_log: Option<Arc<Mutex<OpLog>>>,
+    receiver: Receiver<Operation>,
+}
+
+impl<T: EthSpec> Operations<T> {
+    async fn run(mut self) -> Result<()> {
+        // As with queries, a reply only fails to send if the caller stopped waiting for it, eg.
+        // because its client disconnected. The operation has been applied either way, and the
+        // ones after it still have to be
         while let Some(op) = self.receiver.recv().await {
             match op {
-                Operation::CreateExecutionEnvironment(args, mut reply) => {
+                Operation::AdvanceSlot(mut reply) => {
                     let res = self
-                        .simulation
-                        .create_execution_environment(args)
-                        .context(Sim);
-                    reply.send(res).await.map_err(|_| Error::Send)?;
+                        .apply(op_log::Operation::AdvanceSlot, |simulation| {
+                            simulation.advance_slot()
+                        })
+                        .await;
+                    let _ = reply.send(res).await;
                 }
-                Operation::CreateShardBlock(args, mut reply) => {
-                    let res = self.simulation.create_shard_block(args).context(Sim);
-                    reply.send(res).await.map_err(|_| Error::Send)?;
+                Operation::CreateBeaconBlock(args, mut reply) => {
+                    let operation = op_log::Operation::CreateBeaconBlock(args.clone());
+                    let res = self
+                        .apply(operation, |simulation| simulation.create_beacon_block(args))
+                        .await;
+                    let _ = reply.send(res).await;
                 }
-                Operation::GetExecutionEnvironment(args, mut reply) => {
-                    let res = self.simulation.get_execution_environment(args).context(Sim);
-                    reply.send(res).await.map_err(|_| Error::Send)?;
+                Operation::CreateExecutionEnvironment(args, mut reply) => {
+                    let operation = op_log::Operation::CreateExecutionEnvironment(args.clone());
+                    let res = self
+                        .apply(operation, |simulation| {
+                            simulation.create_execution_environment(args)
+                        })
+                        .await;
+                    let _ = reply.send(res).await;
                 }
-                Operation::GetExecutionEnvironmentState(args, mut reply) => {
+                Operation::CreateShardBlock(args, mut reply) => {
+                    let operation = op_log::Operation::CreateShardBlock(args.clone());
+                    let args = simulation_args::CreateShardBlocks {
+                        shard_blocks: vec![args],
+                    };
                     let res = self
-                        .simulation
-                        .get_execution_environment_state(args)
-                        .context(Sim);
-                    reply.send(res).await.map_err(|_| Error::Send)?;
+                        .create_shard_blocks(operation, args)
+                        .await
+                        .map(|mut shard_block_receipts| shard_block_receipts.remove(0));
+                    let _ = reply.send(res).await;
                 }
-                Operation::GetShardBlock(args, mut reply) => {
-                    let res = self.simulation.get_shard_block(args).context(Sim);
-                    reply.send(res).await.map_err(|_| Error::Send)?;
+                Operation::CreateShardBlocks(args, mut reply) => {
+                    let operation = op_log::Operation::CreateShardBlocks(args.clone());
+                    let res = self.create_shard_blocks(operation, args).await;
+                    let _ = reply.send(res).await;
                 }
-                Operation::GetShardState(args, mut reply) => {
-                    let res = self.simulation.get_shard_state(args).context(Sim);
-                    reply.send(res).await.map_err(|_| Error::Send)?;
+                Operation::LoadSnapshot(args, mut reply) => {
+                    // Replaying the log wouldn't reproduce the snapshot, which is outside of it
+                    let res = match self.op_log {
+                        Some(_) => NotLoggable {
+                            operation: "loading a snapshot",
+                        }
+                        .fail(),
+                        None => {
+                            write(Arc::clone(&self.simulation), |simulation| {
+                                simulation.load_snapshot(args.path)
+                            })
+                            .await
+                        }
+                    };
+                    let _ = reply.send(res).await;
                 }
             }
         }
 
         Ok(())
     }
+
+    /// Apply an operation that changes the simulation, recording it in the operation log first if
+    /// there is one.
+    async fn apply<V, F>(&self, operation: op_log::Operation, apply: F) -> Result<V>
+    where
+        V: std::marker::Send + 'static,
+        F: FnOnce(&mut Simulation<T>) -> simulation::Result<V> + std::marker::Send + 'static,
+    {
+        let op_log = self.op_log.clone();
+        write(
+            Arc::clone(&self.simulation),
+            move |simulation| match op_log {
+                Some(op_log) => lock(&op_log).record(simulation, operation, apply),
+                None => apply(simulation),
+            },
+        )
+        .await
+    }
+
+    /// Create shard blocks, recording the operation that creates them in the operation log if there
+    /// is one.
+    ///
+    /// The blocks are executed while holding the read lock, so queries are still answered while
+    /// they run, and only stored once the write lock is taken. Nothing else can change the
+    /// simulation in between, since operations are applied one at a time. Executing the blocks
+    /// doesn't change the simulation, so the operation is only recorded once they are stored, and
+    /// isn't left in the log without its outcome if executing them can't finish.
+    async fn create_shard_blocks(
+        &self,
+        operation: op_log::Operation,
+        args: simulation_args::CreateShardBlocks,
+    ) -> Result<Vec<simulation_args::ShardBlockReceipt>> {
+        let executed = read(Arc::clone(&self.simulation), |simulation| {
+            Ok(simulation.execute_shard_blocks(args))
+        })
+        .await?;
+        self.apply(operation, |simulation| {
+            executed.and_then(|executed| simulation.commit_shard_blocks(executed))
+        })
+        .await
+    }
+}
+
+/// Lock the operation log. It's only poisoned if applying an operation panicked, which leaves
+/// the simulation poisoned too, so nothing is applied with it afterwards anyway.
+fn lock(op_log: &Mutex<OpLog>) -> MutexGuard<'_, OpLog> {
+    op_log.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Answer each query in its own task, so a slow query doesn't hold up the ones after it
+async fn answer_queries<T: EthSpec>(
+    simulation: Arc<RwLock<Simulation<T>>>,
+    mut receiver: Receiver<Query>,
+) -> Result<()> {
+    while let Some(query) = receiver.recv().await {
+        tokio::spawn(answer_query(Arc::clone(&simulation), query));
+    }
+
+    Ok(())
+}
+
+async fn answer_query<T: EthSpec>(simulation: Arc<RwLock<Simulation<T>>>, query: Query) {
+    // A reply can only fail to send if nobody is waiting for it any more, in which case there's
+    // nothing left to do
+    match query {
+        Query::GetBeaconStateRoot(mut reply) => {
+            let res = read(simulation, |simulation| simulation.get_beacon_state_root()).await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetExecutionEnvironment(args, mut reply) => {
+            let res = read(simulation, |simulation| {
+                simulation.get_execution_environment(args)
+            })
+            .await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetExecutionEnvironmentState(args, mut reply) => {
+            let res = read(simulation, |simulation| {
+                simulation.get_execution_environment_state(args)
+            })
+            .await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetExecutionEnvironmentStateProof(args, mut reply) => {
+            let res = read(simulation, |simulation| {
+                simulation.get_execution_environment_state_proof(args)
+            })
+            .await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetLogs(args, mut reply) => {
+            let res = read(simulation, |simulation| simulation.get_logs(args)).await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetModuleCacheStats(mut reply) => {
+            let res = read(simulation, |simulation| {
+                Ok(simulation.get_module_cache_stats())
+            })
+            .await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetShardBlock(args, mut reply) => {
+            let res = read(simulation, |simulation| simulation.get_shard_block(args)).await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetShardBlockRoot(args, mut reply) => {
+            let res = read(simulation, |simulation| {
+                simulation.get_shard_block_root(args)
+            })
+            .await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetShardBlockByRoot(args, mut reply) => {
+            let res = read(simulation, |simulation| {
+                simulation.get_shard_block_by_root(args)
+            })
+            .await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetShardBlockReceipts(args, mut reply) => {
+            let res = read(simulation, |simulation| {
+                simulation.get_shard_block_receipts(args)
+            })
+            .await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetShardState(args, mut reply) => {
+            let res = read(simulation, |simulation| simulation.get_shard_state(args)).await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetShardStateRoot(args, mut reply) => {
+            let res = read(simulation, |simulation| {
+                simulation.get_shard_state_root(args)
+            })
+            .await;
+            let _ = reply.send(res).await;
+        }
+        Query::GetSpec(mut reply) => {
+            let res = read(simulation, |simulation| Ok(simulation.get_spec())).await;
+            let _ = reply.send(res).await;
+        }
+        Query::SaveSnapshot(args, mut reply) => {
+            let res = read(simulation, |simulation| simulation.save_snapshot(args.path)).await;
+            let _ = reply.send(res).await;
+        }
+        Query::SimulateShardBlock(args, mut reply) => {
+            let res = read(simulation, |simulation| {
+                simulation.simulate_shard_block(args)
+            })
+            .await;
+            let _ = reply.send(res).await;
+        }
+        Query::SimulateTransaction(args, mut reply) => {
+            let res = read(simulation, |simulation| {
+                simulation.simulate_transaction(args)
+            })
+            .await;
+            let _ = reply.send(res).await;
+        }
+    }
+}
+
+/// Run `query` on a blocking thread while holding the read lock, so that waiting for an operation
+/// to finish changing the simulation doesn't block the runtime
+async fn read<T, V, F>(simulation: Arc<RwLock<Simulation<T>>>, query: F) -> Result<V>
+where
+    T: EthSpec,
+    V: std::marker::Send + 'static,
+    F: FnOnce(&Simulation<T>) -> simulation::Result<V> + std::marker::Send + 'static,
+{
+    task::spawn_blocking(move || {
+        let simulation = simulation.read().map_err(|_| Error::Poisoned)?;
+        query(&simulation).context(Sim)
+    })
+    .await
+    .context(Join)?
+}
+
+/// Run `operation` on a blocking thread while holding the write lock, so that waiting for queries
+/// to finish reading the simulation doesn't block the runtime
+async fn write<T, V, F>(simulation: Arc<RwLock<Simulation<T>>>, operation: F) -> Result<V>
+where
+    T: EthSpec,
+    V: std::marker::Send + 'static,
+    F: FnOnce(&mut Simulation<T>) -> simulation::Result<V> + std::marker::Send + 'static,
+{
+    task::spawn_blocking(move || {
+        let mut simulation = simulation.write().map_err(|_| Error::Poisoned)?;
+        operation(&mut simulation).context(Sim)
+    })
+    .await
+    .context(Join)?
 }
 
 #[derive(Debug, Clone)]
 pub struct Handle {
     sender: Sender<Operation>,
+    query_sender: Sender<Query>,
+    // Snapshots are only saved and loaded in here, and not at all without it
+    snapshot_dir: Option<Arc<Path>>,
 }
 
 impl Handle {
+    /// Whether snapshots can be saved and loaded, which needs a snapshot directory
+    pub fn has_snapshot_dir(&self) -> bool {
+        self.snapshot_dir.is_some()
+    }
+
+    /// The path of the snapshot called `name` in the snapshot directory. Only plain file names are
+    /// accepted, so that clients can't read or write files anywhere else.
+    fn snapshot_path(&self, name: &Path) -> Result<PathBuf> {
+        let snapshot_dir = self.snapshot_dir.as_ref().context(SnapshotsDisabled)?;
+        let mut components = name.components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(file_name)), None) => Ok(snapshot_dir.join(file_name)),
+            _ => InvalidSnapshotName { name }.fail(),
+        }
+    }
+
+    pub async fn advance_slot(&mut self) -> Result<u64> {
+        let (sender, mut receiver) = channel(1);
+
+        self.sender
+            .send(Operation::AdvanceSlot(sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn create_beacon_block(
+        &mut self,
+        arg: simulation_args::CreateBeaconBlock,
+    ) -> Result<u64> {
+        let (sender, mut receiver) = channel(1);
+
+        self.sender
+            .send(Operation::CreateBeaconBlock(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
     pub async fn create_execution_environment(
         &mut self,
         arg: simulation_args::CreateExecutionEnvironment,
@@ -135,7 +512,7 @@ impl Handle {
     pub async fn create_shard_block(
         &mut self,
         arg: simulation_args::CreateShardBlock,
-    ) -> Result<u64> {
+    ) -> Result<simulation_args::ShardBlockReceipt> {
         let (sender, mut receiver) = channel(1);
 
         self.sender
@@ -146,14 +523,39 @@ impl Handle {
         receiver.recv().await.context(Terminated)?
     }
 
+    pub async fn create_shard_blocks(
+        &mut self,
+        arg: simulation_args::CreateShardBlocks,
+    ) -> Result<Vec<simulation_args::ShardBlockReceipt>> {
+        let (sender, mut receiver) = channel(1);
+
+        self.sender
+            .send(Operation::CreateShardBlocks(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn get_beacon_state_root(&mut self) -> Result<[u8; 32]> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::GetBeaconStateRoot(sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
     pub async fn get_execution_environment(
         &mut self,
         arg: simulation_args::GetExecutionEnvironment,
     ) -> Result<simulation_args::ExecutionEnvironment> {
         let (sender, mut receiver) = channel(1);
 
-        self.sender
-            .send(Operation::GetExecutionEnvironment(arg, sender))
+        self.query_sender
+            .send(Query::GetExecutionEnvironment(arg, sender))
             .await
             .map_err(|_| Error::Send)?;
 
@@ -166,8 +568,47 @@ impl Handle {
     ) -> Result<[u8; 32]> {
         let (sender, mut receiver) = channel(1);
 
-        self.sender
-            .send(Operation::GetExecutionEnvironmentState(arg, sender))
+        self.query_sender
+            .send(Query::GetExecutionEnvironmentState(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn get_execution_environment_state_proof(
+        &mut self,
+        arg: simulation_args::GetExecutionEnvironmentStateProof,
+    ) -> Result<simulation_args::ExecutionEnvironmentStateProof> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::GetExecutionEnvironmentStateProof(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn get_logs(
+        &mut self,
+        arg: simulation_args::GetLogs,
+    ) -> Result<Vec<simulation_args::LogEntry>> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::GetLogs(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn get_module_cache_stats(&mut self) -> Result<simulation_args::ModuleCacheStats> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::GetModuleCacheStats(sender))
             .await
             .map_err(|_| Error::Send)?;
 
@@ -180,8 +621,50 @@ impl Handle {
     ) -> Result<simulation_args::ShardBlock> {
         let (sender, mut receiver) = channel(1);
 
-        self.sender
-            .send(Operation::GetShardBlock(arg, sender))
+        self.query_sender
+            .send(Query::GetShardBlock(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn get_shard_block_root(
+        &mut self,
+        arg: simulation_args::GetShardBlock,
+    ) -> Result<[u8; 32]> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::GetShardBlockRoot(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn get_shard_block_by_root(
+        &mut self,
+        arg: simulation_args::GetShardBlockByRoot,
+    ) -> Result<simulation_args::ShardBlock> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::GetShardBlockByRoot(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn get_shard_block_receipts(
+        &mut self,
+        arg: simulation_args::GetShardBlockReceipts,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::GetShardBlockReceipts(arg, sender))
             .await
             .map_err(|_| Error::Send)?;
 
@@ -194,11 +677,233 @@ impl Handle {
     ) -> Result<simulation_args::ShardState> {
         let (sender, mut receiver) = channel(1);
 
+        self.query_sender
+            .send(Query::GetShardState(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn get_shard_state_root(
+        &mut self,
+        arg: simulation_args::GetShardState,
+    ) -> Result<[u8; 32]> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::GetShardStateRoot(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn get_spec(&mut self) -> Result<simulation_args::Spec> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::GetSpec(sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    /// Load the snapshot that `arg.path` names in the snapshot directory
+    pub async fn load_snapshot(&mut self, arg: simulation_args::LoadSnapshot) -> Result<()> {
+        let path = self.snapshot_path(&arg.path)?;
+        let arg = simulation_args::LoadSnapshot { path };
+        let (sender, mut receiver) = channel(1);
+
         self.sender
-            .send(Operation::GetShardState(arg, sender))
+            .send(Operation::LoadSnapshot(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    /// Save a snapshot under the name `arg.path` in the snapshot directory
+    pub async fn save_snapshot(&mut self, arg: simulation_args::SaveSnapshot) -> Result<()> {
+        let path = self.snapshot_path(&arg.path)?;
+        let arg = simulation_args::SaveSnapshot { path };
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::SaveSnapshot(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+
+    pub async fn simulate_shard_block(
+        &mut self,
+        arg: simulation_args::SimulateShardBlock,
+    ) -> Result<simulation_args::SimulatedShardBlock> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::SimulateShardBlock(arg, sender))
             .await
             .map_err(|_| Error::Send)?;
 
         receiver.recv().await.context(Terminated)?
     }
+
+    pub async fn simulate_transaction(
+        &mut self,
+        arg: simulation_args::SimulateTransaction,
+    ) -> Result<simulation_args::TransactionReceipt> {
+        let (sender, mut receiver) = channel(1);
+
+        self.query_sender
+            .send(Query::SimulateTransaction(arg, sender))
+            .await
+            .map_err(|_| Error::Send)?;
+
+        receiver.recv().await.context(Terminated)?
+    }
+}
+
+#[async_trait]
+impl SimulationApi for Handle {
+    type Error = Error;
+
+    async fn advance_slot(&mut self) -> Result<u64> {
+        Handle::advance_slot(self).await
+    }
+
+    async fn create_beacon_block(
+        &mut self,
+        arg: simulation_args::CreateBeaconBlock,
+    ) -> Result<u64> {
+        Handle::create_beacon_block(self, arg).await
+    }
+
+    async fn create_execution_environment(
+        &mut self,
+        arg: simulation_args::CreateExecutionEnvironment,
+    ) -> Result<u64> {
+        Handle::create_execution_environment(self, arg).await
+    }
+
+    async fn create_shard_block(
+        &mut self,
+        arg: simulation_args::CreateShardBlock,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
+        Handle::create_shard_block(self, arg).await
+    }
+
+    async fn create_shard_blocks(
+        &mut self,
+        arg: simulation_args::CreateShardBlocks,
+    ) -> Result<Vec<simulation_args::ShardBlockReceipt>> {
+        Handle::create_shard_blocks(self, arg).await
+    }
+
+    async fn get_beacon_state_root(&mut self) -> Result<[u8; 32]> {
+        Handle::get_beacon_state_root(self).await
+    }
+
+    async fn get_execution_environment(
+        &mut self,
+        arg: simulation_args::GetExecutionEnvironment,
+    ) -> Result<simulation_args::ExecutionEnvironment> {
+        Handle::get_execution_environment(self, arg).await
+    }
+
+    async fn get_execution_environment_state(
+        &mut self,
+        arg: simulation_args::GetExecutionEnvironmentState,
+    ) -> Result<[u8; 32]> {
+        Handle::get_execution_environment_state(self, arg).await
+    }
+
+    async fn get_execution_environment_state_proof(
+        &mut self,
+        arg: simulation_args::GetExecutionEnvironmentStateProof,
+    ) -> Result<simulation_args::ExecutionEnvironmentStateProof> {
+        Handle::get_execution_environment_state_proof(self, arg).await
+    }
+
+    async fn get_logs(
+        &mut self,
+        arg: simulation_args::GetLogs,
+    ) -> Result<Vec<simulation_args::LogEntry>> {
+        Handle::get_logs(self, arg).await
+    }
+
+    async fn get_module_cache_stats(&mut self) -> Result<simulation_args::ModuleCacheStats> {
+        Handle::get_module_cache_stats(self).await
+    }
+
+    async fn get_shard_block(
+        &mut self,
+        arg: simulation_args::GetShardBlock,
+    ) -> Result<simulation_args::ShardBlock> {
+        Handle::get_shard_block(self, arg).await
+    }
+
+    async fn get_shard_block_by_root(
+        &mut self,
+        arg: simulation_args::GetShardBlockByRoot,
+    ) -> Result<simulation_args::ShardBlock> {
+        Handle::get_shard_block_by_root(self, arg).await
+    }
+
+    async fn get_shard_block_root(
+        &mut self,
+        arg: simulation_args::GetShardBlock,
+    ) -> Result<[u8; 32]> {
+        Handle::get_shard_block_root(self, arg).await
+    }
+
+    async fn get_shard_block_receipts(
+        &mut self,
+        arg: simulation_args::GetShardBlockReceipts,
+    ) -> Result<simulation_args::ShardBlockReceipt> {
+        Handle::get_shard_block_receipts(self, arg).await
+    }
+
+    async fn get_shard_state(
+        &mut self,
+        arg: simulation_args::GetShardState,
+    ) -> Result<simulation_args::ShardState> {
+        Handle::get_shard_state(self, arg).await
+    }
+
+    async fn get_shard_state_root(
+        &mut self,
+        arg: simulation_args::GetShardState,
+    ) -> Result<[u8; 32]> {
+        Handle::get_shard_state_root(self, arg).await
+    }
+
+    async fn get_spec(&mut self) -> Result<simulation_args::Spec> {
+        Handle::get_spec(self).await
+    }
+
+    async fn load_snapshot(&mut self, arg: simulation_args::LoadSnapshot) -> Result<()> {
+        Handle::load_snapshot(self, arg).await
+    }
+
+    async fn save_snapshot(&mut self, arg: simulation_args::SaveSnapshot) -> Result<()> {
+        Handle::save_snapshot(self, arg).await
+    }
+
+    async fn simulate_shard_block(
+        &mut self,
+        arg: simulation_args::SimulateShardBlock,
+    ) -> Result<simulation_args::SimulatedShardBlock> {
+        Handle::simulate_shard_block(self, arg).await
+    }
+
+    async fn simulate_transaction(
+        &mut self,
+        arg: simulation_args::SimulateTransaction,
+    ) -> Result<simulation_args::TransactionReceipt> {
+        Handle::simulate_transaction(self, arg).await
+    }
 }
@@ -1,7 +1,10 @@
 //! `simulation_server` is a server that simulates Ethereum 2.0's second phase,
 //! with a particular focus on evaluating execution environments.
 
-#![feature(proc_macro_hygiene, decl_macro)]
+#![cfg_attr(
+    all(feature = "rocket-backend", not(feature = "hyper-backend")),
+    feature(proc_macro_hygiene, decl_macro)
+)]
 #![warn(missing_docs)]
 #![warn(missing_debug_implementations)]
 
@@ -10,10 +13,13 @@ mod dispatch;
 
 use futures_util::future::{self, FutureExt};
 use futures_util::pin_mut;
-use simulation::Simulation;
+use simulation::op_log::{self, Divergence, Header, OpLog};
+use simulation::{ChainConfig, DiskStore, Genesis, Simulation};
 use snafu::{Backtrace, ResultExt, Snafu};
+use std::fs;
 use std::marker::PhantomData;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use types::eth_spec::EthSpec;
 
 mod error {
@@ -29,12 +35,43 @@ mod error {
             source: api::Error,
         },
 
+        /// A chain config file was given along with a genesis that sets the chain config too.
+        ChainConfigTwice,
+
         /// Errors returned by the Dispatch logic
         Dispatch {
             /// The underlying error as returned by the dispatch mod
             source: dispatch::Error,
         },
 
+        /// The operation log can't be replayed onto a simulation that is persisted in a data
+        /// directory, since the directory already holds the operations' effects.
+        OpLogWithDataDir,
+
+        /// Replaying the operation log at startup led to different states than the ones recorded
+        /// in it.
+        #[snafu(display("unable to replay operation log: {}", divergence))]
+        OpLogDiverged {
+            /// The first operation whose recorded post-state roots differ.
+            divergence: Divergence,
+        },
+
+        /// The snapshot directory couldn't be created.
+        #[snafu(display("unable to create snapshot directory {}: {}", path.display(), source))]
+        SnapshotDir {
+            /// The snapshot directory.
+            path: PathBuf,
+
+            /// The underlying error as returned by the OS.
+            source: std::io::Error,
+        },
+
+        /// Errors returned while setting up the simulation, eg. opening its data directory.
+        Simulation {
+            /// The underlying error as returned by the simulation.
+            source: simulation::Error,
+        },
+
         /// Errors returned by tokio.
         Tokio {
             /// The underlying error as returned by tokio.
@@ -55,6 +92,11 @@ pub type Result<V, E = Error> = std::result::Result<V, E>;
 #[derive(Debug, Clone)]
 pub struct SimulationServerBuilder<T: EthSpec> {
     bind: SocketAddr,
+    chain_config: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+    genesis: Option<PathBuf>,
+    op_log: Option<PathBuf>,
+    snapshot_dir: Option<PathBuf>,
     // #PhantomDataExplanation
     // Required to be able to write SimulationServerBuilder<T: EthSpec> without actually
     // using the T value anywhere in the SimulationServerBuilder implementation, which is
@@ -68,6 +110,11 @@ impl<T: EthSpec> SimulationServerBuilder<T> {
     pub fn build(self) -> SimulationServer<T> {
         SimulationServer {
             bind: self.bind,
+            chain_config: self.chain_config,
+            data_dir: self.data_dir,
+            genesis: self.genesis,
+            op_log: self.op_log,
+            snapshot_dir: self.snapshot_dir,
             phantom: PhantomData,
         }
     }
@@ -79,12 +126,67 @@ impl<T: EthSpec> SimulationServerBuilder<T> {
         self.bind = bind;
         self
     }
+
+    /// Enforce the limits in a chain config file instead of the spec's.
+    ///
+    /// The limits can be lower than the spec's, but not higher. An operation log records them, but
+    /// a data directory doesn't, so the same file should be passed again when restarting from one.
+    /// Can't be combined with a genesis that sets a chain config.
+    pub fn chain_config(mut self, chain_config: PathBuf) -> Self {
+        self.chain_config = Some(chain_config);
+        self
+    }
+
+    /// Persist the simulation in a directory, so that it survives a restart.
+    ///
+    /// If the directory already holds a simulation, the server carries on from where it left off.
+    /// Without a data directory, everything is kept in memory.
+    pub fn data_dir(mut self, data_dir: PathBuf) -> Self {
+        self.data_dir = Some(data_dir);
+        self
+    }
+
+    /// Start the simulation from a genesis file instead of an empty simulation, under the chain
+    /// config it sets if any.
+    ///
+    /// With a data directory, the genesis is only used if the directory doesn't hold a simulation
+    /// yet, such as when the last attempt to start from a genesis failed. An operation log records
+    /// the genesis it was started from and replays on top of it, so a genesis given when
+    /// restarting from a log has to be the same one.
+    pub fn genesis(mut self, genesis: PathBuf) -> Self {
+        self.genesis = Some(genesis);
+        self
+    }
+
+    /// Record every operation that changes the simulation in a log file before applying it.
+    ///
+    /// If the file already holds operations, they are replayed from genesis when the server starts,
+    /// under the chain config and genesis recorded in the file. Can't be combined with a data
+    /// directory.
+    pub fn op_log(mut self, op_log: PathBuf) -> Self {
+        self.op_log = Some(op_log);
+        self
+    }
+
+    /// Let clients save snapshots to a directory and load them from it, through the admin routes.
+    ///
+    /// Snapshots are named with plain file names in the directory, which is created if it doesn't
+    /// exist. Without a snapshot directory, the admin routes aren't served.
+    pub fn snapshot_dir(mut self, snapshot_dir: PathBuf) -> Self {
+        self.snapshot_dir = Some(snapshot_dir);
+        self
+    }
 }
 
 impl<T: EthSpec> Default for SimulationServerBuilder<T> {
     fn default() -> Self {
         SimulationServerBuilder {
             bind: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080),
+            chain_config: None,
+            data_dir: None,
+            genesis: None,
+            op_log: None,
+            snapshot_dir: None,
             phantom: PhantomData,
         }
     }
@@ -94,6 +196,11 @@ impl<T: EthSpec> Default for SimulationServerBuilder<T> {
 #[derive(Debug)]
 pub struct SimulationServer<T: EthSpec> {
     bind: SocketAddr,
+    chain_config: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+    genesis: Option<PathBuf>,
+    op_log: Option<PathBuf>,
+    snapshot_dir: Option<PathBuf>,
     // See #PhantomDataExplanation
     phantom: PhantomData<T>,
 }
@@ -112,12 +219,76 @@ impl<T: EthSpec> SimulationServer<T> {
     #[tokio::main]
     async fn async_run(self) -> Result<()> {
         //        let simulation = ethereum::Simulation::new();
-        let simulation: Simulation<T> = Simulation::new();
-        let (dispatch, handle) = dispatch::Dispatch::new(simulation);
+        if self.data_dir.is_some() && self.op_log.is_some() {
+            return error::OpLogWithDataDir.fail();
+        }
+        let chain_config = match &self.chain_config {
+            Some(path) => Some(ChainConfig::read::<T, _>(path).context(error::Simulation)?),
+            None => None,
+        };
+        let genesis = match &self.genesis {
+            Some(path) => Some(Genesis::read::<T, _>(path).context(error::Simulation)?),
+            None => None,
+        };
+        let chain_config = match genesis.as_ref().and_then(|genesis| genesis.chain_config.clone()) {
+            Some(_) if chain_config.is_some() => return error::ChainConfigTwice.fail(),
+            Some(genesis_chain_config) => Some(genesis_chain_config),
+            None => chain_config,
+        };
+        let (simulation, op_log): (Simulation<T>, _) = match &self.op_log {
+            Some(path) => {
+                let (mut op_log, entries) = OpLog::open(path).context(error::Simulation)?;
+                // The log records what the simulation started from, so it's used instead of the
+                // chain config and genesis, which only have to match it if they're given
+                let header = match op_log::header(&entries) {
+                    Some(header) => {
+                        header
+                            .check::<T>(chain_config.as_ref(), genesis.as_ref())
+                            .context(error::Simulation)?;
+                        header.clone()
+                    }
+                    None => {
+                        let chain_config = chain_config.unwrap_or_else(ChainConfig::of::<T>);
+                        let header = Header::new::<T>(chain_config, genesis);
+                        op_log
+                            .append_header(header.clone())
+                            .context(error::Simulation)?;
+                        header
+                    }
+                };
+                let mut simulation = header.simulation().context(error::Simulation)?;
+                let divergence =
+                    op_log::replay(&mut simulation, entries).context(error::Simulation)?;
+                if let Some(divergence) = divergence {
+                    return error::OpLogDiverged { divergence }.fail();
+                }
+                (simulation, Some(op_log))
+            }
+            None => {
+                let simulation = match &self.data_dir {
+                    Some(data_dir) => DiskStore::open(data_dir).and_then(Simulation::with_store),
+                    None => Ok(Simulation::new()),
+                };
+                let chain_config = chain_config.unwrap_or_else(ChainConfig::of::<T>);
+                let simulation = simulation
+                    .and_then(|simulation| simulation.with_chain_config(chain_config))
+                    .and_then(|simulation| match genesis {
+                        // A data directory that already holds a simulation carries on from it
+                        // instead of genesis
+                        Some(genesis) if simulation.is_empty() => simulation.with_genesis(genesis),
+                        _ => Ok(simulation),
+                    })
+                    .context(error::Simulation)?;
+                (simulation, None)
+            }
+        };
+        if let Some(path) = &self.snapshot_dir {
+            fs::create_dir_all(path).context(error::SnapshotDir { path })?;
+        }
+        let (dispatch, handle) = dispatch::Dispatch::new(simulation, op_log, self.snapshot_dir);
 
         let eth_run = tokio::spawn(dispatch.run().map(|x| x.context(error::Dispatch)));
-        let api_run =
-            tokio::task::spawn_blocking(move || api::run(&self, handle).context(error::Api));
+        let api_run = tokio::spawn(api::run(self.bind, handle).map(|x| x.context(error::Api)));
 
         pin_mut!(eth_run);
         pin_mut!(api_run);
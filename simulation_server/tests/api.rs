@@ -0,0 +1,546 @@
+//! Tests of the HTTP API, run against a server listening on a local port.
+//!
+//! These only use HTTP, so they run against whichever API backend the server is built with:
+//! `cargo +nightly test -p simulation_server` tests the Rocket backend, and
+//! `cargo test -p simulation_server --no-default-features --features hyper-backend` the hyper one.
+#[path = "../../eth2/simulation/tests/support/bazaar.rs"]
+mod bazaar;
+
+use bazaar::{BAZAAR_DATA, BAZAAR_INITIAL_STATE, BAZAAR_POST_STATE, BAZAAR_WASM};
+use hex::FromHex;
+use simulation::Simulation;
+use simulation_args::{
+    verify_execution_environment_state_proof, EthSpec, MainnetEthSpec, MinimalEthSpec,
+    SimulationApi,
+};
+use simulation_client::{Error, SimulationClient};
+use simulation_server::{SimulationServer, SimulationServerBuilder};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+/// Start a server with a new simulation on a free local port, and return the URL it can be
+/// reached at once it is answering requests
+async fn start_server() -> Url {
+    start_server_with(SimulationServer::<MainnetEthSpec>::builder()).await
+}
+
+async fn start_server_with<T: EthSpec>(builder: SimulationServerBuilder<T>) -> Url {
+    let bind = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    thread::spawn(move || {
+        builder.bind(bind).build().run().unwrap();
+    });
+
+    let base_url = Url::parse(&format!("http://{}", bind)).unwrap();
+    let client = SimulationClient::new(base_url.clone());
+    for _ in 0..200 {
+        if client.get_beacon_state_root().await.is_ok() {
+            return base_url;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    panic!("server didn't start answering requests");
+}
+
+fn root(hex: &str) -> [u8; 32] {
+    let mut root = [0; 32];
+    root.copy_from_slice(&Vec::from_hex(hex).unwrap());
+    root
+}
+
+fn bazaar_transaction(ee_index: u64) -> simulation_args::ShardTransaction {
+    simulation_args::ShardTransaction {
+        data: Vec::from_hex(BAZAAR_DATA).unwrap(),
+        ee_index,
+        gas_limit: 1_000_000,
+    }
+}
+
+fn bazaar_block(shard_index: u64, ee_index: u64) -> simulation_args::CreateShardBlock {
+    simulation_args::CreateShardBlock {
+        shard_index,
+        shard_block: simulation_args::ShardBlock {
+            transactions: vec![bazaar_transaction(ee_index)],
+            ..Default::default()
+        },
+    }
+}
+
+async fn create_bazaar_ee(client: &SimulationClient) -> u64 {
+    let ee = simulation_args::ExecutionEnvironment {
+        initial_state: root(BAZAAR_INITIAL_STATE),
+        wasm_code: BAZAAR_WASM.to_vec(),
+    };
+    client
+        .create_execution_environment(simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+}
+
+/// Create the bazaar EE and run a block with its transaction, returning the beacon state root
+/// afterwards. It only uses `SimulationApi`, so the same scenario runs in-process and over HTTP.
+async fn bazaar_scenario<A: SimulationApi>(simulation: &mut A) -> [u8; 32] {
+    let ee = simulation_args::ExecutionEnvironment {
+        initial_state: root(BAZAAR_INITIAL_STATE),
+        wasm_code: BAZAAR_WASM.to_vec(),
+    };
+    let ee_index = simulation
+        .create_execution_environment(simulation_args::CreateExecutionEnvironment {
+            ee,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    simulation
+        .create_shard_block(bazaar_block(0, ee_index))
+        .await
+        .unwrap();
+    let ee_state = simulation
+        .get_execution_environment_state(simulation_args::GetExecutionEnvironmentState {
+            ee_index,
+            shard_index: 0,
+            shard_slot_index: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(ee_state, root(BAZAAR_POST_STATE));
+
+    simulation.get_beacon_state_root().await.unwrap()
+}
+
+#[tokio::test]
+async fn scenarios_run_the_same_in_process_and_over_http() {
+    let mut simulation = Simulation::<MainnetEthSpec>::new();
+    let mut client = SimulationClient::new(start_server().await);
+
+    let in_process = bazaar_scenario(&mut simulation).await;
+    let over_http = bazaar_scenario(&mut client).await;
+    assert_eq!(in_process, over_http);
+}
+
+#[tokio::test]
+async fn shard_blocks_are_executed_and_can_be_queried() {
+    let client = SimulationClient::new(start_server().await);
+    let ee_index = create_bazaar_ee(&client).await;
+    assert_eq!(ee_index, 0);
+
+    let mut receipt = client
+        .create_shard_block(bazaar_block(3, ee_index))
+        .await
+        .unwrap();
+    assert_eq!(receipt.shard_index, 3);
+    assert_eq!(receipt.shard_slot_index, 0);
+    assert!(receipt.transaction_receipts[0].success);
+    assert!(receipt.transaction_receipts[0]
+        .execution_time_nanos
+        .is_some());
+    assert_eq!(
+        receipt.transaction_receipts[0].post_state_root,
+        root(BAZAAR_POST_STATE)
+    );
+
+    let ee = client
+        .get_execution_environment(simulation_args::GetExecutionEnvironment { ee_index })
+        .await
+        .unwrap();
+    assert_eq!(ee.wasm_code, BAZAAR_WASM);
+    let ee_state = client
+        .get_execution_environment_state(simulation_args::GetExecutionEnvironmentState {
+            ee_index,
+            shard_index: 3,
+            shard_slot_index: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(ee_state, root(BAZAAR_POST_STATE));
+
+    let get_shard_block = || simulation_args::GetShardBlock {
+        shard_index: 3,
+        shard_slot_index: 0,
+    };
+    let shard_block = client.get_shard_block(get_shard_block()).await.unwrap();
+    assert_eq!(shard_block.transactions, vec![bazaar_transaction(ee_index)]);
+    let shard_block_root = client
+        .get_shard_block_root(get_shard_block())
+        .await
+        .unwrap();
+    assert_eq!(shard_block_root, receipt.shard_block_root);
+    let shard_block_by_root = client
+        .get_shard_block_by_root(simulation_args::GetShardBlockByRoot { shard_block_root })
+        .await
+        .unwrap();
+    assert_eq!(shard_block_by_root.shard, 3);
+    assert_eq!(shard_block_by_root.transactions, shard_block.transactions);
+    let stored_receipt = client
+        .get_shard_block_receipts(simulation_args::GetShardBlockReceipts {
+            shard_index: 3,
+            shard_slot_index: 0,
+        })
+        .await
+        .unwrap();
+    // Timings aren't stored, so only the receipt returned when the block was run has them
+    assert_eq!(
+        stored_receipt.transaction_receipts[0].execution_time_nanos,
+        None
+    );
+    receipt.transaction_receipts[0].execution_time_nanos = None;
+    assert_eq!(stored_receipt, receipt);
+
+    let get_shard_state = || simulation_args::GetShardState {
+        shard_index: 3,
+        shard_slot_index: None,
+    };
+    let shard_state = client.get_shard_state(get_shard_state()).await.unwrap();
+    assert_eq!(
+        shard_state.execution_environment_states,
+        vec![root(BAZAAR_POST_STATE)]
+    );
+    let shard_state_root = client
+        .get_shard_state_root(get_shard_state())
+        .await
+        .unwrap();
+    assert_ne!(shard_state_root, [0; 32]);
+
+    let beacon_state_root = client.get_beacon_state_root().await.unwrap();
+    let proof = client
+        .get_execution_environment_state_proof(simulation_args::GetExecutionEnvironmentStateProof {
+            ee_index,
+            shard_index: 3,
+        })
+        .await
+        .unwrap();
+    assert!(verify_execution_environment_state_proof::<MainnetEthSpec>(
+        &proof,
+        &beacon_state_root
+    ));
+
+    let logs = client
+        .get_logs(simulation_args::GetLogs::default())
+        .await
+        .unwrap();
+    assert!(logs.is_empty());
+    let stats = client.get_module_cache_stats().await.unwrap();
+    assert_eq!(stats.modules, 1);
+}
+
+#[tokio::test]
+async fn server_starts_from_genesis() {
+    let genesis = Path::new(env!("CARGO_MANIFEST_DIR")).join("genesis.yaml");
+    let client = SimulationClient::new(
+        start_server_with(SimulationServer::<MainnetEthSpec>::builder().genesis(genesis)).await,
+    );
+
+    let receipt = client.create_shard_block(bazaar_block(0, 0)).await.unwrap();
+    assert_eq!(
+        receipt.transaction_receipts[0].post_state_root,
+        root(BAZAAR_POST_STATE)
+    );
+    let ee = client
+        .get_execution_environment(simulation_args::GetExecutionEnvironment { ee_index: 1 })
+        .await
+        .unwrap();
+    assert_eq!(
+        ee.wasm_code,
+        include_bytes!("../../eth2/simulation/tests/phase2_helloworld.wasm").to_vec()
+    );
+}
+
+#[tokio::test]
+async fn server_reports_its_spec() {
+    let client = SimulationClient::new(start_server().await);
+    let spec = client.get_spec().await.unwrap();
+    assert_eq!(spec, simulation_args::Spec::of::<MainnetEthSpec>());
+    assert_eq!(spec.name, simulation_args::EthSpecId::Mainnet);
+
+    let client = SimulationClient::new(
+        start_server_with(SimulationServer::<MinimalEthSpec>::builder()).await,
+    );
+    let spec = client.get_spec().await.unwrap();
+    assert_eq!(spec.name, simulation_args::EthSpecId::Minimal);
+    assert_eq!(spec.max_shards, 4);
+
+    // The minimal spec's limits are enforced
+    let ee_index = create_bazaar_ee(&client).await;
+    let receipt = client
+        .create_shard_block(bazaar_block(spec.max_shards - 1, ee_index))
+        .await
+        .unwrap();
+    assert_eq!(
+        receipt.transaction_receipts[0].post_state_root,
+        root(BAZAAR_POST_STATE)
+    );
+    match client
+        .create_shard_block(bazaar_block(spec.max_shards, ee_index))
+        .await
+    {
+        Err(Error::Server { status: 400, .. }) => (),
+        other => panic!("expected an error from the server, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn server_enforces_its_chain_config() {
+    let path = std::env::temp_dir().join(format!(
+        "simulation-server-api-test-{}.yaml",
+        std::process::id()
+    ));
+    std::fs::write(&path, "max_shards: 2\nmax_transactions_per_block: 1\n").unwrap();
+    let client = SimulationClient::new(
+        start_server_with(SimulationServer::<MainnetEthSpec>::builder().chain_config(path.clone()))
+            .await,
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    let spec = client.get_spec().await.unwrap();
+    assert_eq!(spec.max_shards, 2);
+    assert_eq!(spec.max_transactions_per_block, 1);
+    assert_eq!(
+        spec.max_gas_per_shard_block,
+        simulation_args::Spec::of::<MainnetEthSpec>().max_gas_per_shard_block
+    );
+
+    let ee_index = create_bazaar_ee(&client).await;
+    client
+        .create_shard_block(bazaar_block(1, ee_index))
+        .await
+        .unwrap();
+    match client.create_shard_block(bazaar_block(2, ee_index)).await {
+        Err(Error::Server { status: 400, .. }) => (),
+        other => panic!("expected an error from the server, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn beacon_chain_advances() {
+    let client = SimulationClient::new(start_server().await);
+
+    assert_eq!(client.advance_slot().await.unwrap(), 1);
+    let beacon_block = simulation_args::BeaconBlock { slot: 3 };
+    let slot = client
+        .create_beacon_block(simulation_args::CreateBeaconBlock { beacon_block })
+        .await
+        .unwrap();
+    assert_eq!(slot, 3);
+}
+
+#[tokio::test]
+async fn batches_and_simulations() {
+    let client = SimulationClient::new(start_server().await);
+    let ee_index = create_bazaar_ee(&client).await;
+    let get_shard_state = || simulation_args::GetShardState {
+        shard_index: 0,
+        shard_slot_index: None,
+    };
+    let initial_shard_state_root = client
+        .get_shard_state_root(get_shard_state())
+        .await
+        .unwrap();
+
+    let transaction_receipt = client
+        .simulate_transaction(simulation_args::SimulateTransaction {
+            shard_index: 0,
+            transaction: bazaar_transaction(ee_index),
+        })
+        .await
+        .unwrap();
+    assert_eq!(transaction_receipt.post_state_root, root(BAZAAR_POST_STATE));
+    let simulated = client
+        .simulate_shard_block(simulation_args::SimulateShardBlock {
+            shard_index: 0,
+            shard_block: bazaar_block(0, ee_index).shard_block,
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        simulated.execution_environment_states,
+        vec![root(BAZAAR_POST_STATE)]
+    );
+    let shard_state_root = client
+        .get_shard_state_root(get_shard_state())
+        .await
+        .unwrap();
+    assert_eq!(shard_state_root, initial_shard_state_root);
+
+    let receipts = client
+        .create_shard_blocks(simulation_args::CreateShardBlocks {
+            shard_blocks: vec![bazaar_block(0, ee_index), bazaar_block(1, ee_index)],
+        })
+        .await
+        .unwrap();
+    assert_eq!(receipts.len(), 2);
+    assert_eq!(receipts[1].shard_index, 1);
+}
+
+#[tokio::test]
+async fn operations_continue_after_a_client_gives_up() {
+    let base_url = start_server().await;
+    let client = SimulationClient::new(base_url.clone());
+    let ee_index = create_bazaar_ee(&client).await;
+
+    // A block that takes a while to run, so the request below times out while it's being applied
+    let transaction = simulation_args::ShardTransaction {
+        gas_limit: 90_000,
+        ..bazaar_transaction(ee_index)
+    };
+    let create_shard_block_args = simulation_args::CreateShardBlock {
+        shard_index: 0,
+        shard_block: simulation_args::ShardBlock {
+            transactions: vec![transaction; 256],
+            ..Default::default()
+        },
+    };
+    let impatient_client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let res = impatient_client
+        .post(base_url.join("/create-shard-block").unwrap())
+        .body(serde_json::to_string(&create_shard_block_args).unwrap())
+        .send()
+        .await;
+    assert!(res.unwrap_err().is_timeout());
+
+    // The abandoned block is still applied, and so are the operations after it
+    assert_eq!(client.advance_slot().await.unwrap(), 1);
+    let shard_block = client
+        .get_shard_block(simulation_args::GetShardBlock {
+            shard_index: 0,
+            shard_slot_index: 0,
+        })
+        .await
+        .unwrap();
+    assert_eq!(shard_block.transactions.len(), 256);
+}
+
+#[tokio::test]
+async fn snapshots_can_be_saved_and_loaded() {
+    let snapshot_dir = std::env::temp_dir().join(format!(
+        "simulation-server-api-test-snapshots-{}",
+        std::process::id()
+    ));
+    let client = SimulationClient::new(
+        start_server_with(
+            SimulationServer::<MainnetEthSpec>::builder().snapshot_dir(snapshot_dir.clone()),
+        )
+        .await,
+    );
+    let ee_index = create_bazaar_ee(&client).await;
+    client
+        .save_snapshot(simulation_args::SaveSnapshot {
+            path: "snapshot.ssz".into(),
+        })
+        .await
+        .unwrap();
+    assert!(snapshot_dir.join("snapshot.ssz").is_file());
+
+    client
+        .create_shard_block(bazaar_block(0, ee_index))
+        .await
+        .unwrap();
+    client
+        .load_snapshot(simulation_args::LoadSnapshot {
+            path: "snapshot.ssz".into(),
+        })
+        .await
+        .unwrap();
+
+    let ee_state = client
+        .get_execution_environment_state(simulation_args::GetExecutionEnvironmentState {
+            ee_index,
+            shard_index: 0,
+            shard_slot_index: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(ee_state, root(BAZAAR_INITIAL_STATE));
+
+    // Snapshots can't be read or written outside of the snapshot directory
+    let outside = snapshot_dir.with_extension("ssz");
+    for path in &[
+        PathBuf::from("../snapshot.ssz"),
+        PathBuf::from("nested/snapshot.ssz"),
+        PathBuf::from("."),
+        outside.clone(),
+    ] {
+        match client
+            .save_snapshot(simulation_args::SaveSnapshot { path: path.clone() })
+            .await
+        {
+            Err(Error::Server { status: 400, .. }) => {}
+            other => panic!("expected {:?} to be rejected, got {:?}", path, other),
+        }
+        match client
+            .load_snapshot(simulation_args::LoadSnapshot { path: path.clone() })
+            .await
+        {
+            Err(Error::Server { status: 400, .. }) => {}
+            other => panic!("expected {:?} to be rejected, got {:?}", path, other),
+        }
+    }
+    assert!(!outside.exists());
+    std::fs::remove_dir_all(&snapshot_dir).unwrap();
+
+    // Without a snapshot directory, the admin routes aren't served at all
+    let client = SimulationClient::new(start_server().await);
+    match client
+        .save_snapshot(simulation_args::SaveSnapshot {
+            path: "snapshot.ssz".into(),
+        })
+        .await
+    {
+        Err(Error::Server { status: 404, .. }) => {}
+        other => panic!("expected the admin routes to be disabled, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn invalid_requests_are_rejected() {
+    let base_url = start_server().await;
+    let client = SimulationClient::new(base_url.clone());
+
+    match client
+        .get_execution_environment(simulation_args::GetExecutionEnvironment { ee_index: 5 })
+        .await
+    {
+        Err(Error::Server { status, message }) => {
+            assert_eq!(status, 400);
+            assert!(!message.is_empty());
+        }
+        other => panic!("expected an error from the server, got {:?}", other),
+    }
+
+    let http_client = reqwest::Client::new();
+    let status = |method: reqwest::Method, path: &str, body: &'static str| {
+        let request = http_client
+            .request(method, base_url.join(path).unwrap())
+            .body(body)
+            .send();
+        async move { request.await.unwrap().status().as_u16() }
+    };
+    assert_eq!(
+        status(reqwest::Method::POST, "/no-such-route", "").await,
+        404
+    );
+    assert_eq!(status(reqwest::Method::GET, "/advance-slot", "").await, 404);
+    assert_eq!(
+        status(reqwest::Method::POST, "/get-shard-block", "{").await,
+        400
+    );
+    assert_eq!(
+        status(
+            reqwest::Method::POST,
+            "/get-shard-block",
+            r#"{"shard_index": "zero"}"#
+        )
+        .await,
+        422
+    );
+}